@@ -9,6 +9,8 @@
 // This representation makes it easy to analyze and transform the code before
 // generating the target output.
 
+use crate::errors::{ErrorPosition, format_error};
+
 /// Represents a statement in the Vortlang language.
 /// 
 /// Statements are top-level constructs that perform actions or declare variables.
@@ -28,34 +30,269 @@ pub enum Statement {
     /// A numerical variable declaration and assignment.
     NumDeclaration(String, NumExpression, usize),
 
+    /// A constant declaration: name, value, and the line and column of the name.
+    /// The value is a number, integer or string expression that the analysis
+    /// pass computes and replaces with a literal.
+    ConstDeclaration(String, TypedExpression, usize, usize),
+
     /// Reassignment of an existing string variable.
     VariableAssignment(String, Expression, #[allow(dead_code)] usize),
 
     /// Reassignment of an existing numeric variable.
     NumAssignment(String, NumExpression, #[allow(dead_code)] usize),
+
+    /// A boolean variable declaration and assignment.
+    BoolDeclaration(String, Condition, usize),
+
+    /// Reassignment of an existing boolean variable.
+    BoolAssignment(String, Condition, #[allow(dead_code)] usize),
+
+    /// An integer variable declaration and assignment.
+    IntDeclaration(String, IntExpression, usize),
+
+    /// Reassignment of an existing integer variable.
+    IntAssignment(String, IntExpression, #[allow(dead_code)] usize),
+
+    /// A list variable declaration: name, element type, initial value and line number.
+    ListDeclaration(String, Type, ListExpression, usize),
+
+    /// Reassignment of an existing list variable.
+    ListAssignment(String, ListExpression, #[allow(dead_code)] usize),
+
+    /// Assignment to an element of a list variable, `list[index] = value`, with
+    /// the source line number for reporting an index out of bounds.
+    ListElementAssignment(String, IntExpression, TypedExpression, usize),
+
+    /// Appends a value to the end of a list variable: `push(list, value)`.
+    ListPush(String, TypedExpression),
+
+    /// Removes the last element of a list variable and discards it: `pop(list)`.
+    /// Carries the line number for reporting an empty list.
+    ListPop(String, usize),
+
+    /// A map variable declaration: name, value type, initial value and line number.
+    MapDeclaration(String, Type, MapExpression, usize),
+
+    /// Reassignment of an existing map variable.
+    MapAssignment(String, MapExpression, #[allow(dead_code)] usize),
+
+    /// Stores a value under a key of a map variable, `map[key] = value`,
+    /// replacing any value already stored under that key.
+    MapInsert(String, Expression, TypedExpression),
+
+    /// Removes a key and its value from a map variable: `remove(map, key)`.
+    /// Carries the line number for reporting a missing key.
+    MapRemove(String, Expression, usize),
+
+    /// Replaces the contents of a file with a string, creating the file if
+    /// needed: `write_file(path, text)`. Carries the line number for reporting
+    /// failures.
+    WriteFile(Expression, Expression, usize),
+
+    /// Adds a string to the end of a file, creating the file if needed:
+    /// `append_file(path, text)`. Carries the line number for reporting failures.
+    AppendFile(Expression, Expression, usize),
+
+    /// Deletes a file: `delete_file(path)`. Carries the line number for
+    /// reporting failures.
+    DeleteFile(Expression, usize),
+
+    /// Definition of a struct type with a name and typed fields, in declaration order.
+    StructDefinition(String, Vec<(String, Type)>),
+
+    /// A struct variable declaration: name, struct type name, initial value and line number.
+    StructDeclaration(String, String, StructExpression, usize),
+
+    /// Reassignment of an existing struct variable.
+    StructAssignment(String, StructExpression, #[allow(dead_code)] usize),
+
+    /// Assignment to a field of a struct variable: `point.x = value`.
+    FieldAssignment(FieldAccess, TypedExpression),
+
+    /// Definition of an enum type with a name and its variants, in declaration
+    /// order. Each variant lists the types of the values it carries, if any.
+    EnumDefinition(String, Vec<(String, Vec<Type>)>),
+
+    /// An enum variable declaration: name, enum type name, initial value and line number.
+    EnumDeclaration(String, String, EnumExpression, usize),
+
+    /// Reassignment of an existing enum variable.
+    EnumAssignment(String, EnumExpression, #[allow(dead_code)] usize),
+
+    /// A match statement: the value to match, the arms in source order, and the
+    /// line and column of the `match` keyword for reporting incomplete matches.
+    Match(TypedExpression, Vec<MatchArm>, usize, usize),
     
-    /// Definition of a regular function with a name and a body of Vortlang statements.
-    FunctionDefinition(String, Vec<Statement>),
+    /// Definition of a regular function with a name, typed parameters, an optional
+    /// return type and a body of Vortlang statements.
+    FunctionDefinition(String, Vec<(String, Type)>, Option<Type>, Vec<Statement>),
     
     /// A standalone call to a function (works for both regular and C functions).
-    FunctionCall(String),
+    /// Any returned value is discarded.
+    FunctionCall(String, Vec<TypedExpression>),
+
+    /// Leaves the current function, returning a value if the function declares a return type.
+    /// At the top level, ends the program with an integer exit status.
+    Return(Option<TypedExpression>),
+
+    /// Ends the program immediately with an exit status, `exit(code)`, from
+    /// anywhere including functions. Carries the line number for warnings about
+    /// the statements after it.
+    Exit(IntExpression, usize),
 
     /// Definition of a function containing raw C code, identified by `$c` in the syntax.
     /// The first String is the function name, the second String is the raw C code.
     CFunctionDefinition(String, String), // Added to support C code embedding
+
+    /// A conditional statement. Each branch pairs a condition with the body that
+    /// runs when it holds (the `if` followed by any `else if`s, in source order),
+    /// and the optional final body is the `else` branch.
+    If(Vec<(Condition, Vec<Statement>)>, Option<Vec<Statement>>),
+
+    /// A loop that runs its body for as long as the condition holds.
+    While(Condition, Vec<Statement>),
+
+    /// A counted loop over a numeric range: loop variable, start, exclusive end,
+    /// step, body and the source line number. The bounds and step are evaluated once.
+    For(String, NumExpression, NumExpression, NumExpression, Vec<Statement>, usize),
+
+    /// A counted loop over an integer range, with an 'int' loop variable; laid
+    /// out like `For`.
+    IntFor(String, IntExpression, IntExpression, IntExpression, Vec<Statement>, usize),
+
+    /// Leaves the innermost enclosing loop.
+    Break,
+
+    /// Skips to the next iteration of the innermost enclosing loop.
+    Continue,
+}
+
+/// Represents the type of a variable, as implied by the keyword used to declare it.
+#[derive(Clone, PartialEq)]
+pub enum Type {
+    /// A string variable, declared with `let`.
+    Str,
+
+    /// A numerical variable, declared with `num`.
+    Num,
+
+    /// A boolean variable, declared with `bool`.
+    Bool,
+
+    /// A 64-bit integer variable, declared with `int`.
+    Int,
+
+    /// A growable list with elements of the given type, declared with `list`.
+    List(Box<Type>),
+
+    /// A map from string keys to values of the given type, declared with `map`.
+    Map(Box<Type>),
+
+    /// A value of the struct type with the given name, declared with the name
+    /// of the struct.
+    Struct(String),
+
+    /// A value of the enum type with the given name, declared with the name
+    /// of the enum.
+    Enum(String),
+}
+
+/// Represents an expression whose type has been resolved by the parser.
+///
+/// Used where a value of any type can appear, such as function arguments
+/// and return values.
+#[derive(Clone)]
+pub enum TypedExpression {
+    /// A string-valued expression.
+    Str(Expression),
+
+    /// A numerical expression.
+    Num(NumExpression),
+
+    /// A boolean expression.
+    Bool(Condition),
+
+    /// An integer expression.
+    Int(IntExpression),
+
+    /// A list expression.
+    List(ListExpression),
+
+    /// A map expression.
+    Map(MapExpression),
+
+    /// A struct expression, with the name of its struct.
+    Struct(String, StructExpression),
+
+    /// An enum expression, with the name of its enum.
+    Enum(String, EnumExpression),
 }
 
 /// Represents a part of a formatted print statement.
 /// 
 /// Used in PrintFormat to represent either a literal string or an expression
-/// (variable reference or function call) that appears within the format string.
+/// that appears within the braces of the format string.
 #[derive(Clone)]
 pub enum FormatPart {
     /// A literal string portion of the format string.
     Literal(String),
     
-    /// An expression (variable or function call) to be evaluated or executed.
-    Expression(Expression),
+    /// An expression of any type whose value is printed with the given format,
+    /// or a call to a function without a return value, which is executed for
+    /// its output.
+    Expression(TypedExpression, FormatSpec),
+}
+
+/// Formatting options for an interpolated value, written after a ':' inside
+/// the braces, such as `{x:.2}`, `{name:>10}` or `{n:08}`.
+///
+/// Without an explicit alignment, strings and booleans are left-aligned and
+/// numbers are right-aligned within the width.
+#[derive(Clone, Default)]
+pub struct FormatSpec {
+    /// Alignment within the width: `<`, `>` or `^`.
+    pub align: Option<Alignment>,
+
+    /// Whether numbers are padded with leading zeros instead of spaces (`0`).
+    pub zero_pad: bool,
+
+    /// Minimum number of characters to print.
+    pub width: Option<usize>,
+
+    /// Digits after the decimal point for numbers, or maximum length for strings.
+    pub precision: Option<usize>,
+
+    /// How numbers are written; numbers with a precision default to fixed notation.
+    pub style: Option<NumberStyle>,
+}
+
+/// Represents the alignment of a formatted value within its width.
+#[derive(Clone, Copy)]
+pub enum Alignment {
+    /// Left alignment (<)
+    Left,
+
+    /// Right alignment (>)
+    Right,
+
+    /// Centered (^)
+    Center,
+}
+
+/// Represents the notation used to format a number.
+#[derive(Clone, Copy)]
+pub enum NumberStyle {
+    /// Fixed-point notation for 'num' values (f)
+    Fixed,
+
+    /// Scientific notation for 'num' values (e)
+    Scientific,
+
+    /// Shortest of fixed and scientific notation for 'num' values (g)
+    General,
+
+    /// Hexadecimal notation for 'int' values (x)
+    Hex,
 }
 
 /// Represents an expression in the Vortlang language.
@@ -70,8 +307,53 @@ pub enum Expression {
     /// A reference to a previously defined variable.
     Variable(String),
     
-    /// A call to a function, used within format strings.
-    FunctionCall(String),
+    /// A call to a function. Inside format strings, a function without a return
+    /// type is executed for its side effects; otherwise the call yields a string.
+    FunctionCall(String, Vec<TypedExpression>),
+
+    /// A concatenation of values joined with `+`, producing a new string. The
+    /// first operand is always a string; numbers, integers and booleans among
+    /// the others are converted to text.
+    Concat(Vec<TypedExpression>),
+
+    /// An element of a list of strings.
+    ListElement(ListElement),
+
+    /// A value of a map of strings.
+    MapValue(MapValue),
+
+    /// A string field of a struct.
+    Field(FieldAccess),
+
+    /// A format string used as a value, such as `o"Hello {name}"`, producing a
+    /// new string. Unlike in `print`, every interpolated expression has a value.
+    Format(Vec<FormatPart>),
+
+    /// A line read from standard input with `input("prompt")`, without its line
+    /// terminator. The line number is used to report the end of the input.
+    Input(Option<Box<Expression>>, usize),
+
+    /// A value of any type converted to text with `tostr(x)`, as `print` would
+    /// show it.
+    ToStr(Box<TypedExpression>),
+
+    /// A call to a built-in string function returning a string, such as
+    /// `upper(s)` or `substr(s, 0, 3)`, with the line number for reporting
+    /// invalid arguments.
+    StringCall(StringFunction, Vec<TypedExpression>, usize),
+
+    /// The whole contents of a file: `read_file(path)`. Carries the line number
+    /// for reporting failures.
+    ReadFile(Box<Expression>, usize),
+
+    /// A command-line argument of the program: `arg(i)`, where `arg(0)` is the
+    /// program itself. Carries the line number for reporting an index out of bounds.
+    Arg(Box<IntExpression>, usize),
+
+    /// The value of an environment variable, `env(name)`, or `env(name, default)`
+    /// to fall back on a default value when the variable isn't set. Carries the
+    /// line number for reporting a missing variable without a default.
+    Env(Box<Expression>, Option<Box<Expression>>, usize),
 }
 
 /// Represents a numerical expression in the Vortlang language.
@@ -91,6 +373,333 @@ pub enum NumExpression {
     
     /// A parenthesized numerical expression for precedence control.
     Grouping(Box<NumExpression>),
+
+    /// A negated numerical expression (unary minus).
+    Negate(Box<NumExpression>),
+
+    /// A call to a function that returns a number.
+    FunctionCall(String, Vec<TypedExpression>),
+
+    /// A call to a built-in math function, such as `sqrt(x)` or `max(a, b)`.
+    MathCall(MathFunction, Vec<NumExpression>),
+
+    /// An explicit conversion of an integer to a number: `num(i)`.
+    FromInt(Box<IntExpression>),
+
+    /// An element of a list of numbers.
+    ListElement(ListElement),
+
+    /// A value of a map of numbers.
+    MapValue(MapValue),
+
+    /// A numerical field of a struct.
+    Field(FieldAccess),
+
+    /// A number read from standard input with `inputnum("prompt")`. Invalid
+    /// numbers are asked for again; the line number is used to report the end
+    /// of the input.
+    InputNum(Option<Box<Expression>>, usize),
+
+    /// A string parsed as a number with `tonum(s)`. Text that isn't a number is
+    /// a runtime error reported with the line number.
+    ToNum(Box<Expression>, usize),
+}
+
+/// Represents an integer expression in the Vortlang language.
+///
+/// Integer expressions use 64-bit arithmetic: division truncates towards zero
+/// and `%` takes the sign of the dividend. Operations that can fail at runtime
+/// (overflow, division by zero) carry the source line number for error reporting.
+#[derive(Clone)]
+pub enum IntExpression {
+    /// A literal integer value.
+    IntegerLiteral(i64),
+
+    /// A reference to a previously defined integer variable.
+    Variable(String),
+
+    /// A binary operation between two integer expressions, with the line number of the operator.
+    BinaryOp(Box<IntExpression>, BinaryOperator, Box<IntExpression>, usize),
+
+    /// A parenthesized integer expression for precedence control.
+    Grouping(Box<IntExpression>),
+
+    /// A negated integer expression (unary minus), with the line number of the operator.
+    Negate(Box<IntExpression>, usize),
+
+    /// A call to a function that returns an integer.
+    FunctionCall(String, Vec<TypedExpression>),
+
+    /// An explicit conversion of a number to an integer, truncating towards zero: `int(x)`.
+    /// Carries the line number for reporting values out of the integer range.
+    FromNum(Box<NumExpression>, usize),
+
+    /// An element of a list of integers.
+    ListElement(ListElement),
+
+    /// The number of elements of a list: `len(list)`.
+    ListLength(ListExpression),
+
+    /// A value of a map of integers.
+    MapValue(MapValue),
+
+    /// The number of keys of a map: `len(map)`.
+    MapLength(MapExpression),
+
+    /// An integer field of a struct.
+    Field(FieldAccess),
+
+    /// A call to a built-in string function returning an integer, `len(s)` or
+    /// `index_of(s, part)`, with the line number of the call.
+    StringCall(StringFunction, Vec<TypedExpression>, usize),
+
+    /// The number of command-line arguments, including the program itself: `argc()`.
+    ArgCount,
+}
+
+/// Represents an expression producing a list.
+///
+/// Lists are shared by reference: assigning a list to another variable or
+/// passing it to a function doesn't copy its elements.
+#[derive(Clone)]
+pub enum ListExpression {
+    /// A list literal such as `[1, 2, 3]`, with the element type and the elements.
+    Literal(Type, Vec<TypedExpression>),
+
+    /// A reference to a previously defined list variable.
+    Variable(String),
+
+    /// A call to a function that returns a list.
+    FunctionCall(String, Vec<TypedExpression>),
+
+    /// A new list of strings holding the keys of a map, in insertion order: `keys(map)`.
+    MapKeys(MapExpression),
+
+    /// A list field of a struct.
+    Field(FieldAccess),
+
+    /// A new list of strings made by a built-in string function, `split(s, sep)`,
+    /// with the line number for reporting an empty separator.
+    StringCall(StringFunction, Vec<TypedExpression>, usize),
+}
+
+/// Represents a read of a single element of a list variable. The element type
+/// is given by the expression that contains it.
+#[derive(Clone)]
+pub enum ListElement {
+    /// Indexing, `list[index]`, with the line number for reporting an index
+    /// out of bounds.
+    Index(String, Box<IntExpression>, usize),
+
+    /// Removal of the last element, `pop(list)`, which yields the removed
+    /// element. Carries the line number for reporting an empty list.
+    Pop(String, usize),
+}
+
+/// Represents an expression producing a map.
+///
+/// Like lists, maps are shared by reference.
+#[derive(Clone)]
+pub enum MapExpression {
+    /// A map literal such as `{"alice": 30, "bob": 25}`, with the value type
+    /// and the key-value pairs.
+    Literal(Type, Vec<(Expression, TypedExpression)>),
+
+    /// A reference to a previously defined map variable.
+    Variable(String),
+
+    /// A call to a function that returns a map.
+    FunctionCall(String, Vec<TypedExpression>),
+
+    /// A map field of a struct.
+    Field(FieldAccess),
+}
+
+/// Represents a lookup of the value stored under a key of a map variable,
+/// `map[key]`. The value type is given by the expression that contains it.
+#[derive(Clone)]
+pub struct MapValue {
+    /// The name of the map variable.
+    pub map: String,
+
+    /// The key to look up.
+    pub key: Box<Expression>,
+
+    /// The line number, for reporting a missing key.
+    pub line: usize,
+}
+
+/// Represents an expression producing a struct value.
+///
+/// Unlike lists and maps, structs are values: assigning a struct to another
+/// variable or passing it to a function copies its fields.
+#[derive(Clone)]
+pub enum StructExpression {
+    /// A struct literal such as `Point { x: 1, y: 2 }`, with the struct name and
+    /// a value for every field, in declaration order.
+    Literal(String, Vec<(String, TypedExpression)>),
+
+    /// A reference to a previously defined struct variable.
+    Variable(String),
+
+    /// A call to a function that returns a struct.
+    FunctionCall(String, Vec<TypedExpression>),
+
+    /// A struct field of a struct.
+    Field(FieldAccess),
+}
+
+/// Represents an expression producing an enum value.
+#[derive(Clone)]
+pub enum EnumExpression {
+    /// A variant such as `Color.Red` or `Shape.Circle(2.5)`: the enum name, the
+    /// variant name and the values the variant carries.
+    Variant(String, String, Vec<TypedExpression>),
+
+    /// A reference to a previously defined enum variable.
+    Variable(String),
+
+    /// A call to a function that returns an enum value.
+    FunctionCall(String, Vec<TypedExpression>),
+
+    /// An enum field of a struct.
+    Field(FieldAccess),
+}
+
+/// Represents one arm of a match statement: `pattern => body`.
+#[derive(Clone)]
+pub struct MatchArm {
+    /// The pattern the matched value is compared against.
+    pub pattern: Pattern,
+
+    /// The statements run when the pattern matches.
+    pub body: Vec<Statement>,
+
+    /// The line of the pattern, for reporting unreachable arms.
+    pub line: usize,
+
+    /// The column of the pattern, for reporting unreachable arms.
+    pub column: usize,
+}
+
+/// Represents the pattern of a match arm.
+#[derive(Clone)]
+pub enum Pattern {
+    /// `_`, which matches any value.
+    Wildcard,
+
+    /// A number literal, matching a `num` value.
+    Num(f64),
+
+    /// An integer literal, matching an `int` value.
+    Int(i64),
+
+    /// A string literal, matching a `str` value.
+    Str(String),
+
+    /// `true` or `false`, matching a `bool` value.
+    Bool(bool),
+
+    /// An enum variant with a name and type for each of the values it carries.
+    /// The names are bound to the values inside the arm; `_` ignores a value.
+    Variant(String, Vec<(String, Type)>),
+}
+
+/// Represents a field of a struct variable, such as `line.start.x`: the
+/// variable followed by the path of field names.
+#[derive(Clone)]
+pub struct FieldAccess {
+    /// The name of the struct variable.
+    pub variable: String,
+
+    /// The names of the accessed fields, outermost first.
+    pub fields: Vec<String>,
+}
+
+/// Represents the built-in math functions available in numerical expressions.
+///
+/// These are called without 'callfn' and map directly onto C's math library.
+#[derive(Clone, Copy)]
+pub enum MathFunction {
+    /// Square root: sqrt(x)
+    Sqrt,
+
+    /// Absolute value: abs(x)
+    Abs,
+
+    /// Round down to an integer: floor(x)
+    Floor,
+
+    /// Round up to an integer: ceil(x)
+    Ceil,
+
+    /// Round to the nearest integer, halfway cases away from zero: round(x)
+    Round,
+
+    /// Sine of an angle in radians: sin(x)
+    Sin,
+
+    /// Cosine of an angle in radians: cos(x)
+    Cos,
+
+    /// Tangent of an angle in radians: tan(x)
+    Tan,
+
+    /// Natural logarithm: log(x)
+    Log,
+
+    /// Exponential function e^x: exp(x)
+    Exp,
+
+    /// Smaller of two numbers: min(a, b)
+    Min,
+
+    /// Larger of two numbers: max(a, b)
+    Max,
+}
+
+/// Represents the built-in string functions.
+///
+/// Like the math functions, these are called without 'callfn'. Lengths and
+/// positions count characters rather than bytes, so text encoded as UTF-8 can
+/// be measured and sliced safely.
+#[derive(Clone, Copy)]
+pub enum StringFunction {
+    /// Number of characters: len(s)
+    Length,
+
+    /// Copy with ASCII letters in upper case: upper(s)
+    Upper,
+
+    /// Copy with ASCII letters in lower case: lower(s)
+    Lower,
+
+    /// Copy without leading and trailing whitespace: trim(s)
+    Trim,
+
+    /// Whether a string occurs in another: contains(s, part)
+    Contains,
+
+    /// Whether a string begins with another: starts_with(s, prefix)
+    StartsWith,
+
+    /// Whether a string ends with another: ends_with(s, suffix)
+    EndsWith,
+
+    /// Position of the first occurrence of a string, or -1: index_of(s, part)
+    IndexOf,
+
+    /// Part of a string given its start and number of characters: substr(s, start, count)
+    Substr,
+
+    /// Copy with every occurrence of a string replaced: replace(s, from, to)
+    Replace,
+
+    /// List of the parts between occurrences of a separator: split(s, separator)
+    Split,
+
+    /// Elements of a list of strings joined by a separator: join(list, separator)
+    Join,
 }
 
 /// Represents binary mathematical operators in the Vortlang language.
@@ -107,140 +716,1219 @@ pub enum BinaryOperator {
     
     /// Division operator (/)
     Divide,
+
+    /// Modulo operator (% or mod), with the sign of the dividend
+    Modulo,
+
+    /// Exponentiation operator (** or pow)
+    Power,
+}
+
+/// Represents a boolean expression, such as the condition of an `if` or a
+/// `while`, or the value of a `bool` variable.
+///
+/// The parser resolves the type of the compared operands, so numbers and
+/// strings end up in separate variants and can be lowered differently.
+#[derive(Clone)]
+pub enum Condition {
+    /// A comparison between two numerical expressions.
+    NumComparison(NumExpression, ComparisonOperator, NumExpression),
+
+    /// A comparison between two integer expressions.
+    IntComparison(IntExpression, ComparisonOperator, IntExpression),
+
+    /// A comparison between two string expressions (lexicographic, via `strcmp`).
+    StrComparison(Expression, ComparisonOperator, Expression),
+
+    /// A boolean literal (`true` or `false`).
+    Literal(bool),
+
+    /// A reference to a previously defined boolean variable.
+    Variable(String),
+
+    /// Logical conjunction (`and`); the right side is only evaluated if the left side holds.
+    And(Box<Condition>, Box<Condition>),
+
+    /// Logical disjunction (`or`); the right side is only evaluated if the left side fails.
+    Or(Box<Condition>, Box<Condition>),
+
+    /// Logical negation (`not`).
+    Not(Box<Condition>),
+
+    /// A comparison between two booleans (only `==` and `!=`).
+    BoolComparison(Box<Condition>, ComparisonOperator, Box<Condition>),
+
+    /// A call to a function that returns a boolean.
+    FunctionCall(String, Vec<TypedExpression>),
+
+    /// An element of a list of booleans.
+    ListElement(ListElement),
+
+    /// A value of a map of booleans.
+    MapValue(MapValue),
+
+    /// Whether a map has a value stored under a key: `has(map, key)`.
+    MapHas(MapExpression, Expression),
+
+    /// A boolean field of a struct.
+    Field(FieldAccess),
+
+    /// Whether a string can be converted to a number: `isnum(s)`.
+    IsNum(Expression),
+
+    /// A call to a built-in string function returning a boolean, such as
+    /// `contains(s, part)`, with the line number of the call.
+    StringCall(StringFunction, Vec<TypedExpression>, usize),
+
+    /// Whether a file or directory exists: `file_exists(path)`.
+    FileExists(Expression),
+}
+
+/// Represents comparison operators usable in conditions.
+#[derive(Clone)]
+pub enum ComparisonOperator {
+    /// Equality operator (==)
+    Equal,
+
+    /// Inequality operator (!=)
+    NotEqual,
+
+    /// Less-than operator (<)
+    Less,
+
+    /// Less-than-or-equal operator (<=)
+    LessEqual,
+
+    /// Greater-than operator (>)
+    Greater,
+
+    /// Greater-than-or-equal operator (>=)
+    GreaterEqual,
 }
 
 /// Analyzes the AST for semantic errors and optimization opportunities.
 ///
 /// This function performs static analysis on the program to detect issues
-/// like unused variables, and could be extended to implement optimizations
-/// such as constant folding or dead code elimination.
+/// like unused variables, match statements that don't handle every value and
+/// code after an 'exit' that can never run, and computes the values of
+/// constants, replacing them with literals. It could be extended to implement
+/// further optimizations such as dead code elimination.
 ///
 /// # Arguments
 ///
 /// * `ast` - A vector of Statement objects representing the program
+/// * `source` - The original source code (for error reporting)
+/// * `source_path` - The path to the source file (for error reporting)
 ///
 /// # Returns
 ///
-/// A tuple containing:
-/// * The potentially transformed AST
-/// * A vector of warning messages
-pub fn analyze(ast: Vec<Statement>) -> (Vec<Statement>, Vec<String>) {
-    // Use HashSet for efficient membership testing of variable usage
-    let mut used_variables = std::collections::HashSet::new();
-    
-    // Track where variables are declared to provide precise warning locations
-    let mut declared_variables = std::collections::HashMap::new();
-    
+/// A Result containing either:
+/// * The potentially transformed AST and a vector of warning messages
+/// * A formatted error message if the program is invalid
+pub fn analyze(ast: Vec<Statement>, source: &str, source_path: &str) -> Result<(Vec<Statement>, Vec<String>), String> {
     // Accumulate warnings for reporting to the user
     let mut warnings = Vec::new();
 
-    // First pass: collect all declared variables with their positions
-    // This allows us to know all variables before checking their usage
-    for stmt in ast.iter() {
-        match stmt {
-            Statement::VariableDeclaration(name, _, line_number) => {
-                // Store the actual line number from the source code for warning messages
-                declared_variables.insert(name.clone(), *line_number);
-            },
-            Statement::NumDeclaration(name, _, line_number) => {
-                // Also track numerical variable declarations with source line numbers
-                declared_variables.insert(name.clone(), *line_number);
-            },
-            Statement::FunctionDefinition(_, body) => {
-                // Recursively collect variables from function bodies since all variables are global
-                for body_stmt in body {
-                    match body_stmt {
-                        Statement::VariableDeclaration(name, _, line_number) => {
-                            declared_variables.insert(name.clone(), *line_number);
-                        },
-                        Statement::NumDeclaration(name, _, line_number) => {
-                            declared_variables.insert(name.clone(), *line_number);
-                        },
-                        _ => {}
-                    }
-                }
-            },
-            _ => {}  // Skip other statement types
+    // First pass: collect all global variables with their positions
+    // This allows globals used inside functions before their declaration to count as used
+    let mut globals = Scope::new();
+    for stmt in &ast {
+        if let Some((name, line_number)) = declared_variable(stmt) {
+            // Store the actual line number from the source code for warning messages
+            globals.insert(name.clone(), (line_number, false));
         }
     }
 
-    // Second pass: find all variable usages across the program, including inside functions
-    for stmt in &ast {
-        match stmt {
-            Statement::Print(expr) => {
-                // Check for variable usage in print statements
-                if let Expression::Variable(name) = expr {
-                    used_variables.insert(name.clone());
-                }
-            },
-            Statement::PrintFormat(parts) => {
-                // Handle format strings which may contain variable references or function calls
-                for part in parts {
-                    if let FormatPart::Expression(expr) = part {
-                        match expr {
-                            Expression::Variable(name) => {
-                                used_variables.insert(name.clone());
-                            },
-                            Expression::FunctionCall(_) => {
-                                // Function calls don't produce values, so no variable usage to track here
-                            },
-                            Expression::StringLiteral(_) => {},
-                        }
-                    }
-                }
-            },
-            Statement::NumDeclaration(_, expr, _) => {
-                // Check for variable usage in numerical expressions
-                collect_num_expr_variables(expr, &mut used_variables);
-            },
-            Statement::NumAssignment(_, expr, _) => {
-                collect_num_expr_variables(expr, &mut used_variables);
-            },
-            Statement::FunctionDefinition(_, body) => {
-                // Analyze function body for variable usage
-                for body_stmt in body {
-                    match body_stmt {
-                        Statement::Print(expr) => {
-                            if let Expression::Variable(name) = expr {
-                                used_variables.insert(name.clone());
-                            }
-                        },
-                        Statement::PrintFormat(parts) => {
-                            for part in parts {
-                                if let FormatPart::Expression(expr) = part {
-                                    if let Expression::Variable(name) = expr {
-                                        used_variables.insert(name.clone());
-                                    }
-                                }
-                            }
-                        },
-                        Statement::NumDeclaration(_, expr, _) => {
-                            collect_num_expr_variables(expr, &mut used_variables);
-                        },
-                        Statement::NumAssignment(_, expr, _) => {
-                            collect_num_expr_variables(expr, &mut used_variables);
-                        },
-                        _ => {},
-                    }
-                }
-            },
-            _ => {}  // Skip other statement types
-        }
+    // Second pass: walk the program scope by scope, marking each variable usage
+    // on the declaration it refers to, and report locals as their scopes end
+    let mut scopes = vec![globals];
+    analyze_block(&ast, &mut scopes, &mut warnings);
+
+    // Find unused globals and generate appropriate warnings
+    if let Some(globals) = scopes.pop() {
+        report_unused(globals, &mut warnings);
     }
 
-    // Find unused variables and generate appropriate warnings
-    for (var_name, &line_number) in &declared_variables {
-        if !used_variables.contains(var_name) {
-            warnings.push(format!(
-                "Unused variable '{}' at line {}",
-                var_name, line_number
-            ));
+    // Third pass: check that every match handles all values of its subject
+    let mut enums = std::collections::HashMap::new();
+    for stmt in &ast {
+        if let Statement::EnumDefinition(name, variants) = stmt {
+            let names: Vec<String> = variants.iter().map(|(variant, _)| variant.clone()).collect();
+            enums.insert(name.clone(), names);
         }
     }
+    check_matches(&ast, &enums, source, source_path, &mut warnings)?;
+    check_exits(&ast, &mut warnings);
+
+    // Fourth pass: compute constants, so that errors such as a division by
+    // zero are reported at compile time
+    let ast = fold_constants(ast, source, source_path)?;
 
     // Return the AST (potentially optimized in a more advanced implementation)
     // along with any warnings that should be displayed to the user
-    (ast, warnings)
+    Ok((ast, warnings))
+}
+
+/// Variables declared in a single scope, mapped to their declaration line and
+/// whether they have been used.
+type Scope = std::collections::HashMap<String, (usize, bool)>;
+
+/// Returns the name and line of the variable declared by a statement, if any.
+fn declared_variable(stmt: &Statement) -> Option<(&String, usize)> {
+    match stmt {
+        Statement::VariableDeclaration(name, _, line_number)
+        | Statement::NumDeclaration(name, _, line_number)
+        | Statement::BoolDeclaration(name, _, line_number)
+        | Statement::IntDeclaration(name, _, line_number)
+        | Statement::ListDeclaration(name, _, _, line_number)
+        | Statement::MapDeclaration(name, _, _, line_number)
+        | Statement::StructDeclaration(name, _, _, line_number)
+        | Statement::EnumDeclaration(name, _, _, line_number)
+        | Statement::ConstDeclaration(name, _, line_number, _) => Some((name, *line_number)),
+        _ => None,
+    }
+}
+
+/// Helper function to analyze the statements of one scope.
+///
+/// Usages are resolved from the innermost scope outwards, matching the
+/// scoping rules of the parser, and nested blocks get their own scopes.
+///
+/// # Arguments
+///
+/// * `statements` - The statements to analyze
+/// * `scopes` - The enclosing scopes, innermost last
+/// * `warnings` - Warnings to append to
+fn analyze_block(statements: &[Statement], scopes: &mut Vec<Scope>, warnings: &mut Vec<String>) {
+    for stmt in statements {
+        // Usages are marked before the declaration is added, so the initializer
+        // of a shadowing local still refers to the outer variable
+        let mut used_variables = std::collections::HashSet::new();
+        collect_statement_usages(stmt, &mut used_variables);
+        for name in &used_variables {
+            if let Some(entry) = scopes.iter_mut().rev().find_map(|scope| scope.get_mut(name)) {
+                entry.1 = true;
+            }
+        }
+
+        // Globals were already collected up front
+        if scopes.len() > 1
+            && let Some((name, line_number)) = declared_variable(stmt)
+        {
+            scopes.last_mut().unwrap().insert(name.clone(), (line_number, false));
+        }
+
+        match stmt {
+            Statement::FunctionDefinition(_, params, _, body) => {
+                let params: Vec<String> = params.iter().map(|(name, _)| name.clone()).collect();
+                analyze_nested(body, &params, scopes, warnings);
+            },
+            Statement::If(branches, else_body) => {
+                for (_, body) in branches {
+                    analyze_nested(body, &[], scopes, warnings);
+                }
+                if let Some(body) = else_body {
+                    analyze_nested(body, &[], scopes, warnings);
+                }
+            },
+            Statement::While(_, body) => {
+                analyze_nested(body, &[], scopes, warnings);
+            },
+            Statement::For(var, _, _, _, body, _) | Statement::IntFor(var, _, _, _, body, _) => {
+                analyze_nested(body, std::slice::from_ref(var), scopes, warnings);
+            },
+            Statement::Match(_, arms, _, _) => {
+                for arm in arms {
+                    // Values bound by the pattern are like parameters of the arm
+                    let bindings: Vec<String> = match &arm.pattern {
+                        Pattern::Variant(_, bindings) => bindings.iter().map(|(name, _)| name.clone()).collect(),
+                        _ => Vec::new(),
+                    };
+                    analyze_nested(&arm.body, &bindings, scopes, warnings);
+                }
+            },
+            _ => {}  // Other statement types have no nested scope
+        }
+    }
+}
+
+/// Helper function to analyze a nested scope and report its unused variables.
+///
+/// # Arguments
+///
+/// * `body` - The statements of the nested scope
+/// * `implicit` - Variables introduced by the construct itself (function parameters,
+///   loop variables), which are never reported as unused
+/// * `scopes` - The enclosing scopes, innermost last
+/// * `warnings` - Warnings to append to
+fn analyze_nested(body: &[Statement], implicit: &[String], scopes: &mut Vec<Scope>, warnings: &mut Vec<String>) {
+    let mut scope = Scope::new();
+    for name in implicit {
+        scope.insert(name.clone(), (0, true));
+    }
+
+    scopes.push(scope);
+    analyze_block(body, scopes, warnings);
+    if let Some(scope) = scopes.pop() {
+        report_unused(scope, warnings);
+    }
+}
+
+/// Returns the bodies of the blocks nested directly in a statement.
+fn nested_bodies(stmt: &Statement) -> Vec<&Vec<Statement>> {
+    match stmt {
+        Statement::FunctionDefinition(_, _, _, body)
+        | Statement::While(_, body)
+        | Statement::For(_, _, _, _, body, _)
+        | Statement::IntFor(_, _, _, _, body, _) => vec![body],
+        Statement::If(branches, else_body) => branches.iter().map(|(_, body)| body).chain(else_body).collect(),
+        Statement::Match(_, arms, _, _) => arms.iter().map(|arm| &arm.body).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Helper function to warn about statements following an 'exit' in the same
+/// block, including nested blocks, since they can never run.
+///
+/// Definitions are not code that runs in place, so they don't count.
+///
+/// # Arguments
+///
+/// * `statements` - The statements to check
+/// * `warnings` - Warnings to append to
+fn check_exits(statements: &[Statement], warnings: &mut Vec<String>) {
+    let mut exit_line = None;
+    let mut reported = false;
+    for stmt in statements {
+        let is_definition = matches!(
+            stmt,
+            Statement::FunctionDefinition(..)
+                | Statement::CFunctionDefinition(..)
+                | Statement::StructDefinition(..)
+                | Statement::EnumDefinition(..)
+                | Statement::ConstDeclaration(..)
+        );
+        if let Some(line) = exit_line
+            && !is_definition
+            && !reported
+        {
+            warnings.push(format!("Unreachable code after 'exit' at line {}", line));
+            reported = true;
+        }
+        if let Statement::Exit(_, line) = stmt
+            && exit_line.is_none()
+        {
+            exit_line = Some(*line);
+        }
+        for body in nested_bodies(stmt) {
+            check_exits(body, warnings);
+        }
+    }
+}
+
+/// Helper function to check every match statement of a block, including the
+/// ones in nested blocks.
+///
+/// # Arguments
+///
+/// * `statements` - The statements to check
+/// * `enums` - The variant names of every enum, in declaration order
+/// * `source` - The original source code (for error reporting)
+/// * `source_path` - The path to the source file (for error reporting)
+/// * `warnings` - Warnings to append to
+///
+/// # Returns
+///
+/// A Result indicating success or a formatted error message for the first
+/// match that doesn't handle every value
+fn check_matches(
+    statements: &[Statement],
+    enums: &std::collections::HashMap<String, Vec<String>>,
+    source: &str,
+    source_path: &str,
+    warnings: &mut Vec<String>,
+) -> Result<(), String> {
+    for stmt in statements {
+        if let Statement::Match(subject, arms, line, column) = stmt {
+            check_match(subject, arms, ErrorPosition { line: *line, column: *column }, enums, source, source_path, warnings)?;
+        }
+        for body in nested_bodies(stmt) {
+            check_matches(body, enums, source, source_path, warnings)?;
+        }
+    }
+    Ok(())
+}
+
+/// Helper function to check a single match statement.
+///
+/// Arms that can never run, because an earlier arm handles the same value or
+/// the arms above already handle every value, are reported as warnings. A
+/// match that leaves values unhandled is an error: enums must handle every
+/// variant, booleans both values, and numbers and strings need a '_' arm.
+///
+/// # Arguments
+///
+/// * `subject` - The matched value
+/// * `arms` - The arms of the match, in source order
+/// * `position` - The position of the 'match' keyword
+/// * `enums` - The variant names of every enum, in declaration order
+/// * `source` - The original source code (for error reporting)
+/// * `source_path` - The path to the source file (for error reporting)
+/// * `warnings` - Warnings to append to
+fn check_match(
+    subject: &TypedExpression,
+    arms: &[MatchArm],
+    position: ErrorPosition,
+    enums: &std::collections::HashMap<String, Vec<String>>,
+    source: &str,
+    source_path: &str,
+    warnings: &mut Vec<String>,
+) -> Result<(), String> {
+    // The values a complete match has to handle, if there are finitely many
+    let all_values: Option<Vec<String>> = match subject {
+        TypedExpression::Enum(name, _) => Some(enums.get(name).cloned().unwrap_or_default()),
+        TypedExpression::Bool(_) => Some(vec!["true".to_string(), "false".to_string()]),
+        _ => None,
+    };
+
+    // Patterns handled so far, with the line of their arm
+    let mut handled: Vec<(String, usize)> = Vec::new();
+    // Why the remaining arms can't run, once every value is handled
+    let mut complete: Option<String> = None;
+    for arm in arms {
+        let value = match &arm.pattern {
+            Pattern::Wildcard => None,
+            Pattern::Num(value) => Some(value.to_string()),
+            Pattern::Int(value) => Some(value.to_string()),
+            Pattern::Str(value) => Some(format!("{:?}", value)),
+            Pattern::Bool(value) => Some(value.to_string()),
+            Pattern::Variant(name, _) => Some(name.clone()),
+        };
+
+        let unreachable = match (&complete, &value) {
+            (Some(reason), _) => Some(reason.clone()),
+            (None, Some(value)) => handled
+                .iter()
+                .find(|(existing, _)| existing == value)
+                .map(|(_, line)| format!("'{}' is already handled at line {}", value, line)),
+            (None, None) => None,
+        };
+        if let Some(reason) = unreachable {
+            warnings.push(format!(
+                "Unreachable match arm at line {}, column {}: {}",
+                arm.line, arm.column, reason
+            ));
+            continue;
+        }
+
+        match value {
+            None => complete = Some(format!("the '_' arm at line {} already handles every value", arm.line)),
+            Some(value) => {
+                handled.push((value, arm.line));
+                if let Some(values) = &all_values
+                    && values.iter().all(|value| handled.iter().any(|(existing, _)| existing == value))
+                {
+                    complete = Some("the arms above already handle every value".to_string());
+                }
+            }
+        }
+    }
+
+    if complete.is_some() {
+        return Ok(());
+    }
+    let message = match (&all_values, subject) {
+        (Some(values), _) => {
+            let missing: Vec<String> = values
+                .iter()
+                .filter(|value| !handled.iter().any(|(existing, _)| existing == *value))
+                .map(|value| format!("'{}'", value))
+                .collect();
+            let (noun, verb) = if missing.len() == 1 { ("value", "is") } else { ("values", "are") };
+            match subject {
+                TypedExpression::Enum(name, _) => format!(
+                    "Non-exhaustive match: {} {} of '{}' {} not handled",
+                    if missing.len() == 1 { "variant" } else { "variants" },
+                    missing.join(", "),
+                    name,
+                    verb
+                ),
+                _ => format!("Non-exhaustive match: {} {} {} not handled", noun, missing.join(", "), verb),
+            }
+        }
+        (None, TypedExpression::Str(_)) => "Non-exhaustive match: not every string is handled".to_string(),
+        (None, TypedExpression::Int(_)) => "Non-exhaustive match: not every integer is handled".to_string(),
+        (None, _) => "Non-exhaustive match: not every number is handled".to_string(),
+    };
+    let hint = match all_values {
+        Some(_) => "Add an arm for each missing value, or a '_' arm to handle the rest",
+        None => "Add a '_' arm to handle the remaining values",
+    };
+    Err(format_error(source_path, source, position, message, hint.to_string()))
+}
+
+/// The value of a constant, computed by the analysis pass.
+#[derive(Clone)]
+enum ConstValue {
+    Num(f64),
+    Int(i64),
+    Str(String),
+}
+
+/// Why a constant can't be computed: the message and a hint for fixing it.
+type ConstError = (String, &'static str);
+
+/// Hint for constants that refer to values only known at run time.
+const RUNTIME_VALUE_HINT: &str = "Constants can only be computed from literals, other constants, operators and math functions";
+
+/// Helper function to compute every constant of the program and replace its
+/// value with the resulting literal.
+///
+/// # Arguments
+///
+/// * `ast` - The statements of the program
+/// * `source` - The original source code (for error reporting)
+/// * `source_path` - The path to the source file (for error reporting)
+///
+/// # Returns
+///
+/// A Result containing either:
+/// * The statements with the values of constants folded into literals
+/// * A formatted error message for the first constant that can't be computed
+fn fold_constants(ast: Vec<Statement>, source: &str, source_path: &str) -> Result<Vec<Statement>, String> {
+    let mut constants = std::collections::HashMap::new();
+    ast.into_iter()
+        .map(|stmt| {
+            let Statement::ConstDeclaration(name, value, line, column) = stmt else {
+                return Ok(stmt);
+            };
+            let computed = match &value {
+                TypedExpression::Str(expr) => evaluate_str(expr, &constants).map(ConstValue::Str),
+                TypedExpression::Int(expr) => evaluate_int(expr, &constants).map(ConstValue::Int),
+                TypedExpression::Num(expr) => match evaluate_num(expr, &constants) {
+                    Ok(value) if !value.is_finite() => Err((
+                        "the result is not a finite number".to_string(),
+                        "Check the operands of divisions, logarithms, square roots and powers",
+                    )),
+                    result => result.map(ConstValue::Num),
+                },
+                _ => Err(("constants can only hold numbers, integers and strings".to_string(), RUNTIME_VALUE_HINT)),
+            };
+            let computed = computed.map_err(|(reason, hint)| {
+                format_error(
+                    source_path,
+                    source,
+                    ErrorPosition { line, column },
+                    format!("Cannot compute constant '{}': {}", name, reason),
+                    hint.to_string(),
+                )
+            })?;
+
+            let literal = match &computed {
+                ConstValue::Num(value) => TypedExpression::Num(NumExpression::NumberLiteral(*value)),
+                ConstValue::Int(value) => TypedExpression::Int(IntExpression::IntegerLiteral(*value)),
+                ConstValue::Str(value) => TypedExpression::Str(Expression::StringLiteral(value.clone())),
+            };
+            constants.insert(name.clone(), computed);
+            Ok(Statement::ConstDeclaration(name, literal, line, column))
+        })
+        .collect()
+}
+
+/// Helper function to look up a constant referenced by another constant.
+fn constant_value<'a>(
+    name: &str,
+    constants: &'a std::collections::HashMap<String, ConstValue>,
+) -> Result<&'a ConstValue, ConstError> {
+    constants
+        .get(name)
+        .ok_or_else(|| (format!("'{}' is a variable, not a constant", name), RUNTIME_VALUE_HINT))
+}
+
+/// Helper function to compute a constant string expression.
+fn evaluate_str(
+    expr: &Expression,
+    constants: &std::collections::HashMap<String, ConstValue>,
+) -> Result<String, ConstError> {
+    match expr {
+        Expression::StringLiteral(value) => Ok(value.clone()),
+        Expression::Variable(name) => match constant_value(name, constants)? {
+            ConstValue::Str(value) => Ok(value.clone()),
+            _ => Err((format!("'{}' is not a string", name), RUNTIME_VALUE_HINT)),
+        },
+        Expression::Concat(operands) => {
+            let mut text = String::new();
+            for operand in operands {
+                text.push_str(&evaluate_text(operand, constants)?);
+            }
+            Ok(text)
+        }
+        Expression::ToStr(value) => evaluate_text(value, constants),
+        Expression::Format(_) => Err((
+            "format strings are built at run time".to_string(),
+            "Join the parts of a constant string with '+' instead",
+        )),
+        Expression::FunctionCall(name, _) => Err((format!("calls to function '{}' happen at run time", name), RUNTIME_VALUE_HINT)),
+        Expression::Input(_, _) => Err(("input is only read at run time".to_string(), RUNTIME_VALUE_HINT)),
+        Expression::ReadFile(_, _) => Err(("files are only read at run time".to_string(), RUNTIME_VALUE_HINT)),
+        Expression::Arg(_, _) | Expression::Env(_, _, _) => {
+            Err(("arguments and environment variables are only known at run time".to_string(), RUNTIME_VALUE_HINT))
+        }
+        Expression::StringCall(function, args, _) => {
+            let Some(TypedExpression::Str(text)) = args.first() else {
+                return Err(("lists are only known at run time".to_string(), RUNTIME_VALUE_HINT));
+            };
+            let text = evaluate_str(text, constants)?;
+            match (function, &args[1..]) {
+                (StringFunction::Upper, _) => Ok(text.to_ascii_uppercase()),
+                (StringFunction::Lower, _) => Ok(text.to_ascii_lowercase()),
+                // The same whitespace as C's isspace
+                (StringFunction::Trim, _) => Ok(text.trim_matches([' ', '\t', '\r', '\n', '\x0b', '\x0c']).to_string()),
+                (StringFunction::Substr, [TypedExpression::Int(start), TypedExpression::Int(count)]) => {
+                    let start = evaluate_int(start, constants)?;
+                    let count = evaluate_int(count, constants)?;
+                    let length = text.chars().count() as i64;
+                    if start < 0 || count < 0 || start > length || count > length - start {
+                        return Err((
+                            format!("substring from {} with length {} is out of bounds for length {}", start, count, length),
+                            "Take a part that lies within the string",
+                        ));
+                    }
+                    Ok(text.chars().skip(start as usize).take(count as usize).collect())
+                }
+                (StringFunction::Replace, [TypedExpression::Str(from), TypedExpression::Str(to)]) => {
+                    let from = evaluate_str(from, constants)?;
+                    if from.is_empty() {
+                        return Err(("cannot replace an empty string".to_string(), "Replace a string that isn't empty"));
+                    }
+                    Ok(text.replace(&from, &evaluate_str(to, constants)?))
+                }
+                _ => unreachable!(),
+            }
+        }
+        Expression::ListElement(_) | Expression::MapValue(_) | Expression::Field(_) => {
+            Err(("values of lists, maps and structs are only known at run time".to_string(), RUNTIME_VALUE_HINT))
+        }
+    }
+}
+
+/// Helper function to compute the text of a constant value converted to a string.
+fn evaluate_text(
+    expr: &TypedExpression,
+    constants: &std::collections::HashMap<String, ConstValue>,
+) -> Result<String, ConstError> {
+    match expr {
+        TypedExpression::Str(expr) => evaluate_str(expr, constants),
+        TypedExpression::Num(expr) => Ok(format_number(evaluate_num(expr, constants)?)),
+        TypedExpression::Int(expr) => Ok(evaluate_int(expr, constants)?.to_string()),
+        TypedExpression::Bool(Condition::Literal(value)) => Ok(value.to_string()),
+        _ => Err(("only strings and numbers can be converted to a constant string".to_string(), RUNTIME_VALUE_HINT)),
+    }
+}
+
+/// Helper function to compute a constant numerical expression.
+fn evaluate_num(
+    expr: &NumExpression,
+    constants: &std::collections::HashMap<String, ConstValue>,
+) -> Result<f64, ConstError> {
+    match expr {
+        NumExpression::NumberLiteral(value) => Ok(*value),
+        NumExpression::Variable(name) => match constant_value(name, constants)? {
+            ConstValue::Num(value) => Ok(*value),
+            _ => Err((format!("'{}' is not a number", name), RUNTIME_VALUE_HINT)),
+        },
+        NumExpression::BinaryOp(left, op, right) => {
+            let left = evaluate_num(left, constants)?;
+            let right = evaluate_num(right, constants)?;
+            match op {
+                BinaryOperator::Add => Ok(left + right),
+                BinaryOperator::Subtract => Ok(left - right),
+                BinaryOperator::Multiply => Ok(left * right),
+                BinaryOperator::Divide | BinaryOperator::Modulo if right == 0.0 => {
+                    Err(("division by zero".to_string(), "Divide by a value that isn't zero"))
+                }
+                BinaryOperator::Divide => Ok(left / right),
+                // Rust's % on floats matches C's fmod
+                BinaryOperator::Modulo => Ok(left % right),
+                BinaryOperator::Power => Ok(left.powf(right)),
+            }
+        }
+        NumExpression::Grouping(inner) => evaluate_num(inner, constants),
+        NumExpression::Negate(inner) => Ok(-evaluate_num(inner, constants)?),
+        NumExpression::MathCall(function, args) => {
+            let args = args
+                .iter()
+                .map(|arg| evaluate_num(arg, constants))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(match function {
+                MathFunction::Sqrt => args[0].sqrt(),
+                MathFunction::Abs => args[0].abs(),
+                MathFunction::Floor => args[0].floor(),
+                MathFunction::Ceil => args[0].ceil(),
+                MathFunction::Round => args[0].round(),
+                MathFunction::Sin => args[0].sin(),
+                MathFunction::Cos => args[0].cos(),
+                MathFunction::Tan => args[0].tan(),
+                MathFunction::Log => args[0].ln(),
+                MathFunction::Exp => args[0].exp(),
+                MathFunction::Min => args[0].min(args[1]),
+                MathFunction::Max => args[0].max(args[1]),
+            })
+        }
+        NumExpression::FromInt(inner) => Ok(evaluate_int(inner, constants)? as f64),
+        NumExpression::FunctionCall(name, _) => Err((format!("calls to function '{}' happen at run time", name), RUNTIME_VALUE_HINT)),
+        NumExpression::InputNum(_, _) => Err(("input is only read at run time".to_string(), RUNTIME_VALUE_HINT)),
+        NumExpression::ToNum(text, _) => {
+            // Like strtod in the runtime, surrounding whitespace is allowed
+            let text = evaluate_str(text, constants)?;
+            match text.trim().parse::<f64>() {
+                Ok(value) if value.is_finite() => Ok(value),
+                _ => Err((format!("'{}' is not a number", text), "Convert a string that holds a number, such as \"3.5\"")),
+            }
+        }
+        NumExpression::ListElement(_) | NumExpression::MapValue(_) | NumExpression::Field(_) => {
+            Err(("values of lists, maps and structs are only known at run time".to_string(), RUNTIME_VALUE_HINT))
+        }
+    }
+}
+
+/// Helper function to compute a constant integer expression. Overflow is
+/// always an error, as in the checked mode of the integer runtime.
+fn evaluate_int(
+    expr: &IntExpression,
+    constants: &std::collections::HashMap<String, ConstValue>,
+) -> Result<i64, ConstError> {
+    let overflow = || ("integer overflow".to_string(), "Keep the value within the range of 'int'");
+    match expr {
+        IntExpression::IntegerLiteral(value) => Ok(*value),
+        IntExpression::Variable(name) => match constant_value(name, constants)? {
+            ConstValue::Int(value) => Ok(*value),
+            _ => Err((format!("'{}' is not an integer", name), RUNTIME_VALUE_HINT)),
+        },
+        IntExpression::BinaryOp(left, op, right, _) => {
+            let left = evaluate_int(left, constants)?;
+            let right = evaluate_int(right, constants)?;
+            match op {
+                BinaryOperator::Add => left.checked_add(right).ok_or_else(overflow),
+                BinaryOperator::Subtract => left.checked_sub(right).ok_or_else(overflow),
+                BinaryOperator::Multiply => left.checked_mul(right).ok_or_else(overflow),
+                BinaryOperator::Divide | BinaryOperator::Modulo if right == 0 => {
+                    Err(("division by zero".to_string(), "Divide by a value that isn't zero"))
+                }
+                BinaryOperator::Divide => left.checked_div(right).ok_or_else(overflow),
+                // Like the runtime, the remainder of a division by -1 is 0 even for the smallest int
+                BinaryOperator::Modulo => Ok(left.checked_rem(right).unwrap_or(0)),
+                BinaryOperator::Power => {
+                    if right < 0 {
+                        return Err(("negative exponent in integer power".to_string(), "Use a 'num' power for negative exponents"));
+                    }
+                    let exponent = u32::try_from(right).map_err(|_| overflow())?;
+                    left.checked_pow(exponent).ok_or_else(overflow)
+                }
+            }
+        }
+        IntExpression::Grouping(inner) => evaluate_int(inner, constants),
+        IntExpression::Negate(inner, _) => evaluate_int(inner, constants)?.checked_neg().ok_or_else(overflow),
+        IntExpression::FromNum(inner, _) => {
+            let value = evaluate_num(inner, constants)?;
+            if value.is_nan() || !(-9223372036854775808.0..9223372036854775808.0).contains(&value) {
+                return Err(("number out of range for int".to_string(), "Keep the value within the range of 'int'"));
+            }
+            Ok(value as i64)
+        }
+        IntExpression::FunctionCall(name, _) => Err((format!("calls to function '{}' happen at run time", name), RUNTIME_VALUE_HINT)),
+        IntExpression::StringCall(function, args, _) => match (function, args.as_slice()) {
+            (StringFunction::Length, [TypedExpression::Str(text)]) => Ok(evaluate_str(text, constants)?.chars().count() as i64),
+            (StringFunction::IndexOf, [TypedExpression::Str(text), TypedExpression::Str(part)]) => {
+                let text = evaluate_str(text, constants)?;
+                let part = evaluate_str(part, constants)?;
+                Ok(text.find(&part).map_or(-1, |index| text[..index].chars().count() as i64))
+            }
+            _ => unreachable!(),
+        },
+        IntExpression::ArgCount => {
+            Err(("arguments and environment variables are only known at run time".to_string(), RUNTIME_VALUE_HINT))
+        }
+        IntExpression::ListElement(_)
+        | IntExpression::ListLength(_)
+        | IntExpression::MapValue(_)
+        | IntExpression::MapLength(_)
+        | IntExpression::Field(_) => {
+            Err(("values of lists, maps and structs are only known at run time".to_string(), RUNTIME_VALUE_HINT))
+        }
+    }
+}
+
+/// Formats a number the way the generated C code does with "%g": six
+/// significant digits without trailing zeros, in exponent notation for very
+/// large or small values.
+fn format_number(value: f64) -> String {
+    if value == 0.0 || !value.is_finite() {
+        return match value {
+            v if v.is_nan() => "nan".to_string(),
+            v if v.is_infinite() => if v > 0.0 { "inf" } else { "-inf" }.to_string(),
+            v if v.is_sign_negative() => "-0".to_string(),
+            _ => "0".to_string(),
+        };
+    }
+
+    // Round to six significant digits first, which may change the exponent
+    let scientific = format!("{:.5e}", value);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let strip = |digits: &str| {
+        if digits.contains('.') {
+            digits.trim_end_matches('0').trim_end_matches('.').to_string()
+        } else {
+            digits.to_string()
+        }
+    };
+    if !(-4..6).contains(&exponent) {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", strip(mantissa), sign, exponent.abs())
+    } else {
+        strip(&format!("{:.*}", (5 - exponent) as usize, value))
+    }
+}
+
+/// Helper function to generate a warning for every unused variable of a scope.
+fn report_unused(scope: Scope, warnings: &mut Vec<String>) {
+    for (var_name, (line_number, used)) in scope {
+        if !used {
+            warnings.push(format!(
+                "Unused variable '{}' at line {}",
+                var_name, line_number
+            ));
+        }
+    }
+}
+
+/// Helper function to collect the variables referenced by a statement itself.
+///
+/// Bodies of nested blocks are not included; they are analyzed in their own scopes.
+///
+/// # Arguments
+///
+/// * `stmt` - The statement to scan
+/// * `used_variables` - Set of used variables to update
+fn collect_statement_usages(
+    stmt: &Statement,
+    used_variables: &mut std::collections::HashSet<String>
+) {
+    match stmt {
+        Statement::Print(expr) => {
+            // Check for variable usage in print statements
+            collect_expr_variables(expr, used_variables);
+        },
+        Statement::PrintFormat(parts) => {
+            // Handle format strings which may contain variable references or function calls
+            collect_format_variables(parts, used_variables);
+        },
+        Statement::VariableDeclaration(_, expr, _) | Statement::VariableAssignment(_, expr, _) => {
+            collect_expr_variables(expr, used_variables);
+        },
+        Statement::NumDeclaration(_, expr, _) | Statement::NumAssignment(_, expr, _) => {
+            // Check for variable usage in numerical expressions
+            collect_num_expr_variables(expr, used_variables);
+        },
+        Statement::BoolDeclaration(_, condition, _) | Statement::BoolAssignment(_, condition, _) => {
+            collect_condition_variables(condition, used_variables);
+        },
+        Statement::IntDeclaration(_, expr, _) | Statement::IntAssignment(_, expr, _) => {
+            collect_int_expr_variables(expr, used_variables);
+        },
+        Statement::ListDeclaration(_, _, expr, _) | Statement::ListAssignment(_, expr, _) => {
+            collect_list_expr_variables(expr, used_variables);
+        },
+        Statement::ListElementAssignment(_, index, value, _) => {
+            // Storing into a list doesn't count as using it, just like an assignment
+            collect_int_expr_variables(index, used_variables);
+            collect_typed_expr_variables(value, used_variables);
+        },
+        Statement::ListPush(name, value) => {
+            // Unlike storing into an element, appending works on the list's
+            // current contents, so it counts as using the list
+            used_variables.insert(name.clone());
+            collect_typed_expr_variables(value, used_variables);
+        },
+        Statement::ListPop(name, _) => {
+            used_variables.insert(name.clone());
+        },
+        Statement::MapDeclaration(_, _, expr, _) | Statement::MapAssignment(_, expr, _) => {
+            collect_map_expr_variables(expr, used_variables);
+        },
+        Statement::MapInsert(_, key, value) => {
+            // Storing into a map doesn't count as using it, just like an assignment
+            collect_expr_variables(key, used_variables);
+            collect_typed_expr_variables(value, used_variables);
+        },
+        Statement::MapRemove(name, key, _) => {
+            used_variables.insert(name.clone());
+            collect_expr_variables(key, used_variables);
+        },
+        Statement::WriteFile(path, text, _) | Statement::AppendFile(path, text, _) => {
+            collect_expr_variables(path, used_variables);
+            collect_expr_variables(text, used_variables);
+        },
+        Statement::DeleteFile(path, _) => {
+            collect_expr_variables(path, used_variables);
+        },
+        Statement::StructDeclaration(_, _, expr, _) | Statement::StructAssignment(_, expr, _) => {
+            collect_struct_expr_variables(expr, used_variables);
+        },
+        Statement::FieldAssignment(_, value) => {
+            // Storing into a field doesn't count as using the struct, just like an assignment
+            collect_typed_expr_variables(value, used_variables);
+        },
+        Statement::EnumDeclaration(_, _, expr, _) | Statement::EnumAssignment(_, expr, _) => {
+            collect_enum_expr_variables(expr, used_variables);
+        },
+        Statement::Match(subject, _, _, _) => {
+            collect_typed_expr_variables(subject, used_variables);
+        },
+        Statement::ConstDeclaration(_, value, _, _) => {
+            collect_typed_expr_variables(value, used_variables);
+        },
+        Statement::FunctionCall(_, args) => {
+            collect_args_variables(args, used_variables);
+        },
+        Statement::Return(Some(value)) => {
+            collect_typed_expr_variables(value, used_variables);
+        },
+        Statement::Exit(code, _) => {
+            collect_int_expr_variables(code, used_variables);
+        },
+        Statement::If(branches, _) => {
+            for (condition, _) in branches {
+                collect_condition_variables(condition, used_variables);
+            }
+        },
+        Statement::While(condition, _) => {
+            collect_condition_variables(condition, used_variables);
+        },
+        Statement::For(_, start, end, step, _, _) => {
+            collect_num_expr_variables(start, used_variables);
+            collect_num_expr_variables(end, used_variables);
+            collect_num_expr_variables(step, used_variables);
+        },
+        Statement::IntFor(_, start, end, step, _, _) => {
+            collect_int_expr_variables(start, used_variables);
+            collect_int_expr_variables(end, used_variables);
+            collect_int_expr_variables(step, used_variables);
+        },
+        _ => {}  // Skip other statement types
+    }
+}
+
+/// Helper function to collect all variable references in a string expression.
+///
+/// # Arguments
+///
+/// * `expr` - The string expression to analyze
+/// * `used_variables` - Set of used variables to update
+fn collect_expr_variables(
+    expr: &Expression,
+    used_variables: &mut std::collections::HashSet<String>
+) {
+    match expr {
+        Expression::Variable(name) => {
+            used_variables.insert(name.clone());
+        },
+        Expression::FunctionCall(_, args) | Expression::Concat(args) | Expression::StringCall(_, args, _) => {
+            collect_args_variables(args, used_variables);
+        },
+        Expression::Format(parts) => {
+            collect_format_variables(parts, used_variables);
+        },
+        Expression::ListElement(element) => {
+            collect_list_element_variables(element, used_variables);
+        },
+        Expression::MapValue(value) => {
+            collect_map_value_variables(value, used_variables);
+        },
+        Expression::Field(access) => {
+            used_variables.insert(access.variable.clone());
+        },
+        Expression::Input(prompt, _) => {
+            if let Some(prompt) = prompt {
+                collect_expr_variables(prompt, used_variables);
+            }
+        },
+        Expression::ToStr(value) => {
+            collect_typed_expr_variables(value, used_variables);
+        },
+        Expression::ReadFile(path, _) => {
+            collect_expr_variables(path, used_variables);
+        },
+        Expression::Arg(index, _) => {
+            collect_int_expr_variables(index, used_variables);
+        },
+        Expression::Env(name, default, _) => {
+            collect_expr_variables(name, used_variables);
+            if let Some(default) = default {
+                collect_expr_variables(default, used_variables);
+            }
+        },
+        Expression::StringLiteral(_) => {},
+    }
+}
+
+/// Helper function to collect all variable references in the parts of a format string.
+///
+/// # Arguments
+///
+/// * `parts` - The literal and interpolated parts of the format string
+/// * `used_variables` - Set of used variables to update
+fn collect_format_variables(
+    parts: &[FormatPart],
+    used_variables: &mut std::collections::HashSet<String>
+) {
+    for part in parts {
+        if let FormatPart::Expression(expr, _) = part {
+            collect_typed_expr_variables(expr, used_variables);
+        }
+    }
+}
+
+/// Helper function to collect all variable references in a condition.
+///
+/// # Arguments
+///
+/// * `condition` - The condition to analyze
+/// * `used_variables` - Set of used variables to update
+fn collect_condition_variables(
+    condition: &Condition,
+    used_variables: &mut std::collections::HashSet<String>
+) {
+    match condition {
+        Condition::NumComparison(left, _, right) => {
+            collect_num_expr_variables(left, used_variables);
+            collect_num_expr_variables(right, used_variables);
+        },
+        Condition::IntComparison(left, _, right) => {
+            collect_int_expr_variables(left, used_variables);
+            collect_int_expr_variables(right, used_variables);
+        },
+        Condition::StrComparison(left, _, right) => {
+            collect_expr_variables(left, used_variables);
+            collect_expr_variables(right, used_variables);
+        },
+        Condition::Variable(name) => {
+            used_variables.insert(name.clone());
+        },
+        Condition::And(left, right) | Condition::Or(left, right) => {
+            collect_condition_variables(left, used_variables);
+            collect_condition_variables(right, used_variables);
+        },
+        Condition::Not(inner) => {
+            collect_condition_variables(inner, used_variables);
+        },
+        Condition::BoolComparison(left, _, right) => {
+            collect_condition_variables(left, used_variables);
+            collect_condition_variables(right, used_variables);
+        },
+        Condition::FunctionCall(_, args) | Condition::StringCall(_, args, _) => {
+            collect_args_variables(args, used_variables);
+        },
+        Condition::ListElement(element) => {
+            collect_list_element_variables(element, used_variables);
+        },
+        Condition::MapValue(value) => {
+            collect_map_value_variables(value, used_variables);
+        },
+        Condition::Field(access) => {
+            used_variables.insert(access.variable.clone());
+        },
+        Condition::MapHas(map, key) => {
+            collect_map_expr_variables(map, used_variables);
+            collect_expr_variables(key, used_variables);
+        },
+        Condition::IsNum(text) | Condition::FileExists(text) => {
+            collect_expr_variables(text, used_variables);
+        },
+        Condition::Literal(_) => {},
+    }
+}
+
+/// Helper function to collect all variable references in an expression of any type.
+///
+/// # Arguments
+///
+/// * `expr` - The typed expression to analyze
+/// * `used_variables` - Set of used variables to update
+fn collect_typed_expr_variables(
+    expr: &TypedExpression,
+    used_variables: &mut std::collections::HashSet<String>
+) {
+    match expr {
+        TypedExpression::Str(expr) => collect_expr_variables(expr, used_variables),
+        TypedExpression::Num(expr) => collect_num_expr_variables(expr, used_variables),
+        TypedExpression::Bool(condition) => collect_condition_variables(condition, used_variables),
+        TypedExpression::Int(expr) => collect_int_expr_variables(expr, used_variables),
+        TypedExpression::List(expr) => collect_list_expr_variables(expr, used_variables),
+        TypedExpression::Map(expr) => collect_map_expr_variables(expr, used_variables),
+        TypedExpression::Struct(_, expr) => collect_struct_expr_variables(expr, used_variables),
+        TypedExpression::Enum(_, expr) => collect_enum_expr_variables(expr, used_variables),
+    }
+}
+
+/// Helper function to collect all variable references in a list expression.
+///
+/// # Arguments
+///
+/// * `expr` - The list expression to analyze
+/// * `used_variables` - Set of used variables to update
+fn collect_list_expr_variables(
+    expr: &ListExpression,
+    used_variables: &mut std::collections::HashSet<String>
+) {
+    match expr {
+        ListExpression::Variable(name) => {
+            used_variables.insert(name.clone());
+        },
+        ListExpression::Literal(_, elements)
+        | ListExpression::FunctionCall(_, elements)
+        | ListExpression::StringCall(_, elements, _) => {
+            collect_args_variables(elements, used_variables);
+        },
+        ListExpression::MapKeys(map) => {
+            collect_map_expr_variables(map, used_variables);
+        },
+        ListExpression::Field(access) => {
+            used_variables.insert(access.variable.clone());
+        },
+    }
+}
+
+/// Helper function to collect all variable references in a list element access.
+///
+/// # Arguments
+///
+/// * `element` - The list element access to analyze
+/// * `used_variables` - Set of used variables to update
+fn collect_list_element_variables(
+    element: &ListElement,
+    used_variables: &mut std::collections::HashSet<String>
+) {
+    match element {
+        ListElement::Index(name, index, _) => {
+            used_variables.insert(name.clone());
+            collect_int_expr_variables(index, used_variables);
+        },
+        ListElement::Pop(name, _) => {
+            used_variables.insert(name.clone());
+        },
+    }
+}
+
+/// Helper function to collect all variable references in a map expression.
+///
+/// # Arguments
+///
+/// * `expr` - The map expression to analyze
+/// * `used_variables` - Set of used variables to update
+fn collect_map_expr_variables(
+    expr: &MapExpression,
+    used_variables: &mut std::collections::HashSet<String>
+) {
+    match expr {
+        MapExpression::Variable(name) => {
+            used_variables.insert(name.clone());
+        },
+        MapExpression::Literal(_, entries) => {
+            for (key, value) in entries {
+                collect_expr_variables(key, used_variables);
+                collect_typed_expr_variables(value, used_variables);
+            }
+        },
+        MapExpression::FunctionCall(_, args) => {
+            collect_args_variables(args, used_variables);
+        },
+        MapExpression::Field(access) => {
+            used_variables.insert(access.variable.clone());
+        },
+    }
+}
+
+/// Helper function to collect all variable references in a struct expression.
+///
+/// # Arguments
+///
+/// * `expr` - The struct expression to analyze
+/// * `used_variables` - Set of used variables to update
+fn collect_struct_expr_variables(
+    expr: &StructExpression,
+    used_variables: &mut std::collections::HashSet<String>
+) {
+    match expr {
+        StructExpression::Variable(name) => {
+            used_variables.insert(name.clone());
+        },
+        StructExpression::Literal(_, fields) => {
+            for (_, value) in fields {
+                collect_typed_expr_variables(value, used_variables);
+            }
+        },
+        StructExpression::FunctionCall(_, args) => {
+            collect_args_variables(args, used_variables);
+        },
+        StructExpression::Field(access) => {
+            used_variables.insert(access.variable.clone());
+        },
+    }
+}
+
+/// Helper function to collect all variable references in an enum expression.
+///
+/// # Arguments
+///
+/// * `expr` - The enum expression to analyze
+/// * `used_variables` - Set of used variables to update
+fn collect_enum_expr_variables(
+    expr: &EnumExpression,
+    used_variables: &mut std::collections::HashSet<String>
+) {
+    match expr {
+        EnumExpression::Variable(name) => {
+            used_variables.insert(name.clone());
+        },
+        EnumExpression::Variant(_, _, args) | EnumExpression::FunctionCall(_, args) => {
+            collect_args_variables(args, used_variables);
+        },
+        EnumExpression::Field(access) => {
+            used_variables.insert(access.variable.clone());
+        },
+    }
+}
+
+/// Helper function to collect all variable references in a map lookup.
+///
+/// # Arguments
+///
+/// * `value` - The map lookup to analyze
+/// * `used_variables` - Set of used variables to update
+fn collect_map_value_variables(
+    value: &MapValue,
+    used_variables: &mut std::collections::HashSet<String>
+) {
+    used_variables.insert(value.map.clone());
+    collect_expr_variables(&value.key, used_variables);
+}
+
+/// Helper function to collect all variable references in a list of typed expressions,
+/// such as the arguments of a function call or the operands of a concatenation.
+///
+/// # Arguments
+///
+/// * `args` - The expressions to analyze
+/// * `used_variables` - Set of used variables to update
+fn collect_args_variables(
+    args: &[TypedExpression],
+    used_variables: &mut std::collections::HashSet<String>
+) {
+    for arg in args {
+        collect_typed_expr_variables(arg, used_variables);
+    }
 }
 
 /// Helper function to collect all variable references in a numerical expression.
@@ -266,12 +1954,183 @@ fn collect_num_expr_variables(
             collect_num_expr_variables(left, used_variables);
             collect_num_expr_variables(right, used_variables);
         },
-        NumExpression::Grouping(inner) => {
-            // Recursively check inside parenthesis groups
+        NumExpression::Grouping(inner) | NumExpression::Negate(inner) => {
+            // Recursively check inside parenthesis groups and negations
             collect_num_expr_variables(inner, used_variables);
         },
+        NumExpression::FunctionCall(_, args) => {
+            collect_args_variables(args, used_variables);
+        },
+        NumExpression::MathCall(_, args) => {
+            for arg in args {
+                collect_num_expr_variables(arg, used_variables);
+            }
+        },
+        NumExpression::FromInt(inner) => {
+            collect_int_expr_variables(inner, used_variables);
+        },
+        NumExpression::ListElement(element) => {
+            collect_list_element_variables(element, used_variables);
+        },
+        NumExpression::MapValue(value) => {
+            collect_map_value_variables(value, used_variables);
+        },
+        NumExpression::Field(access) => {
+            used_variables.insert(access.variable.clone());
+        },
+        NumExpression::InputNum(prompt, _) => {
+            if let Some(prompt) = prompt {
+                collect_expr_variables(prompt, used_variables);
+            }
+        },
+        NumExpression::ToNum(text, _) => {
+            collect_expr_variables(text, used_variables);
+        },
         NumExpression::NumberLiteral(_) => {
             // Literals don't reference variables
         },
     }
 }
+
+/// Helper function to collect all variable references in an integer expression.
+///
+/// # Arguments
+///
+/// * `expr` - The integer expression to analyze
+/// * `used_variables` - Set of used variables to update
+fn collect_int_expr_variables(
+    expr: &IntExpression,
+    used_variables: &mut std::collections::HashSet<String>
+) {
+    match expr {
+        IntExpression::Variable(name) => {
+            used_variables.insert(name.clone());
+        },
+        IntExpression::BinaryOp(left, _, right, _) => {
+            collect_int_expr_variables(left, used_variables);
+            collect_int_expr_variables(right, used_variables);
+        },
+        IntExpression::Grouping(inner) | IntExpression::Negate(inner, _) => {
+            collect_int_expr_variables(inner, used_variables);
+        },
+        IntExpression::FunctionCall(_, args) | IntExpression::StringCall(_, args, _) => {
+            collect_args_variables(args, used_variables);
+        },
+        IntExpression::FromNum(inner, _) => {
+            collect_num_expr_variables(inner, used_variables);
+        },
+        IntExpression::ListElement(element) => {
+            collect_list_element_variables(element, used_variables);
+        },
+        IntExpression::MapValue(value) => {
+            collect_map_value_variables(value, used_variables);
+        },
+        IntExpression::Field(access) => {
+            used_variables.insert(access.variable.clone());
+        },
+        IntExpression::ListLength(expr) => {
+            collect_list_expr_variables(expr, used_variables);
+        },
+        IntExpression::MapLength(expr) => {
+            collect_map_expr_variables(expr, used_variables);
+        },
+        IntExpression::IntegerLiteral(_) | IntExpression::ArgCount => {},
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn int(value: i64) -> Box<IntExpression> {
+        Box::new(IntExpression::IntegerLiteral(value))
+    }
+
+    fn int_op(left: i64, op: BinaryOperator, right: i64) -> IntExpression {
+        IntExpression::BinaryOp(int(left), op, int(right), 1)
+    }
+
+    /// Parses and analyzes a program, returning the analysis result.
+    fn analyze_source(source: &str) -> Result<(Vec<Statement>, Vec<String>), String> {
+        let tokens = crate::lexer::tokenize(source, "test.vl").unwrap();
+        let ast = crate::parser::parse(tokens, source, "test.vl").unwrap();
+        analyze(ast, source, "test.vl")
+    }
+
+    #[test]
+    fn non_exhaustive_matches() {
+        let error = analyze_source("enum C { A, B }\nC c = C.A\nmatch c {\n    A => print(\"a\")\n}\n").err().unwrap();
+        assert!(error.contains("test.vl:3:1") && error.contains("variant 'B' of 'C' is not handled"));
+        let error = analyze_source("int n = 1\nmatch n {\n    1 => print(\"one\")\n}\n").err().unwrap();
+        assert!(error.contains("Non-exhaustive match: not every integer is handled"));
+        analyze_source("bool b = true\nmatch b {\n    true => print(\"yes\")\n    false => print(\"no\")\n}\n").unwrap();
+    }
+
+    #[test]
+    fn unreachable_match_arms() {
+        let (_, warnings) = analyze_source("int n = 1\nmatch n {\n    _ => print(\"any\")\n    1 => print(\"one\")\n}\n").unwrap();
+        assert_eq!(
+            warnings,
+            ["Unreachable match arm at line 4, column 5: the '_' arm at line 3 already handles every value"]
+        );
+        let source = "enum C { A, B }\nC c = C.A\nmatch c {\n    A => print(\"a\")\n    B => print(\"b\")\n    A => print(\"again\")\n}\n";
+        let (_, warnings) = analyze_source(source).unwrap();
+        assert_eq!(warnings, ["Unreachable match arm at line 6, column 5: the arms above already handle every value"]);
+    }
+
+    #[test]
+    fn integer_constants_are_exact_and_checked() {
+        let constants = HashMap::new();
+        assert_eq!(evaluate_int(&int_op(7, BinaryOperator::Multiply, 6), &constants), Ok(42));
+        assert_eq!(evaluate_int(&int_op(i64::MIN, BinaryOperator::Modulo, -1), &constants), Ok(0));
+        let error = |expr: IntExpression| evaluate_int(&expr, &constants).unwrap_err().0;
+        assert_eq!(error(int_op(i64::MAX, BinaryOperator::Add, 1)), "integer overflow");
+        assert_eq!(error(IntExpression::Negate(int(i64::MIN), 1)), "integer overflow");
+        assert_eq!(error(int_op(1, BinaryOperator::Divide, 0)), "division by zero");
+        assert_eq!(error(int_op(2, BinaryOperator::Power, -1)), "negative exponent in integer power");
+    }
+
+    #[test]
+    fn constants_refer_to_earlier_constants() {
+        let constants = HashMap::from([("N".to_string(), ConstValue::Int(10))]);
+        let as_num = NumExpression::FromInt(Box::new(IntExpression::Variable("N".to_string())));
+        let scaled = NumExpression::BinaryOp(Box::new(as_num), BinaryOperator::Multiply, Box::new(NumExpression::NumberLiteral(2.5)));
+        assert_eq!(evaluate_num(&scaled, &constants), Ok(25.0));
+
+        let label = Expression::Concat(vec![
+            TypedExpression::Str(Expression::StringLiteral("n=".to_string())),
+            TypedExpression::Int(IntExpression::Variable("N".to_string())),
+        ]);
+        assert_eq!(evaluate_str(&label, &constants), Ok("n=10".to_string()));
+        assert!(evaluate_int(&IntExpression::Variable("x".to_string()), &constants).unwrap_err().0.contains("is a variable"));
+    }
+
+    #[test]
+    fn numbers_format_like_printf_g() {
+        assert_eq!(format_number(0.1 + 0.2), "0.3");
+        assert_eq!(format_number(2.5), "2.5");
+        assert_eq!(format_number(1e20), "1e+20");
+    }
+
+    #[test]
+    fn folding_replaces_constants_with_literals() {
+        let source = "const N = 10\nconst HALF = N / 4.0\n";
+        let tokens = crate::lexer::tokenize(source, "test.vl").unwrap();
+        let ast = crate::parser::parse(tokens, source, "test.vl").unwrap();
+        let folded = fold_constants(ast, source, "test.vl").unwrap();
+        assert!(matches!(
+            folded.as_slice(),
+            [
+                Statement::ConstDeclaration(_, TypedExpression::Int(IntExpression::IntegerLiteral(10)), _, _),
+                Statement::ConstDeclaration(_, TypedExpression::Num(NumExpression::NumberLiteral(value)), 2, 7),
+            ] if *value == 2.5
+        ));
+
+        let source = "const BAD = 1 / 0\n";
+        let tokens = crate::lexer::tokenize(source, "test.vl").unwrap();
+        let ast = crate::parser::parse(tokens, source, "test.vl").unwrap();
+        let error = fold_constants(ast, source, "test.vl").err().unwrap();
+        assert!(error.contains("test.vl:1:7") && error.contains("Cannot compute constant 'BAD': division by zero"));
+    }
+}
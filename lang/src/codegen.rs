@@ -7,14 +7,682 @@
 // language construct, handling variable declarations, assignments, expressions,
 // and statements according to the language semantics.
 
-use crate::ast::{BinaryOperator, Expression, NumExpression, Statement, FormatPart};
-use std::collections::HashSet;
+use crate::ast::{Alignment, BinaryOperator, ComparisonOperator, Condition, Expression, IntExpression, ListElement, ListExpression, MapExpression, MapValue, MatchArm, MathFunction, Pattern, StringFunction, StructExpression, EnumExpression, FieldAccess, NumExpression, Statement, FormatPart, FormatSpec, NumberStyle, Type, TypedExpression};
+use std::collections::HashMap;
 use std::fmt::Write;
 
+/// C helpers for integer arithmetic, emitted at the top of every program.
+///
+/// Overflow wraps around by default. When the program is compiled in checked
+/// mode (`VORTLANG_CHECKED` defined as 1), overflow and out-of-range
+/// conversions are runtime errors instead. Division by zero is always an error.
+const INT_RUNTIME: &str = r#"#ifndef VORTLANG_CHECKED
+#define VORTLANG_CHECKED 0
+#endif
+
+static void __int_error(const char* message, int line) {
+    fprintf(stderr, "Error at line %d: %s\n", line, message);
+    exit(1);
+}
+
+static long long __int_add(long long a, long long b, int line) {
+    long long result;
+    if (__builtin_add_overflow(a, b, &result) && VORTLANG_CHECKED) __int_error("integer overflow", line);
+    return result;
+}
+
+static long long __int_sub(long long a, long long b, int line) {
+    long long result;
+    if (__builtin_sub_overflow(a, b, &result) && VORTLANG_CHECKED) __int_error("integer overflow", line);
+    return result;
+}
+
+static long long __int_mul(long long a, long long b, int line) {
+    long long result;
+    if (__builtin_mul_overflow(a, b, &result) && VORTLANG_CHECKED) __int_error("integer overflow", line);
+    return result;
+}
+
+static long long __int_div(long long a, long long b, int line) {
+    if (b == 0) __int_error("integer division by zero", line);
+    if (a == LLONG_MIN && b == -1) {
+        if (VORTLANG_CHECKED) __int_error("integer overflow", line);
+        return LLONG_MIN;
+    }
+    return a / b;
+}
+
+static long long __int_mod(long long a, long long b, int line) {
+    if (b == 0) __int_error("integer modulo by zero", line);
+    if (b == -1) return 0;
+    return a % b;
+}
+
+static long long __int_pow(long long base, long long exponent, int line) {
+    if (exponent < 0) __int_error("negative exponent in integer power", line);
+    long long result = 1;
+    bool overflow = false;
+    while (exponent > 0) {
+        if (exponent & 1) overflow |= __builtin_mul_overflow(result, base, &result);
+        exponent >>= 1;
+        if (exponent > 0) overflow |= __builtin_mul_overflow(base, base, &base);
+    }
+    if (overflow && VORTLANG_CHECKED) __int_error("integer overflow", line);
+    return result;
+}
+
+static long long __int_neg(long long a, int line) {
+    long long result;
+    if (__builtin_sub_overflow(0LL, a, &result) && VORTLANG_CHECKED) __int_error("integer overflow", line);
+    return result;
+}
+
+static long long __int_from_num(double value, int line) {
+    if (isnan(value) || value >= 9223372036854775808.0 || value < -9223372036854775808.0) {
+        if (VORTLANG_CHECKED) __int_error("number out of range for int", line);
+        if (isnan(value)) return 0;
+        return value > 0 ? LLONG_MAX : LLONG_MIN;
+    }
+    return (long long)value;
+}
+
+"#;
+
+/// C helpers for strings, emitted at the top of every program.
+///
+/// Strings are immutable: values built at runtime (such as concatenations) are
+/// allocated on the heap and can be shared freely between variables, so they
+/// are never freed.
+const STRING_RUNTIME: &str = r#"static char* __str_format(const char* format, ...) {
+    va_list args;
+    va_start(args, format);
+    int length = vsnprintf(NULL, 0, format, args);
+    va_end(args);
+    char* result = malloc(length + 1);
+    if (result == NULL) {
+        fprintf(stderr, "Error: out of memory\n");
+        exit(1);
+    }
+    va_start(args, format);
+    vsnprintf(result, length + 1, format, args);
+    va_end(args);
+    return result;
+}
+
+static char* __str_center(char* text, int width) {
+    // Takes ownership of a temporary string, which is freed once padded
+    int length = (int) strlen(text);
+    int padding = width > length ? width - length : 0;
+    char* result = __str_format("%*s%s%*s", padding / 2, "", text, padding - padding / 2, "");
+    free(text);
+    return result;
+}
+
+static void __print_centered(char* text, int width) {
+    char* centered = __str_center(text, width);
+    printf("%s", centered);
+    free(centered);
+}
+
+static bool __str_parse_num(const char* text, double* value) {
+    // The whole text must be a finite number, optionally surrounded by whitespace
+    char* end;
+    *value = strtod(text, &end);
+    bool parsed = end != text && isfinite(*value);
+    end += strspn(end, " \t\r\n");
+    return parsed && *end == '\0';
+}
+
+static double __str_to_num(const char* text, int line) {
+    double value;
+    if (!__str_parse_num(text, &value)) {
+        fprintf(stderr, "Error at line %d: cannot convert '%s' to a number\n", line, text);
+        exit(1);
+    }
+    return value;
+}
+
+"#;
+
+/// C helpers for reading standard input, emitted at the top of every program.
+///
+/// Each call reads one whole line into a new heap-allocated string. Reaching
+/// the end of the input before anything could be read is a runtime error, so
+/// programs never loop on an exhausted stdin.
+const INPUT_RUNTIME: &str = r#"static char* __input(const char* prompt, int line) {
+    if (prompt != NULL) {
+        fputs(prompt, stdout);
+    }
+    fflush(stdout);
+    size_t capacity = 64;
+    size_t length = 0;
+    char* result = malloc(capacity);
+    if (result == NULL) {
+        fprintf(stderr, "Error: out of memory\n");
+        exit(1);
+    }
+    int c;
+    while ((c = getchar()) != EOF && c != '\n') {
+        if (length + 1 == capacity) {
+            capacity *= 2;
+            result = realloc(result, capacity);
+            if (result == NULL) {
+                fprintf(stderr, "Error: out of memory\n");
+                exit(1);
+            }
+        }
+        result[length++] = (char) c;
+    }
+    if (c == EOF && length == 0) {
+        fprintf(stderr, "Error at line %d: end of input while reading a line\n", line);
+        exit(1);
+    }
+    // Lines written on Windows end with "\r\n"
+    if (length > 0 && result[length - 1] == '\r') {
+        length--;
+    }
+    result[length] = '\0';
+    return result;
+}
+
+static double __input_num(const char* prompt, int line) {
+    while (true) {
+        char* text = __input(prompt, line);
+        double value;
+        if (__str_parse_num(text, &value)) {
+            free(text);
+            return value;
+        }
+        fprintf(stderr, "'%s' is not a number, please try again\n", text);
+        free(text);
+    }
+}
+
+"#;
+
+/// C helpers for the file built-ins, emitted at the top of every program.
+///
+/// Any failure stops the program with an error naming the file and the reason
+/// given by the operating system.
+const FILE_RUNTIME: &str = r#"static void __file_error(const char* action, const char* path, int line) {
+    fprintf(stderr, "Error at line %d: cannot %s file '%s': %s\n", line, action, path, strerror(errno));
+    exit(1);
+}
+
+static char* __file_read(const char* path, int line) {
+    FILE* file = fopen(path, "rb");
+    if (file == NULL) __file_error("read", path, line);
+    size_t capacity = 4096;
+    size_t length = 0;
+    char* result = malloc(capacity);
+    if (result == NULL) {
+        fprintf(stderr, "Error: out of memory\n");
+        exit(1);
+    }
+    size_t count;
+    while ((count = fread(result + length, 1, capacity - length - 1, file)) > 0) {
+        length += count;
+        if (length + 1 == capacity) {
+            capacity *= 2;
+            result = realloc(result, capacity);
+            if (result == NULL) {
+                fprintf(stderr, "Error: out of memory\n");
+                exit(1);
+            }
+        }
+    }
+    if (ferror(file)) __file_error("read", path, line);
+    fclose(file);
+    result[length] = '\0';
+    return result;
+}
+
+static void __file_write(const char* path, const char* text, bool append, int line) {
+    const char* action = append ? "append to" : "write";
+    FILE* file = fopen(path, append ? "ab" : "wb");
+    if (file == NULL) __file_error(action, path, line);
+    size_t length = strlen(text);
+    if (fwrite(text, 1, length, file) != length || fclose(file) != 0) __file_error(action, path, line);
+}
+
+static bool __file_exists(const char* path) {
+    struct stat info;
+    return stat(path, &info) == 0;
+}
+
+static void __file_delete(const char* path, int line) {
+    if (remove(path) != 0) __file_error("delete", path, line);
+}
+
+"#;
+
+/// C helpers for command-line arguments and environment variables, emitted
+/// at the top of every program. `main` stores its arguments here so that
+/// functions can read them too.
+const ARGS_RUNTIME: &str = r#"static int __args_count = 0;
+static char** __args = NULL;
+
+static char* __arg(long long index, int line) {
+    if (index < 0 || index >= __args_count) {
+        fprintf(stderr, "Error at line %d: argument index %lld out of bounds for %d argument(s)\n", line, index, __args_count);
+        exit(1);
+    }
+    return __args[index];
+}
+
+static char* __env(const char* name, char* fallback, int line) {
+    char* value = getenv(name);
+    if (value != NULL) return value;
+    if (fallback == NULL) {
+        fprintf(stderr, "Error at line %d: environment variable '%s' is not set\n", line, name);
+        exit(1);
+    }
+    return fallback;
+}
+
+"#;
+
+/// C helpers for lists, emitted at the top of every program.
+///
+/// A list stores its elements in a growable array of unions, so the same
+/// helpers serve every element type; the kind of the elements is only needed
+/// to convert the list to text. Lists are shared by reference and, like
+/// strings, never freed.
+const LIST_RUNTIME: &str = r#"typedef union {
+    double num;
+    long long integer;
+    char* str;
+    bool boolean;
+} __item;
+
+typedef struct {
+    char kind; // 'n' (num), 'i' (int), 's' (str) or 'b' (bool)
+    long long length;
+    long long capacity;
+    __item* items;
+} __list;
+
+static void __list_reserve(__list* list, long long capacity) {
+    if (capacity <= list->capacity) return;
+    long long new_capacity = list->capacity > 0 ? list->capacity * 2 : 4;
+    if (new_capacity < capacity) new_capacity = capacity;
+    list->items = realloc(list->items, new_capacity * sizeof(__item));
+    if (list->items == NULL) {
+        fprintf(stderr, "Error: out of memory\n");
+        exit(1);
+    }
+    list->capacity = new_capacity;
+}
+
+static __list* __list_new(char kind, int count, ...) {
+    __list* list = calloc(1, sizeof(__list));
+    if (list == NULL) {
+        fprintf(stderr, "Error: out of memory\n");
+        exit(1);
+    }
+    list->kind = kind;
+    __list_reserve(list, count);
+    va_list args;
+    va_start(args, count);
+    for (int i = 0; i < count; i++) list->items[i] = va_arg(args, __item);
+    va_end(args);
+    list->length = count;
+    return list;
+}
+
+static long long __list_check(__list* list, long long index, int line) {
+    if (index < 0 || index >= list->length) {
+        fprintf(stderr, "Error at line %d: list index %lld out of bounds for length %lld\n", line, index, list->length);
+        exit(1);
+    }
+    return index;
+}
+
+static __item __list_get(__list* list, long long index, int line) {
+    return list->items[__list_check(list, index, line)];
+}
+
+static void __list_set(__list* list, long long index, __item value, int line) {
+    list->items[__list_check(list, index, line)] = value;
+}
+
+static void __list_push(__list* list, __item value) {
+    __list_reserve(list, list->length + 1);
+    list->items[list->length++] = value;
+}
+
+static __item __list_pop(__list* list, int line) {
+    if (list->length == 0) {
+        fprintf(stderr, "Error at line %d: pop from an empty list\n", line);
+        exit(1);
+    }
+    return list->items[--list->length];
+}
+
+static char* __item_to_str(char kind, __item item) {
+    switch (kind) {
+        case 'n': return __str_format("%g", item.num);
+        case 'i': return __str_format("%lld", item.integer);
+        case 'b': return __str_format("%s", item.boolean ? "true" : "false");
+        default: return __str_format("\"%s\"", item.str);
+    }
+}
+
+static char* __list_to_str(__list* list) {
+    char* result = __str_format("[");
+    for (long long i = 0; i < list->length; i++) {
+        char* element = __item_to_str(list->kind, list->items[i]);
+        char* joined = __str_format("%s%s%s", result, i > 0 ? ", " : "", element);
+        free(result);
+        free(element);
+        result = joined;
+    }
+    char* closed = __str_format("%s]", result);
+    free(result);
+    return closed;
+}
+
+"#;
+
+/// C helpers for maps, emitted after the list helpers they build on.
+///
+/// A map keeps its keys and values in insertion order, which is the order they
+/// are printed and listed in, and finds them through an open-addressing hash
+/// table of indices into those arrays. Like lists, maps are shared by
+/// reference and never freed.
+const MAP_RUNTIME: &str = r#"typedef struct {
+    char kind; // Kind of the values, as for lists
+    long long length;
+    long long capacity;
+    char** keys;
+    __item* values;
+    long long* slots; // Index of the entry in each slot, or -1 when empty
+    long long slot_count; // Always a power of two
+} __map;
+
+static unsigned long long __map_hash(const char* key) {
+    unsigned long long hash = 14695981039346656037ULL;
+    for (; *key; key++) {
+        hash ^= (unsigned char) *key;
+        hash *= 1099511628211ULL;
+    }
+    return hash;
+}
+
+static long long __map_find(__map* map, const char* key) {
+    unsigned long long mask = map->slot_count - 1;
+    unsigned long long slot = __map_hash(key) & mask;
+    while (map->slots[slot] != -1 && strcmp(map->keys[map->slots[slot]], key) != 0) {
+        slot = (slot + 1) & mask;
+    }
+    return slot;
+}
+
+static void __map_rehash(__map* map, long long slot_count) {
+    free(map->slots);
+    map->slots = malloc(slot_count * sizeof(long long));
+    if (map->slots == NULL) {
+        fprintf(stderr, "Error: out of memory\n");
+        exit(1);
+    }
+    map->slot_count = slot_count;
+    for (long long i = 0; i < slot_count; i++) map->slots[i] = -1;
+    for (long long i = 0; i < map->length; i++) map->slots[__map_find(map, map->keys[i])] = i;
+}
+
+static void __map_set(__map* map, char* key, __item value) {
+    long long slot = __map_find(map, key);
+    if (map->slots[slot] != -1) {
+        map->values[map->slots[slot]] = value;
+        return;
+    }
+    if (map->length == map->capacity) {
+        map->capacity = map->capacity > 0 ? map->capacity * 2 : 4;
+        map->keys = realloc(map->keys, map->capacity * sizeof(char*));
+        map->values = realloc(map->values, map->capacity * sizeof(__item));
+        if (map->keys == NULL || map->values == NULL) {
+            fprintf(stderr, "Error: out of memory\n");
+            exit(1);
+        }
+    }
+    map->keys[map->length] = key;
+    map->values[map->length] = value;
+    map->slots[slot] = map->length++;
+    // Keep the table at most half full so that probing stays short
+    if (map->length * 2 > map->slot_count) __map_rehash(map, map->slot_count * 2);
+}
+
+static __map* __map_new(char kind, int count, ...) {
+    __map* map = calloc(1, sizeof(__map));
+    if (map == NULL) {
+        fprintf(stderr, "Error: out of memory\n");
+        exit(1);
+    }
+    map->kind = kind;
+    __map_rehash(map, 8);
+    va_list args;
+    va_start(args, count);
+    for (int i = 0; i < count; i++) {
+        char* key = va_arg(args, char*);
+        __map_set(map, key, va_arg(args, __item));
+    }
+    va_end(args);
+    return map;
+}
+
+static bool __map_has(__map* map, const char* key) {
+    return map->slots[__map_find(map, key)] != -1;
+}
+
+static long long __map_index(__map* map, const char* key, int line) {
+    long long index = map->slots[__map_find(map, key)];
+    if (index == -1) {
+        fprintf(stderr, "Error at line %d: key \"%s\" not found in map\n", line, key);
+        exit(1);
+    }
+    return index;
+}
+
+static __item __map_get(__map* map, const char* key, int line) {
+    return map->values[__map_index(map, key, line)];
+}
+
+static void __map_remove(__map* map, const char* key, int line) {
+    long long index = __map_index(map, key, line);
+    memmove(&map->keys[index], &map->keys[index + 1], (map->length - index - 1) * sizeof(char*));
+    memmove(&map->values[index], &map->values[index + 1], (map->length - index - 1) * sizeof(__item));
+    map->length--;
+    __map_rehash(map, map->slot_count);
+}
+
+static __list* __map_keys(__map* map) {
+    __list* keys = __list_new('s', 0);
+    for (long long i = 0; i < map->length; i++) __list_push(keys, (__item){.str = map->keys[i]});
+    return keys;
+}
+
+static char* __map_to_str(__map* map) {
+    char* result = __str_format("{");
+    for (long long i = 0; i < map->length; i++) {
+        char* value = __item_to_str(map->kind, map->values[i]);
+        char* joined = __str_format("%s%s\"%s\": %s", result, i > 0 ? ", " : "", map->keys[i], value);
+        free(result);
+        free(value);
+        result = joined;
+    }
+    char* closed = __str_format("%s}", result);
+    free(result);
+    return closed;
+}
+
+"#;
+
+/// C helpers for the string built-ins, emitted after the list helpers used by
+/// `split` and `join`.
+///
+/// Strings are stored as UTF-8, so lengths and positions count the bytes that
+/// start a character and skip continuation bytes (those of the form 10xxxxxx).
+/// Every function returning a string allocates a new one.
+const STRING_LIBRARY_RUNTIME: &str = r#"static char* __str_alloc(size_t length) {
+    char* result = malloc(length + 1);
+    if (result == NULL) {
+        fprintf(stderr, "Error: out of memory\n");
+        exit(1);
+    }
+    result[length] = '\0';
+    return result;
+}
+
+static char* __str_copy(const char* start, size_t length) {
+    char* result = __str_alloc(length);
+    memcpy(result, start, length);
+    return result;
+}
+
+static bool __str_is_char_start(char c) {
+    return ((unsigned char) c & 0xC0) != 0x80;
+}
+
+static long long __str_length(const char* text) {
+    long long length = 0;
+    for (; *text != '\0'; text++) {
+        if (__str_is_char_start(*text)) length++;
+    }
+    return length;
+}
+
+static const char* __str_at(const char* text, long long index) {
+    // Skips the given number of characters, stopping at the end of the text
+    for (; *text != '\0'; text++) {
+        if (__str_is_char_start(*text) && index-- == 0) break;
+    }
+    return text;
+}
+
+static char* __str_upper(const char* text) {
+    char* result = __str_copy(text, strlen(text));
+    for (char* c = result; *c != '\0'; c++) {
+        if (*c >= 'a' && *c <= 'z') *c -= 'a' - 'A';
+    }
+    return result;
+}
+
+static char* __str_lower(const char* text) {
+    char* result = __str_copy(text, strlen(text));
+    for (char* c = result; *c != '\0'; c++) {
+        if (*c >= 'A' && *c <= 'Z') *c += 'a' - 'A';
+    }
+    return result;
+}
+
+static char* __str_trim(const char* text) {
+    const char* whitespace = " \t\r\n\v\f";
+    text += strspn(text, whitespace);
+    size_t length = strlen(text);
+    while (length > 0 && strchr(whitespace, text[length - 1]) != NULL) length--;
+    return __str_copy(text, length);
+}
+
+static bool __str_contains(const char* text, const char* part) {
+    return strstr(text, part) != NULL;
+}
+
+static bool __str_starts_with(const char* text, const char* prefix) {
+    return strncmp(text, prefix, strlen(prefix)) == 0;
+}
+
+static bool __str_ends_with(const char* text, const char* suffix) {
+    size_t text_length = strlen(text);
+    size_t suffix_length = strlen(suffix);
+    return suffix_length <= text_length && strcmp(text + text_length - suffix_length, suffix) == 0;
+}
+
+static long long __str_index_of(const char* text, const char* part) {
+    const char* found = strstr(text, part);
+    if (found == NULL) return -1;
+    long long index = 0;
+    for (; text < found; text++) {
+        if (__str_is_char_start(*text)) index++;
+    }
+    return index;
+}
+
+static char* __str_substr(const char* text, long long start, long long count, int line) {
+    long long length = __str_length(text);
+    if (start < 0 || count < 0 || start > length || count > length - start) {
+        fprintf(stderr, "Error at line %d: substring from %lld with length %lld out of bounds for length %lld\n", line, start, count, length);
+        exit(1);
+    }
+    const char* begin = __str_at(text, start);
+    const char* end = __str_at(begin, count);
+    return __str_copy(begin, end - begin);
+}
+
+static char* __str_replace(const char* text, const char* from, const char* to, int line) {
+    size_t from_length = strlen(from);
+    if (from_length == 0) {
+        fprintf(stderr, "Error at line %d: cannot replace an empty string\n", line);
+        exit(1);
+    }
+    size_t to_length = strlen(to);
+    size_t length = strlen(text);
+    for (const char* found = strstr(text, from); found != NULL; found = strstr(found + from_length, from)) {
+        length = length - from_length + to_length;
+    }
+    char* result = __str_alloc(length);
+    char* out = result;
+    for (const char* found = strstr(text, from); found != NULL; found = strstr(text, from)) {
+        memcpy(out, text, found - text);
+        out += found - text;
+        memcpy(out, to, to_length);
+        out += to_length;
+        text = found + from_length;
+    }
+    strcpy(out, text);
+    return result;
+}
+
+static __list* __str_split(const char* text, const char* separator, int line) {
+    size_t separator_length = strlen(separator);
+    if (separator_length == 0) {
+        fprintf(stderr, "Error at line %d: cannot split with an empty separator\n", line);
+        exit(1);
+    }
+    __list* parts = __list_new('s', 0);
+    for (const char* found = strstr(text, separator); found != NULL; found = strstr(text, separator)) {
+        __list_push(parts, (__item){.str = __str_copy(text, found - text)});
+        text = found + separator_length;
+    }
+    __list_push(parts, (__item){.str = __str_copy(text, strlen(text))});
+    return parts;
+}
+
+static char* __str_join(__list* list, const char* separator) {
+    size_t separator_length = strlen(separator);
+    size_t length = 0;
+    for (long long i = 0; i < list->length; i++) {
+        length += strlen(list->items[i].str) + (i > 0 ? separator_length : 0);
+    }
+    char* result = __str_alloc(length);
+    char* out = result;
+    for (long long i = 0; i < list->length; i++) {
+        if (i > 0) {
+            memcpy(out, separator, separator_length);
+            out += separator_length;
+        }
+        size_t part_length = strlen(list->items[i].str);
+        memcpy(out, list->items[i].str, part_length);
+        out += part_length;
+    }
+    return result;
+}
+
+"#;
+
 /// Enum to differentiate between regular and C code functions during code generation
 #[derive(Clone)]
 enum FunctionType {
-    Regular(Vec<Statement>),
+    Regular(Vec<(String, Type)>, Option<Type>, Vec<Statement>),
     CCode(String),
 }
 
@@ -38,23 +706,57 @@ pub fn generate_c_code(ast: &[Statement]) -> Result<String, String> {
     // Add standard includes
     write!(
         code,
-        "#include <stdio.h>\n#include <stdlib.h>\n#include <string.h>\n#include <math.h>\n\n"
+        "#include <stdio.h>\n#include <stdlib.h>\n#include <string.h>\n#include <math.h>\n#include <stdbool.h>\n#include <limits.h>\n#include <stdarg.h>\n#include <errno.h>\n#include <sys/stat.h>\n\n"
     ).unwrap();
+    code.push_str(INT_RUNTIME);
+    code.push_str(STRING_RUNTIME);
+    code.push_str(INPUT_RUNTIME);
+    code.push_str(FILE_RUNTIME);
+    code.push_str(ARGS_RUNTIME);
+    code.push_str(LIST_RUNTIME);
+    code.push_str(MAP_RUNTIME);
+    code.push_str(STRING_LIBRARY_RUNTIME);
 
     // Collect variables and functions from the AST
-    let mut str_variables = HashSet::new();
-    let mut num_variables = HashSet::new();
-    collect_variables(ast, &mut str_variables, &mut num_variables);
+    let mut variables = HashMap::new();
+    collect_variables(ast, &mut variables);
 
     let mut functions = Vec::new();
+    let mut constants = Vec::new();
+    let mut return_types = HashMap::new();
     let mut main_statements = Vec::new();
     for stmt in ast {
         match stmt {
-            Statement::FunctionDefinition(name, body) => {
-                functions.push((name.clone(), FunctionType::Regular(body.clone())));
+            Statement::StructDefinition(name, fields) => {
+                // Structs and enums are defined in source order, so a type
+                // holding another one always comes after it
+                code.push_str(&generate_struct_definition(name, fields));
+            }
+            Statement::EnumDefinition(name, variants) => {
+                code.push_str(&generate_enum_definition(name, variants));
+            }
+            Statement::ConstDeclaration(name, value, _, _) => {
+                // The analysis pass has already replaced the value with a literal
+                let const_type = match value {
+                    TypedExpression::Str(_) => Type::Str,
+                    TypedExpression::Int(_) => Type::Int,
+                    _ => Type::Num,
+                };
+                code.push_str(&format!(
+                    "static {} const {} = {};\n",
+                    c_type(&const_type),
+                    name,
+                    generate_typed_expression(value, &variables)?
+                ));
+                constants.push((name.clone(), const_type));
+            }
+            Statement::FunctionDefinition(name, params, return_type, body) => {
+                functions.push((name.clone(), FunctionType::Regular(params.clone(), return_type.clone(), body.clone())));
+                return_types.insert(name.clone(), return_type.clone());
             }
             Statement::CFunctionDefinition(name, c_code) => {
                 functions.push((name.clone(), FunctionType::CCode(c_code.clone())));
+                return_types.insert(name.clone(), None);
             }
             _ => {
                 main_statements.push(stmt.clone());
@@ -63,42 +765,56 @@ pub fn generate_c_code(ast: &[Statement]) -> Result<String, String> {
     }
 
     // Generate global variable declarations
-    for var in &str_variables {
-        code.push_str(&format!("char* {};\n", var));
+    for (var, var_type) in &variables {
+        code.push_str(&format!("{} {};\n", c_type(var_type), var));
     }
-    for var in &num_variables {
-        code.push_str(&format!("double {};\n", var));
-    }
-    code.push_str("\n");
+    code.push('\n');
+    variables.extend(constants);
 
     // Generate function prototypes
     code.push_str("// Function prototypes\n");
-    for (name, _) in &functions {
-        code.push_str(&format!("void {}();\n", name));
+    for (name, func_type) in &functions {
+        code.push_str(&format!("{};\n", function_signature(name, func_type)));
     }
-    code.push_str("\n");
+    code.push('\n');
 
     // Generate function definitions
     for (name, func_type) in functions {
+        let signature = function_signature(&name, &func_type);
         match func_type {
-            FunctionType::Regular(body) => {
-                code.push_str(&format!("void {}(void) {{\n", name));
-                for stmt in body {
-                    let stmt_code = generate_statement(&stmt, &str_variables, &num_variables)?;
-                    code.push_str(&stmt_code);
+            FunctionType::Regular(params, return_type, body) => {
+                // Parameters shadow globals of the same name inside the body
+                let mut function_variables = variables.clone();
+                for (param, param_type) in params {
+                    function_variables.insert(param, param_type);
+                }
+
+                code.push_str(&format!("{} {{\n", signature));
+                code.push_str(&generate_scope(&body, &function_variables, &return_types)?);
+                if return_type.is_some() {
+                    // Falling off the end of a function that must return a value is a runtime error
+                    code.push_str(&format!(
+                        "    fprintf(stderr, \"Error: function '{}' ended without returning a value\\n\");\n",
+                        name
+                    ));
+                    code.push_str("    exit(1);\n");
                 }
                 code.push_str("}\n\n");
             }
             FunctionType::CCode(c_code) => {
-                code.push_str(&format!("void {}(void) {{ {} }}\n\n", name, c_code));
+                code.push_str(&format!("{} {{ {} }}\n\n", signature, c_code));
             }
         }
     }
 
     // Generate main function
-    code.push_str("int main() {\n");
+    // The parameters get reserved names, so that globals named 'argc' or 'argv'
+    // aren't shadowed inside main
+    code.push_str("int main(int __argc, char** __argv) {\n");
+    code.push_str("    __args_count = __argc;\n");
+    code.push_str("    __args = __argv;\n");
     for stmt in main_statements {
-        let stmt_code = generate_statement(&stmt, &str_variables, &num_variables)?;
+        let stmt_code = generate_statement(&stmt, &variables, &return_types)?;
         code.push_str(&stmt_code);
     }
     code.push_str("    return 0;\n");
@@ -108,31 +824,46 @@ pub fn generate_c_code(ast: &[Statement]) -> Result<String, String> {
 }
 
 
-/// Collects all variable declarations from the AST, including inside functions.
+/// Collects the global variable declarations from the AST.
 ///
-/// Since all variables are global, this traverses the entire AST to gather
-/// string and numerical variable names.
+/// Globals are the variables declared at the top level of the program; they
+/// are emitted as C globals so that functions can use them too. Variables
+/// declared inside functions and blocks are locals and are emitted where they
+/// are declared instead.
 ///
 /// # Arguments
 ///
-/// * `statements` - The list of statements to analyze
-/// * `str_vars` - Set to store string variable names
-/// * `num_vars` - Set to store numerical variable names
+/// * `statements` - The top-level statements of the program
+/// * `variables` - Map to store variable names and their types
 fn collect_variables(
     statements: &[Statement],
-    str_vars: &mut HashSet<String>,
-    num_vars: &mut HashSet<String>,
+    variables: &mut HashMap<String, Type>,
 ) {
     for stmt in statements {
         match stmt {
             Statement::VariableDeclaration(name, _, _) => {
-                str_vars.insert(name.clone());
+                variables.insert(name.clone(), Type::Str);
             }
             Statement::NumDeclaration(name, _, _) => {
-                num_vars.insert(name.clone());
+                variables.insert(name.clone(), Type::Num);
+            }
+            Statement::BoolDeclaration(name, _, _) => {
+                variables.insert(name.clone(), Type::Bool);
+            }
+            Statement::IntDeclaration(name, _, _) => {
+                variables.insert(name.clone(), Type::Int);
+            }
+            Statement::ListDeclaration(name, element_type, _, _) => {
+                variables.insert(name.clone(), Type::List(Box::new(element_type.clone())));
+            }
+            Statement::MapDeclaration(name, value_type, _, _) => {
+                variables.insert(name.clone(), Type::Map(Box::new(value_type.clone())));
+            }
+            Statement::StructDeclaration(name, struct_name, _, _) => {
+                variables.insert(name.clone(), Type::Struct(struct_name.clone()));
             }
-            Statement::FunctionDefinition(_, body) => {
-                collect_variables(body, str_vars, num_vars);
+            Statement::EnumDeclaration(name, enum_name, _, _) => {
+                variables.insert(name.clone(), Type::Enum(enum_name.clone()));
             }
             _ => {}
         }
@@ -144,8 +875,8 @@ fn collect_variables(
 /// # Arguments
 ///
 /// * `stmt` - The statement to generate code for
-/// * `str_vars` - Set of declared string variables
-/// * `num_vars` - Set of declared numerical variables
+/// * `variables` - Map of declared variables to their types
+/// * `functions` - Map of defined functions to their return types
 ///
 /// # Returns
 ///
@@ -154,8 +885,8 @@ fn collect_variables(
 /// * An error message if code generation fails
 fn generate_statement(
     stmt: &Statement,
-    str_vars: &HashSet<String>,
-    num_vars: &HashSet<String>,
+    variables: &HashMap<String, Type>,
+    functions: &HashMap<String, Option<Type>>,
 ) -> Result<String, String> {
     let mut code = String::new();
     match stmt {
@@ -164,20 +895,7 @@ fn generate_statement(
                         code.push_str("    ");
                         code.push_str(name);
                         code.push_str(" = ");
-                        match expr {
-                            Expression::StringLiteral(value) => {
-                                code.push_str("\"");
-                                code.push_str(&escape_string(value));
-                                code.push_str("\"");
-                            }
-                            Expression::Variable(var) => {
-                                if !str_vars.contains(var) {
-                                    return Err(format!("Variable '{}' used before declaration", var));
-                                }
-                                code.push_str(var);
-                            }
-                            _ => return Err("Invalid expression for variable declaration".to_string()),
-                        }
+                        code.push_str(&generate_expression(expr, variables)?);
                         code.push_str(";\n");
             }
         Statement::NumDeclaration(name, expr, _) => {
@@ -185,44 +903,141 @@ fn generate_statement(
                 code.push_str("    ");
                 code.push_str(name);
                 code.push_str(" = ");
-                let expr_code = generate_num_expression(expr, num_vars)?;
+                let expr_code = generate_num_expression(expr, variables)?;
                 code.push_str(&expr_code);
                 code.push_str(";\n");
             }
         Statement::VariableAssignment(name, expr, _) => {
-                if !str_vars.contains(name) {
+                if !is_variable_of_type(variables, name, Type::Str) {
                     return Err(format!("Variable '{}' assigned before declaration", name));
                 }
                 code.push_str("    ");
                 code.push_str(name);
                 code.push_str(" = ");
-                match expr {
-                    Expression::StringLiteral(value) => {
-                        code.push_str("\"");
-                        code.push_str(&escape_string(value));
-                        code.push_str("\"");
-                    }
-                    Expression::Variable(var) => {
-                        if !str_vars.contains(var) {
-                            return Err(format!("Variable '{}' used before declaration", var));
-                        }
-                        code.push_str(var);
-                    }
-                    _ => return Err("Invalid expression for variable assignment".to_string()),
-                }
+                code.push_str(&generate_expression(expr, variables)?);
                 code.push_str(";\n");
             }
         Statement::NumAssignment(name, expr, _) => {
-                if !num_vars.contains(name) {
+                if !is_variable_of_type(variables, name, Type::Num) {
                     return Err(format!("Numerical variable '{}' assigned before declaration", name));
                 }
                 code.push_str("    ");
                 code.push_str(name);
                 code.push_str(" = ");
-                let expr_code = generate_num_expression(expr, num_vars)?;
+                let expr_code = generate_num_expression(expr, variables)?;
                 code.push_str(&expr_code);
                 code.push_str(";\n");
             }
+        Statement::BoolDeclaration(name, condition, _) => {
+                // Treat as assignment since variable is declared globally
+                let condition_code = generate_condition(condition, variables)?;
+                code.push_str(&format!("    {} = {};\n", name, condition_code));
+            }
+        Statement::BoolAssignment(name, condition, _) => {
+                if !is_variable_of_type(variables, name, Type::Bool) {
+                    return Err(format!("Boolean variable '{}' assigned before declaration", name));
+                }
+                let condition_code = generate_condition(condition, variables)?;
+                code.push_str(&format!("    {} = {};\n", name, condition_code));
+            }
+        Statement::IntDeclaration(name, expr, _) => {
+                // Treat as assignment since variable is declared globally
+                let expr_code = generate_int_expression(expr, variables)?;
+                code.push_str(&format!("    {} = {};\n", name, expr_code));
+            }
+        Statement::IntAssignment(name, expr, _) => {
+                if !is_variable_of_type(variables, name, Type::Int) {
+                    return Err(format!("Integer variable '{}' assigned before declaration", name));
+                }
+                let expr_code = generate_int_expression(expr, variables)?;
+                code.push_str(&format!("    {} = {};\n", name, expr_code));
+            }
+        Statement::ListDeclaration(name, _, expr, _) => {
+                // Treat as assignment since variable is declared globally
+                let expr_code = generate_list_expression(expr, variables)?;
+                code.push_str(&format!("    {} = {};\n", name, expr_code));
+            }
+        Statement::ListAssignment(name, expr, _) => {
+                if !matches!(variables.get(name), Some(Type::List(_))) {
+                    return Err(format!("List variable '{}' assigned before declaration", name));
+                }
+                let expr_code = generate_list_expression(expr, variables)?;
+                code.push_str(&format!("    {} = {};\n", name, expr_code));
+            }
+        Statement::ListElementAssignment(name, index, value, line_number) => {
+                let index_code = generate_int_expression(index, variables)?;
+                let value_code = generate_item(value, variables)?;
+                code.push_str(&format!("    __list_set({}, {}, {}, {});\n", name, index_code, value_code, line_number));
+            }
+        Statement::ListPush(name, value) => {
+                let value_code = generate_item(value, variables)?;
+                code.push_str(&format!("    __list_push({}, {});\n", name, value_code));
+            }
+        Statement::ListPop(name, line_number) => {
+                code.push_str(&format!("    __list_pop({}, {});\n", name, line_number));
+            }
+        Statement::MapDeclaration(name, _, expr, _) => {
+                // Treat as assignment since variable is declared globally
+                let expr_code = generate_map_expression(expr, variables)?;
+                code.push_str(&format!("    {} = {};\n", name, expr_code));
+            }
+        Statement::MapAssignment(name, expr, _) => {
+                if !matches!(variables.get(name), Some(Type::Map(_))) {
+                    return Err(format!("Map variable '{}' assigned before declaration", name));
+                }
+                let expr_code = generate_map_expression(expr, variables)?;
+                code.push_str(&format!("    {} = {};\n", name, expr_code));
+            }
+        Statement::MapInsert(name, key, value) => {
+                let key_code = generate_expression(key, variables)?;
+                let value_code = generate_item(value, variables)?;
+                code.push_str(&format!("    __map_set({}, {}, {});\n", name, key_code, value_code));
+            }
+        Statement::MapRemove(name, key, line_number) => {
+                let key_code = generate_expression(key, variables)?;
+                code.push_str(&format!("    __map_remove({}, {}, {});\n", name, key_code, line_number));
+            }
+        Statement::WriteFile(path, text, line_number) | Statement::AppendFile(path, text, line_number) => {
+                let path_code = generate_expression(path, variables)?;
+                let text_code = generate_expression(text, variables)?;
+                let append = matches!(stmt, Statement::AppendFile(..));
+                code.push_str(&format!("    __file_write({}, {}, {}, {});\n", path_code, text_code, append, line_number));
+            }
+        Statement::DeleteFile(path, line_number) => {
+                let path_code = generate_expression(path, variables)?;
+                code.push_str(&format!("    __file_delete({}, {});\n", path_code, line_number));
+            }
+        Statement::StructDeclaration(name, _, expr, _) => {
+                // Treat as assignment since variable is declared globally
+                let expr_code = generate_struct_expression(expr, variables)?;
+                code.push_str(&format!("    {} = {};\n", name, expr_code));
+            }
+        Statement::StructAssignment(name, expr, _) => {
+                if !matches!(variables.get(name), Some(Type::Struct(_))) {
+                    return Err(format!("Struct variable '{}' assigned before declaration", name));
+                }
+                let expr_code = generate_struct_expression(expr, variables)?;
+                code.push_str(&format!("    {} = {};\n", name, expr_code));
+            }
+        Statement::FieldAssignment(access, value) => {
+                let value_code = generate_typed_expression(value, variables)?;
+                code.push_str(&format!("    {} = {};\n", field_code(access), value_code));
+            }
+        Statement::EnumDeclaration(name, _, expr, _) => {
+                // Treat as assignment since variable is declared globally
+                let expr_code = generate_enum_expression(expr, variables)?;
+                code.push_str(&format!("    {} = {};\n", name, expr_code));
+            }
+        Statement::EnumAssignment(name, expr, _) => {
+                if !matches!(variables.get(name), Some(Type::Enum(_))) {
+                    return Err(format!("Enum variable '{}' assigned before declaration", name));
+                }
+                let expr_code = generate_enum_expression(expr, variables)?;
+                code.push_str(&format!("    {} = {};\n", name, expr_code));
+            }
+        Statement::Match(subject, arms, _, _) => {
+                code.push_str(&generate_match(subject, arms, variables, functions)?);
+            }
         Statement::Print(expr) => match expr {
                 Expression::StringLiteral(value) => {
                     code.push_str("    printf(\"%s\\n\", \"");
@@ -230,19 +1045,33 @@ fn generate_statement(
                     code.push_str("\");\n");
                 }
                 Expression::Variable(var) => {
-                    if str_vars.contains(var) {
+                    if is_variable_of_type(variables, var, Type::Str) {
                         code.push_str("    printf(\"%s\\n\", ");
                         code.push_str(var);
                         code.push_str(");\n");
-                    } else if num_vars.contains(var) {
+                    } else if is_variable_of_type(variables, var, Type::Num) {
                         code.push_str("    printf(\"%g\\n\", ");
                         code.push_str(var);
                         code.push_str(");\n");
+                    } else if is_variable_of_type(variables, var, Type::Int) {
+                        code.push_str(&format!("    printf(\"%lld\\n\", {});\n", var));
+                    } else if is_variable_of_type(variables, var, Type::Bool) {
+                        code.push_str(&format!("    printf(\"%s\\n\", {} ? \"true\" : \"false\");\n", var));
+                    } else if let Some(Type::List(_)) = variables.get(var) {
+                        code.push_str(&format!("    printf(\"%s\\n\", __list_to_str({}));\n", var));
+                    } else if let Some(Type::Map(_)) = variables.get(var) {
+                        code.push_str(&format!("    printf(\"%s\\n\", __map_to_str({}));\n", var));
+                    } else if let Some(Type::Struct(struct_name)) = variables.get(var) {
+                        code.push_str(&format!("    printf(\"%s\\n\", __struct_{}_to_str({}));\n", struct_name, var));
+                    } else if let Some(Type::Enum(enum_name)) = variables.get(var) {
+                        code.push_str(&format!("    printf(\"%s\\n\", __enum_{}_to_str({}));\n", enum_name, var));
                     } else {
                         return Err(format!("Variable '{}' used before declaration", var));
                     }
                 }
-                _ => return Err("Invalid expression for print statement".to_string()),
+                _ => {
+                    code.push_str(&format!("    printf(\"%s\\n\", {});\n", generate_expression(expr, variables)?));
+                }
             },
         Statement::PrintFormat(parts) => {
                 // Generate separate statements for each part
@@ -251,45 +1080,1127 @@ fn generate_statement(
                         FormatPart::Literal(s) => {
                             code.push_str(&format!("    printf(\"%s\", \"{}\");", escape_string(s)));
                         }
-                        FormatPart::Expression(expr) => {
-                            match expr {
-                                Expression::Variable(name) => {
-                                    if str_vars.contains(name) {
-                                        code.push_str(&format!("    printf(\"%s\", {});", name));
-                                    } else if num_vars.contains(name) {
-                                        code.push_str(&format!("    printf(\"%g\", {});", name));
-                                    } else {
-                                        return Err(format!("Variable '{}' used before declaration", name));
-                                    }
-                                }
-                                Expression::FunctionCall(name) => {
-                                    code.push_str(&format!("    {}();", name));
+                        FormatPart::Expression(TypedExpression::Str(Expression::FunctionCall(name, args)), _)
+                            if functions.get(name) == Some(&None) =>
+                        {
+                            // Functions without a return value are executed for their output
+                            code.push_str(&format!("    {};", generate_call(name, args, variables)?));
+                        }
+                        FormatPart::Expression(expr, spec) => {
+                            let value_code = generate_interpolated_value(expr, variables)?;
+                            match (spec.align, spec.width) {
+                                (Some(Alignment::Center), Some(width)) => {
+                                    // printf can't center, so format the value first and pad it at runtime
+                                    let unpadded = FormatSpec { align: None, width: None, ..spec.clone() };
+                                    code.push_str(&format!(
+                                        "    __print_centered(__str_format(\"{}\", {}), {});",
+                                        format_conversion(expr, &unpadded),
+                                        value_code,
+                                        width
+                                    ));
                                 }
-                                _ => return Err("Invalid expression in format string".to_string()),
+                                _ => code.push_str(&format!("    printf(\"{}\", {});", format_conversion(expr, spec), value_code)),
                             }
                         }
                     }
                 }
                 code.push_str("    printf(\"\\n\");\n");
             }
-        Statement::FunctionCall(name) => {
+        Statement::FunctionCall(name, args) => {
                 code.push_str("    ");
-                code.push_str(name);
-                code.push_str("();\n");
+                code.push_str(&generate_call(name, args, variables)?);
+                code.push_str(";\n");
+            }
+        Statement::Return(value) => match value {
+                Some(value) => {
+                    code.push_str(&format!("    return {};\n", generate_typed_expression(value, variables)?));
+                }
+                None => code.push_str("    return;\n"),
+            },
+        Statement::Exit(status, _) => {
+                code.push_str(&format!("    exit((int) {});\n", generate_int_expression(status, variables)?));
             }
-            Statement::FunctionDefinition(_, _) => {
+            Statement::FunctionDefinition(_, _, _, _) => {
             }
         Statement::CFunctionDefinition(_, _) => todo!(),
-    }
-    Ok(code)
-}
-
-/// Generates C code for a numerical expression.
-///
+        // Struct and enum definitions and constants are emitted ahead of the globals
+        Statement::StructDefinition(_, _) | Statement::EnumDefinition(_, _) | Statement::ConstDeclaration(_, _, _, _) => {}
+        Statement::If(branches, else_body) => {
+                for (i, (condition, body)) in branches.iter().enumerate() {
+                    let condition_code = generate_condition(condition, variables)?;
+                    if i == 0 {
+                        code.push_str(&format!("    if ({}) {{\n", condition_code));
+                    } else {
+                        code.push_str(&format!("    }} else if ({}) {{\n", condition_code));
+                    }
+                    code.push_str(&generate_block(body, variables, functions)?);
+                }
+                if let Some(body) = else_body {
+                    code.push_str("    } else {\n");
+                    code.push_str(&generate_block(body, variables, functions)?);
+                }
+                code.push_str("    }\n");
+            }
+        Statement::While(condition, body) => {
+                let condition_code = generate_condition(condition, variables)?;
+                code.push_str(&format!("    while ({}) {{\n", condition_code));
+                code.push_str(&generate_block(body, variables, functions)?);
+                code.push_str("    }\n");
+            }
+        Statement::For(var, start, end, step, body, line_number) => {
+                let start_code = generate_num_expression(start, variables)?;
+                let end_code = generate_num_expression(end, variables)?;
+                let step_code = generate_num_expression(step, variables)?;
+                let bounds = [start_code, end_code, step_code];
+                code.push_str(&generate_for(var, Type::Num, &bounds, body, *line_number, variables, functions)?);
+            }
+        Statement::IntFor(var, start, end, step, body, line_number) => {
+                let start_code = generate_int_expression(start, variables)?;
+                let end_code = generate_int_expression(end, variables)?;
+                let step_code = generate_int_expression(step, variables)?;
+                let bounds = [start_code, end_code, step_code];
+                code.push_str(&generate_for(var, Type::Int, &bounds, body, *line_number, variables, functions)?);
+            }
+        Statement::Break => {
+                code.push_str("    break;\n");
+            }
+        Statement::Continue => {
+                code.push_str("    continue;\n");
+            }
+    }
+    Ok(code)
+}
+
+/// Generates C code for a counted loop over a numeric or integer range.
+///
+/// # Arguments
+///
+/// * `var` - The name of the loop variable
+/// * `var_type` - The type of the loop variable, 'num' or 'int'
+/// * `bounds` - The C code for the start, the exclusive end and the step
+/// * `body` - The statements inside the loop
+/// * `line_number` - The source line of the loop (for runtime errors)
+/// * `variables` - Map of declared variables to their types
+/// * `functions` - Map of defined functions to their return types
+///
+/// # Returns
+///
+/// A Result containing either:
+/// * The generated C code for the loop
+/// * An error message if code generation fails
+fn generate_for(
+    var: &str,
+    var_type: Type,
+    bounds: &[String; 3],
+    body: &[Statement],
+    line_number: usize,
+    variables: &HashMap<String, Type>,
+    functions: &HashMap<String, Option<Type>>,
+) -> Result<String, String> {
+    let [start_code, end_code, step_code] = bounds;
+    let c_type = c_type(&var_type);
+    let mut code = String::new();
+
+    // Evaluate the bounds and step once, in their own C block so nested
+    // loops can reuse the same temporary names
+    code.push_str("    {\n");
+    code.push_str(&format!("        {} __start = {};\n", c_type, start_code));
+    code.push_str(&format!("        {} __end = {};\n", c_type, end_code));
+    code.push_str(&format!("        {} __step = {};\n", c_type, step_code));
+    code.push_str("        if (__step == 0) {\n");
+    code.push_str(&format!(
+        "            fprintf(stderr, \"Error at line {}: for loop step cannot be zero\\n\");\n",
+        line_number
+    ));
+    code.push_str("            exit(1);\n");
+    code.push_str("        }\n");
+    code.push_str(&format!(
+        "        for ({c_type} {var} = __start; __step > 0 ? {var} < __end : {var} > __end; {var} += __step) {{\n"
+    ));
+    // The loop variable is local to the loop body
+    let mut loop_variables = variables.clone();
+    loop_variables.insert(var.to_string(), var_type);
+    for line in generate_block(body, &loop_variables, functions)?.lines() {
+        code.push_str("    ");
+        code.push_str(line);
+        code.push('\n');
+    }
+    code.push_str("        }\n");
+    code.push_str("    }\n");
+    Ok(code)
+}
+
+/// Generates C code for the statements of a nested block, indented one level
+/// deeper than the enclosing statement.
+///
+/// # Arguments
+///
+/// * `body` - The statements inside the block
+/// * `variables` - Map of declared variables to their types
+/// * `functions` - Map of defined functions to their return types
+///
+/// # Returns
+///
+/// A Result containing either:
+/// * The generated C code for the block's statements
+/// * An error message if code generation fails
+fn generate_block(
+    body: &[Statement],
+    variables: &HashMap<String, Type>,
+    functions: &HashMap<String, Option<Type>>,
+) -> Result<String, String> {
+    let mut code = String::new();
+    for line in generate_scope(body, variables, functions)?.lines() {
+        code.push_str("    ");
+        code.push_str(line);
+        code.push('\n');
+    }
+    Ok(code)
+}
+
+/// Generates C code for the statements of a scope (a function body or a block),
+/// emitting the variables declared in it as C locals.
+///
+/// A local that shadows a variable from an enclosing scope is initialized
+/// through a temporary, since in C the new variable would already be in scope
+/// inside its own initializer.
+///
+/// # Arguments
+///
+/// * `body` - The statements of the scope
+/// * `variables` - Map of variables visible from the enclosing scopes to their types
+/// * `functions` - Map of defined functions to their return types
+///
+/// # Returns
+///
+/// A Result containing either:
+/// * The generated C code for the scope's statements
+/// * An error message if code generation fails
+fn generate_scope(
+    body: &[Statement],
+    variables: &HashMap<String, Type>,
+    functions: &HashMap<String, Option<Type>>,
+) -> Result<String, String> {
+    let mut scope = variables.clone();
+    let mut code = String::new();
+    for stmt in body {
+        let (name, var_type, value_code) = match stmt {
+            Statement::VariableDeclaration(name, expr, _) => (name, Type::Str, generate_expression(expr, &scope)?),
+            Statement::NumDeclaration(name, expr, _) => (name, Type::Num, generate_num_expression(expr, &scope)?),
+            Statement::BoolDeclaration(name, condition, _) => (name, Type::Bool, generate_condition(condition, &scope)?),
+            Statement::IntDeclaration(name, expr, _) => (name, Type::Int, generate_int_expression(expr, &scope)?),
+            Statement::ListDeclaration(name, element_type, expr, _) => {
+                (name, Type::List(Box::new(element_type.clone())), generate_list_expression(expr, &scope)?)
+            }
+            Statement::MapDeclaration(name, value_type, expr, _) => {
+                (name, Type::Map(Box::new(value_type.clone())), generate_map_expression(expr, &scope)?)
+            }
+            Statement::StructDeclaration(name, struct_name, expr, _) => {
+                (name, Type::Struct(struct_name.clone()), generate_struct_expression(expr, &scope)?)
+            }
+            Statement::EnumDeclaration(name, enum_name, expr, _) => {
+                (name, Type::Enum(enum_name.clone()), generate_enum_expression(expr, &scope)?)
+            }
+            _ => {
+                code.push_str(&generate_statement(stmt, &scope, functions)?);
+                continue;
+            }
+        };
+
+        let c_type = c_type(&var_type);
+        if scope.contains_key(name) {
+            code.push_str(&format!("    {} __shadow_{} = {};\n", c_type, name, value_code));
+            code.push_str(&format!("    {} {} = __shadow_{};\n", c_type, name, name));
+        } else {
+            code.push_str(&format!("    {} {} = {};\n", c_type, name, value_code));
+        }
+        scope.insert(name.clone(), var_type);
+    }
+    Ok(code)
+}
+
+/// Generates C code for a condition, producing a C expression that is
+/// non-zero when the condition holds.
+///
+/// # Arguments
+///
+/// * `condition` - The condition to generate code for
+/// * `variables` - Map of declared variables to their types
+///
+/// # Returns
+///
+/// A Result containing either:
+/// * The generated C code for the condition
+/// * An error message if code generation fails
+fn generate_condition(
+    condition: &Condition,
+    variables: &HashMap<String, Type>,
+) -> Result<String, String> {
+    match condition {
+        Condition::NumComparison(left, op, right) => {
+            let left_code = generate_num_expression(left, variables)?;
+            let right_code = generate_num_expression(right, variables)?;
+            Ok(format!("{} {} {}", left_code, comparison_operator(op), right_code))
+        }
+        Condition::IntComparison(left, op, right) => {
+            let left_code = generate_int_expression(left, variables)?;
+            let right_code = generate_int_expression(right, variables)?;
+            Ok(format!("{} {} {}", left_code, comparison_operator(op), right_code))
+        }
+        Condition::StrComparison(left, op, right) => {
+            // Strings are compared by content, so lower to strcmp and compare its result with 0
+            let left_code = generate_expression(left, variables)?;
+            let right_code = generate_expression(right, variables)?;
+            Ok(format!("strcmp({}, {}) {} 0", left_code, right_code, comparison_operator(op)))
+        }
+        Condition::Literal(value) => Ok(value.to_string()),
+        Condition::Variable(name) => {
+            if is_variable_of_type(variables, name, Type::Bool) {
+                Ok(name.clone())
+            } else {
+                Err(format!("Boolean variable '{}' used before declaration", name))
+            }
+        }
+        Condition::And(left, right) => {
+            // C's && already short-circuits, matching the semantics of 'and'
+            let left_code = generate_condition(left, variables)?;
+            let right_code = generate_condition(right, variables)?;
+            Ok(format!("({} && {})", left_code, right_code))
+        }
+        Condition::Or(left, right) => {
+            let left_code = generate_condition(left, variables)?;
+            let right_code = generate_condition(right, variables)?;
+            Ok(format!("({} || {})", left_code, right_code))
+        }
+        Condition::Not(inner) => {
+            let inner_code = generate_condition(inner, variables)?;
+            Ok(format!("!({})", inner_code))
+        }
+        Condition::BoolComparison(left, op, right) => {
+            let left_code = generate_condition(left, variables)?;
+            let right_code = generate_condition(right, variables)?;
+            Ok(format!("({} {} {})", left_code, comparison_operator(op), right_code))
+        }
+        Condition::FunctionCall(name, args) => generate_call(name, args, variables),
+        Condition::ListElement(element) => Ok(format!("{}.boolean", generate_list_element(element, variables)?)),
+        Condition::MapValue(value) => Ok(format!("{}.boolean", generate_map_value(value, variables)?)),
+        Condition::Field(access) => Ok(field_code(access)),
+        Condition::MapHas(map, key) => {
+            let map_code = generate_map_expression(map, variables)?;
+            let key_code = generate_expression(key, variables)?;
+            Ok(format!("__map_has({}, {})", map_code, key_code))
+        }
+        Condition::IsNum(text) => {
+            let text_code = generate_expression(text, variables)?;
+            Ok(format!("__str_parse_num({}, &(double){{0}})", text_code))
+        }
+        Condition::StringCall(function, args, line) => generate_string_call(*function, args, *line, variables),
+        Condition::FileExists(path) => Ok(format!("__file_exists({})", generate_expression(path, variables)?)),
+    }
+}
+
+/// Builds the printf conversion for an interpolated value, such as "%-10s" or "%08.2f".
+///
+/// Centering is not handled here, since printf has no conversion for it.
+fn format_conversion(expr: &TypedExpression, spec: &FormatSpec) -> String {
+    let is_number = matches!(expr, TypedExpression::Num(_) | TypedExpression::Int(_));
+    let mut conversion = String::from("%");
+
+    // Numbers are right-aligned by default, everything else left-aligned
+    let left = match spec.align {
+        Some(Alignment::Left) => true,
+        Some(_) => false,
+        None => !is_number,
+    };
+    if left && spec.width.is_some() {
+        conversion.push('-');
+    }
+    if spec.zero_pad {
+        conversion.push('0');
+    }
+    if let Some(width) = spec.width {
+        write!(conversion, "{}", width).unwrap();
+    }
+    if let Some(precision) = spec.precision {
+        write!(conversion, ".{}", precision).unwrap();
+    }
+
+    conversion.push_str(match expr {
+        TypedExpression::Str(_)
+        | TypedExpression::Bool(_)
+        | TypedExpression::List(_)
+        | TypedExpression::Map(_)
+        | TypedExpression::Struct(_, _)
+        | TypedExpression::Enum(_, _) => "s",
+        TypedExpression::Int(_) => match spec.style {
+            Some(NumberStyle::Hex) => "llx",
+            _ => "lld",
+        },
+        TypedExpression::Num(_) => match spec.style {
+            Some(NumberStyle::Fixed) => "f",
+            Some(NumberStyle::Scientific) => "e",
+            Some(NumberStyle::General) => "g",
+            // A precision without a style asks for a fixed number of decimal places
+            _ if spec.precision.is_some() => "f",
+            _ => "g",
+        },
+    });
+    conversion
+}
+
+/// Returns the C spelling of a comparison operator.
+fn comparison_operator(op: &ComparisonOperator) -> &'static str {
+    match op {
+        ComparisonOperator::Equal => "==",
+        ComparisonOperator::NotEqual => "!=",
+        ComparisonOperator::Less => "<",
+        ComparisonOperator::LessEqual => "<=",
+        ComparisonOperator::Greater => ">",
+        ComparisonOperator::GreaterEqual => ">=",
+    }
+}
+
+/// Generates C code for a string expression used as a value.
+///
+/// # Arguments
+///
+/// * `expr` - The string expression to generate code for
+/// * `variables` - Map of declared variables to their types
+///
+/// # Returns
+///
+/// A Result containing either:
+/// * The generated C code for the expression
+/// * An error message if code generation fails
+fn generate_expression(
+    expr: &Expression,
+    variables: &HashMap<String, Type>,
+) -> Result<String, String> {
+    match expr {
+        Expression::StringLiteral(value) => Ok(format!("\"{}\"", escape_string(value))),
+        Expression::Variable(name) => {
+            if is_variable_of_type(variables, name, Type::Str) {
+                Ok(name.clone())
+            } else {
+                Err(format!("Variable '{}' used before declaration", name))
+            }
+        }
+        Expression::FunctionCall(name, args) => generate_call(name, args, variables),
+        Expression::ListElement(element) => Ok(format!("{}.str", generate_list_element(element, variables)?)),
+        Expression::MapValue(value) => Ok(format!("{}.str", generate_map_value(value, variables)?)),
+        Expression::Field(access) => Ok(field_code(access)),
+        Expression::Concat(operands) => {
+            // The whole concatenation is built with a single formatted allocation
+            let mut format = String::new();
+            let mut args = Vec::new();
+            collect_concat_operands(operands, variables, &mut format, &mut args)?;
+            Ok(format!("__str_format(\"{}\", {})", format, args.join(", ")))
+        }
+        Expression::Format(parts) => {
+            let mut format = String::new();
+            let mut args = Vec::new();
+            collect_format_parts(parts, variables, &mut format, &mut args)?;
+            let args_code: String = args.iter().map(|arg| format!(", {}", arg)).collect();
+            Ok(format!("__str_format(\"{}\"{})", format, args_code))
+        }
+        Expression::Input(prompt, line) => Ok(format!("__input({}, {})", generate_prompt(prompt, variables)?, line)),
+        Expression::ToStr(value) => {
+            // The value is formatted exactly as in a concatenation
+            let mut format = String::new();
+            let mut args = Vec::new();
+            collect_concat_operands(std::slice::from_ref(value.as_ref()), variables, &mut format, &mut args)?;
+            let args_code: String = args.iter().map(|arg| format!(", {}", arg)).collect();
+            Ok(format!("__str_format(\"{}\"{})", format, args_code))
+        }
+        Expression::StringCall(function, args, line) => generate_string_call(*function, args, *line, variables),
+        Expression::ReadFile(path, line) => Ok(format!("__file_read({}, {})", generate_expression(path, variables)?, line)),
+        Expression::Arg(index, line) => Ok(format!("__arg({}, {})", generate_int_expression(index, variables)?, line)),
+        Expression::Env(name, default, line) => {
+            let default_code = match default {
+                Some(default) => generate_expression(default, variables)?,
+                None => "NULL".to_string(),
+            };
+            Ok(format!("__env({}, {}, {})", generate_expression(name, variables)?, default_code, line))
+        }
+    }
+}
+
+/// Generates C code for a call to a built-in string function.
+///
+/// # Arguments
+///
+/// * `function` - The called function
+/// * `args` - The arguments of the call
+/// * `line` - The line number of the call, passed to the functions that can fail
+/// * `variables` - Map of declared variables to their types
+fn generate_string_call(
+    function: StringFunction,
+    args: &[TypedExpression],
+    line: usize,
+    variables: &HashMap<String, Type>,
+) -> Result<String, String> {
+    let mut args = args
+        .iter()
+        .map(|arg| generate_typed_expression(arg, variables))
+        .collect::<Result<Vec<_>, _>>()?;
+    if matches!(function, StringFunction::Substr | StringFunction::Replace | StringFunction::Split) {
+        args.push(line.to_string());
+    }
+    Ok(format!("{}({})", string_function_name(function), args.join(", ")))
+}
+
+/// Generates C code for the optional prompt of `input` and `inputnum`.
+fn generate_prompt(
+    prompt: &Option<Box<Expression>>,
+    variables: &HashMap<String, Type>,
+) -> Result<String, String> {
+    match prompt {
+        Some(prompt) => generate_expression(prompt, variables),
+        None => Ok("NULL".to_string()),
+    }
+}
+
+/// Collects the printf conversions and arguments for the parts of a format
+/// string used as a value.
+///
+/// # Arguments
+///
+/// * `parts` - The literal and interpolated parts of the format string
+/// * `variables` - Map of declared variables to their types
+/// * `format` - The format string to append literal text and conversions to
+/// * `args` - The list of arguments to append to
+fn collect_format_parts(
+    parts: &[FormatPart],
+    variables: &HashMap<String, Type>,
+    format: &mut String,
+    args: &mut Vec<String>,
+) -> Result<(), String> {
+    for part in parts {
+        match part {
+            FormatPart::Literal(s) => format.push_str(&escape_string(s).replace('%', "%%")),
+            FormatPart::Expression(expr, spec) => {
+                let value_code = generate_interpolated_value(expr, variables)?;
+                match (spec.align, spec.width) {
+                    (Some(Alignment::Center), Some(width)) => {
+                        let unpadded = FormatSpec { align: None, width: None, ..spec.clone() };
+                        format.push_str("%s");
+                        args.push(format!(
+                            "__str_center(__str_format(\"{}\", {}), {})",
+                            format_conversion(expr, &unpadded),
+                            value_code,
+                            width
+                        ));
+                    }
+                    _ => {
+                        format.push_str(&format_conversion(expr, spec));
+                        args.push(value_code);
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Generates C code for an interpolated value, matching the conversion built
+/// by `format_conversion`: booleans become the strings "true" and "false",
+/// and lists, maps, structs and enums their text form.
+fn generate_interpolated_value(
+    expr: &TypedExpression,
+    variables: &HashMap<String, Type>,
+) -> Result<String, String> {
+    let value_code = generate_typed_expression(expr, variables)?;
+    match expr {
+        TypedExpression::Bool(_) => Ok(format!("({}) ? \"true\" : \"false\"", value_code)),
+        TypedExpression::List(_) => Ok(format!("__list_to_str({})", value_code)),
+        TypedExpression::Map(_) => Ok(format!("__map_to_str({})", value_code)),
+        TypedExpression::Struct(name, _) => Ok(format!("__struct_{}_to_str({})", name, value_code)),
+        TypedExpression::Enum(name, _) => Ok(format!("__enum_{}_to_str({})", name, value_code)),
+        _ => Ok(value_code),
+    }
+}
+
+/// Collects the printf conversions and arguments for the operands of a
+/// concatenation, flattening nested concatenations.
+///
+/// # Arguments
+///
+/// * `operands` - The operands of the concatenation
+/// * `variables` - Map of declared variables to their types
+/// * `format` - The format string to append conversions to
+/// * `args` - The list of arguments to append to
+fn collect_concat_operands(
+    operands: &[TypedExpression],
+    variables: &HashMap<String, Type>,
+    format: &mut String,
+    args: &mut Vec<String>,
+) -> Result<(), String> {
+    for operand in operands {
+        match operand {
+            TypedExpression::Str(Expression::Concat(inner)) => {
+                collect_concat_operands(inner, variables, format, args)?;
+            }
+            TypedExpression::Str(Expression::Format(parts)) => {
+                collect_format_parts(parts, variables, format, args)?;
+            }
+            TypedExpression::Str(expr) => {
+                format.push_str("%s");
+                args.push(generate_expression(expr, variables)?);
+            }
+            TypedExpression::Num(expr) => {
+                format.push_str("%g");
+                args.push(generate_num_expression(expr, variables)?);
+            }
+            TypedExpression::Int(expr) => {
+                format.push_str("%lld");
+                args.push(generate_int_expression(expr, variables)?);
+            }
+            TypedExpression::Bool(condition) => {
+                format.push_str("%s");
+                args.push(format!("({}) ? \"true\" : \"false\"", generate_condition(condition, variables)?));
+            }
+            TypedExpression::List(expr) => {
+                format.push_str("%s");
+                args.push(format!("__list_to_str({})", generate_list_expression(expr, variables)?));
+            }
+            TypedExpression::Map(expr) => {
+                format.push_str("%s");
+                args.push(format!("__map_to_str({})", generate_map_expression(expr, variables)?));
+            }
+            TypedExpression::Struct(name, expr) => {
+                format.push_str("%s");
+                args.push(format!("__struct_{}_to_str({})", name, generate_struct_expression(expr, variables)?));
+            }
+            TypedExpression::Enum(name, expr) => {
+                format.push_str("%s");
+                args.push(format!("__enum_{}_to_str({})", name, generate_enum_expression(expr, variables)?));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Generates C code for an expression of any type.
+///
+/// # Arguments
+///
+/// * `expr` - The typed expression to generate code for
+/// * `variables` - Map of declared variables to their types
+///
+/// # Returns
+///
+/// A Result containing either:
+/// * The generated C code for the expression
+/// * An error message if code generation fails
+fn generate_typed_expression(
+    expr: &TypedExpression,
+    variables: &HashMap<String, Type>,
+) -> Result<String, String> {
+    match expr {
+        TypedExpression::Str(expr) => generate_expression(expr, variables),
+        TypedExpression::Num(expr) => generate_num_expression(expr, variables),
+        TypedExpression::Bool(condition) => generate_condition(condition, variables),
+        TypedExpression::Int(expr) => generate_int_expression(expr, variables),
+        TypedExpression::List(expr) => generate_list_expression(expr, variables),
+        TypedExpression::Map(expr) => generate_map_expression(expr, variables),
+        TypedExpression::Struct(_, expr) => generate_struct_expression(expr, variables),
+        TypedExpression::Enum(_, expr) => generate_enum_expression(expr, variables),
+    }
+}
+
+/// Generates C code for a list expression, producing a `__list*`.
+///
+/// # Arguments
+///
+/// * `expr` - The list expression to generate code for
+/// * `variables` - Map of declared variables to their types
+///
+/// # Returns
+///
+/// A Result containing either:
+/// * The generated C code for the expression
+/// * An error message if code generation fails
+fn generate_list_expression(
+    expr: &ListExpression,
+    variables: &HashMap<String, Type>,
+) -> Result<String, String> {
+    match expr {
+        ListExpression::Literal(element_type, elements) => {
+            let mut args = vec![format!("'{}'", list_kind(element_type)), elements.len().to_string()];
+            for element in elements {
+                args.push(generate_item(element, variables)?);
+            }
+            Ok(format!("__list_new({})", args.join(", ")))
+        }
+        ListExpression::Variable(name) => {
+            if let Some(Type::List(_)) = variables.get(name) {
+                Ok(name.clone())
+            } else {
+                Err(format!("List variable '{}' used before declaration", name))
+            }
+        }
+        ListExpression::FunctionCall(name, args) => generate_call(name, args, variables),
+        ListExpression::MapKeys(map) => Ok(format!("__map_keys({})", generate_map_expression(map, variables)?)),
+        ListExpression::Field(access) => Ok(field_code(access)),
+        ListExpression::StringCall(function, args, line) => generate_string_call(*function, args, *line, variables),
+    }
+}
+
+/// Generates C code reading a list element, producing an `__item` whose
+/// member for the element type holds the value.
+fn generate_list_element(
+    element: &ListElement,
+    variables: &HashMap<String, Type>,
+) -> Result<String, String> {
+    match element {
+        ListElement::Index(name, index, line_number) => {
+            let index_code = generate_int_expression(index, variables)?;
+            Ok(format!("__list_get({}, {}, {})", name, index_code, line_number))
+        }
+        ListElement::Pop(name, line_number) => Ok(format!("__list_pop({}, {})", name, line_number)),
+    }
+}
+
+/// Generates C code for a map expression, producing a `__map*`.
+///
+/// # Arguments
+///
+/// * `expr` - The map expression to generate code for
+/// * `variables` - Map of declared variables to their types
+///
+/// # Returns
+///
+/// A Result containing either:
+/// * The generated C code for the expression
+/// * An error message if code generation fails
+fn generate_map_expression(
+    expr: &MapExpression,
+    variables: &HashMap<String, Type>,
+) -> Result<String, String> {
+    match expr {
+        MapExpression::Literal(value_type, entries) => {
+            let mut args = vec![format!("'{}'", list_kind(value_type)), entries.len().to_string()];
+            for (key, value) in entries {
+                args.push(generate_expression(key, variables)?);
+                args.push(generate_item(value, variables)?);
+            }
+            Ok(format!("__map_new({})", args.join(", ")))
+        }
+        MapExpression::Variable(name) => {
+            if let Some(Type::Map(_)) = variables.get(name) {
+                Ok(name.clone())
+            } else {
+                Err(format!("Map variable '{}' used before declaration", name))
+            }
+        }
+        MapExpression::FunctionCall(name, args) => generate_call(name, args, variables),
+        MapExpression::Field(access) => Ok(field_code(access)),
+    }
+}
+
+/// Generates C code for a struct expression, producing a struct value.
+///
+/// # Arguments
+///
+/// * `expr` - The struct expression to generate code for
+/// * `variables` - Map of declared variables to their types
+///
+/// # Returns
+///
+/// A Result containing either:
+/// * The generated C code for the expression
+/// * An error message if code generation fails
+fn generate_struct_expression(
+    expr: &StructExpression,
+    variables: &HashMap<String, Type>,
+) -> Result<String, String> {
+    match expr {
+        StructExpression::Literal(name, fields) => {
+            let fields_code = fields
+                .iter()
+                .map(|(field, value)| Ok(format!(".{} = {}", field, generate_typed_expression(value, variables)?)))
+                .collect::<Result<Vec<_>, String>>()?;
+            Ok(format!("((struct {}){{{}}})", name, fields_code.join(", ")))
+        }
+        StructExpression::Variable(name) => {
+            if let Some(Type::Struct(_)) = variables.get(name) {
+                Ok(name.clone())
+            } else {
+                Err(format!("Struct variable '{}' used before declaration", name))
+            }
+        }
+        StructExpression::FunctionCall(name, args) => generate_call(name, args, variables),
+        StructExpression::Field(access) => Ok(field_code(access)),
+    }
+}
+
+/// Generates C code for a field of a struct variable, such as `line.start.x`.
+fn field_code(access: &FieldAccess) -> String {
+    format!("{}.{}", access.variable, access.fields.join("."))
+}
+
+/// Generates the C definition of a struct, followed by the function that
+/// converts its values to text such as `Point { x: 1, y: 2 }`.
+///
+/// # Arguments
+///
+/// * `name` - The name of the struct
+/// * `fields` - The fields of the struct and their types, in declaration order
+fn generate_struct_definition(name: &str, fields: &[(String, Type)]) -> String {
+    let mut code = format!("struct {} {{\n", name);
+    for (field, field_type) in fields {
+        code.push_str(&format!("    {} {};\n", c_type(field_type), field));
+    }
+    code.push_str("};\n\n");
+
+    let mut format = String::new();
+    let mut args = Vec::new();
+    for (field, field_type) in fields {
+        let (conversion, arg) = text_conversion(field_type, format!("value.{}", field));
+        let separator = if format.is_empty() { "" } else { ", " };
+        format.push_str(&format!("{}{}: {}", separator, field, conversion));
+        args.push(arg);
+    }
+    let text = if fields.is_empty() {
+        format!("{} {{}}", name)
+    } else {
+        format!("{} {{ {} }}", name, format)
+    };
+    code.push_str(&format!("static char* __struct_{}_to_str(struct {} value) {{\n", name, name));
+    if args.is_empty() {
+        code.push_str(&format!("    (void) value;\n    return __str_format(\"{}\");\n", text));
+    } else {
+        code.push_str(&format!("    return __str_format(\"{}\", {});\n", text, args.join(", ")));
+    }
+    code.push_str("}\n\n");
+    code
+}
+
+/// Returns the printf conversion and argument that show a value of the given
+/// type inside the text form of a struct or enum, with strings quoted.
+fn text_conversion(value_type: &Type, value: String) -> (&'static str, String) {
+    match value_type {
+        Type::Str => ("\\\"%s\\\"", value),
+        Type::Num => ("%g", value),
+        Type::Int => ("%lld", value),
+        Type::Bool => ("%s", format!("{} ? \"true\" : \"false\"", value)),
+        Type::List(_) => ("%s", format!("__list_to_str({})", value)),
+        Type::Map(_) => ("%s", format!("__map_to_str({})", value)),
+        Type::Struct(name) => ("%s", format!("__struct_{}_to_str({})", name, value)),
+        Type::Enum(name) => ("%s", format!("__enum_{}_to_str({})", name, value)),
+    }
+}
+
+/// Generates the C definition of an enum, followed by the function that
+/// converts its values to text such as `Shape.Circle(2.5)`.
+///
+/// An enum value is a struct holding the tag of its variant and, in a union,
+/// the values carried by the variant as fields `_0`, `_1`, ...
+///
+/// # Arguments
+///
+/// * `name` - The name of the enum
+/// * `variants` - The variants of the enum and the types of their values, in declaration order
+fn generate_enum_definition(name: &str, variants: &[(String, Vec<Type>)]) -> String {
+    let tags: Vec<String> = variants.iter().map(|(variant, _)| format!("__{}_{}", name, variant)).collect();
+    let mut code = format!("enum {{ {} }};\n", tags.join(", "));
+
+    code.push_str(&format!("struct {} {{\n    int tag;\n", name));
+    if variants.iter().any(|(_, value_types)| !value_types.is_empty()) {
+        code.push_str("    union {\n");
+        for (variant, value_types) in variants.iter().filter(|(_, value_types)| !value_types.is_empty()) {
+            let values: Vec<String> = value_types
+                .iter()
+                .enumerate()
+                .map(|(i, value_type)| format!("{} _{};", c_type(value_type), i))
+                .collect();
+            code.push_str(&format!("        struct {{ {} }} {};\n", values.join(" "), variant));
+        }
+        code.push_str("    };\n");
+    }
+    code.push_str("};\n\n");
+
+    code.push_str(&format!("static char* __enum_{}_to_str(struct {} value) {{\n", name, name));
+    code.push_str("    switch (value.tag) {\n");
+    for ((variant, value_types), tag) in variants.iter().zip(&tags) {
+        let mut format = format!("{}.{}", name, variant);
+        let mut args = Vec::new();
+        for (i, value_type) in value_types.iter().enumerate() {
+            let (conversion, arg) = text_conversion(value_type, format!("value.{}._{}", variant, i));
+            format.push_str(if i == 0 { "(" } else { ", " });
+            format.push_str(conversion);
+            args.push(arg);
+        }
+        if args.is_empty() {
+            code.push_str(&format!("    case {}: return __str_format(\"{}\");\n", tag, format));
+        } else {
+            code.push_str(&format!("    case {}: return __str_format(\"{})\", {});\n", tag, format, args.join(", ")));
+        }
+    }
+    code.push_str("    }\n    return NULL;\n}\n\n");
+    code
+}
+
+/// Generates C code for an enum expression, producing a value of the enum's C struct.
+///
+/// # Arguments
+///
+/// * `expr` - The enum expression to generate code for
+/// * `variables` - Map of declared variables to their types
+///
+/// # Returns
+///
+/// A Result containing either:
+/// * The generated C code for the expression
+/// * An error message if code generation fails
+fn generate_enum_expression(
+    expr: &EnumExpression,
+    variables: &HashMap<String, Type>,
+) -> Result<String, String> {
+    match expr {
+        EnumExpression::Variant(name, variant, values) => {
+            if values.is_empty() {
+                return Ok(format!("((struct {}){{.tag = __{}_{}}})", name, name, variant));
+            }
+            let values_code = values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| Ok(format!("._{} = {}", i, generate_typed_expression(value, variables)?)))
+                .collect::<Result<Vec<_>, String>>()?;
+            Ok(format!(
+                "((struct {}){{.tag = __{}_{}, .{} = {{{}}}}})",
+                name,
+                name,
+                variant,
+                variant,
+                values_code.join(", ")
+            ))
+        }
+        EnumExpression::Variable(name) => {
+            if let Some(Type::Enum(_)) = variables.get(name) {
+                Ok(name.clone())
+            } else {
+                Err(format!("Enum variable '{}' used before declaration", name))
+            }
+        }
+        EnumExpression::FunctionCall(name, args) => generate_call(name, args, variables),
+        EnumExpression::Field(access) => Ok(field_code(access)),
+    }
+}
+
+/// Generates C code for a match statement.
+///
+/// The matched value is evaluated once into a temporary, in its own C block
+/// so nested matches can reuse the same name. Integers and enums become a C
+/// `switch`, other values an if-chain, as do matches whose arms use 'break'
+/// to leave an enclosing loop (which would only leave a `switch`). Arms that
+/// can't run because an earlier arm handles the same value are left out.
+///
+/// # Arguments
+///
+/// * `subject` - The matched value
+/// * `arms` - The arms of the match, in source order
+/// * `variables` - Map of declared variables to their types
+/// * `functions` - Map of defined functions to their return types
+///
+/// # Returns
+///
+/// A Result containing either:
+/// * The generated C code for the match
+/// * An error message if code generation fails
+fn generate_match(
+    subject: &TypedExpression,
+    arms: &[MatchArm],
+    variables: &HashMap<String, Type>,
+    functions: &HashMap<String, Option<Type>>,
+) -> Result<String, String> {
+    let subject_type = match subject {
+        TypedExpression::Str(_) => Type::Str,
+        TypedExpression::Num(_) => Type::Num,
+        TypedExpression::Int(_) => Type::Int,
+        TypedExpression::Bool(_) => Type::Bool,
+        TypedExpression::Enum(name, _) => Type::Enum(name.clone()),
+        _ => return Err("Only enums, numbers, integers, strings and booleans can be matched".to_string()),
+    };
+    let use_switch = matches!(subject_type, Type::Int | Type::Enum(_))
+        && !arms.iter().any(|arm| contains_loop_break(&arm.body));
+
+    let mut code = String::from("    {\n");
+    code.push_str(&format!(
+        "        {} __match = {};\n",
+        c_type(&subject_type),
+        generate_typed_expression(subject, variables)?
+    ));
+    if use_switch {
+        let value = if let Type::Enum(_) = subject_type { "__match.tag" } else { "__match" };
+        code.push_str(&format!("        switch ({}) {{\n", value));
+    }
+
+    let mut handled = Vec::new();
+    for arm in arms {
+        // The C label or condition of the arm, None for '_'
+        let test = match (&arm.pattern, &subject_type) {
+            (Pattern::Wildcard, _) => None,
+            (Pattern::Int(value), _) => Some(if use_switch {
+                value.to_string()
+            } else {
+                format!("__match == {}", value)
+            }),
+            (Pattern::Num(value), _) => Some(format!("__match == {:?}", value)),
+            (Pattern::Str(value), _) => Some(format!("strcmp(__match, \"{}\") == 0", escape_string(value))),
+            (Pattern::Bool(value), _) => Some(if *value { "__match" } else { "!__match" }.to_string()),
+            (Pattern::Variant(variant, _), Type::Enum(name)) => Some(if use_switch {
+                format!("__{}_{}", name, variant)
+            } else {
+                format!("__match.tag == __{}_{}", name, variant)
+            }),
+            (Pattern::Variant(_, _), _) => return Err("Variant pattern used on a value that isn't an enum".to_string()),
+        };
+        if let Some(test) = &test {
+            if handled.contains(test) {
+                continue;
+            }
+            handled.push(test.clone());
+        }
+
+        // Values bound by the pattern are locals of the arm
+        let mut arm_variables = variables.clone();
+        let mut arm_code = String::new();
+        if let Pattern::Variant(variant, bindings) = &arm.pattern {
+            for (i, (binding, value_type)) in bindings.iter().enumerate() {
+                if binding != "_" {
+                    arm_code.push_str(&format!("    {} {} = __match.{}._{};\n", c_type(value_type), binding, variant, i));
+                    arm_variables.insert(binding.clone(), value_type.clone());
+                }
+            }
+        }
+        arm_code.push_str(&generate_scope(&arm.body, &arm_variables, functions)?);
+
+        let opening = match (&test, use_switch) {
+            (Some(test), true) => format!("        case {}: {{\n", test),
+            (None, true) => "        default: {\n".to_string(),
+            (Some(test), false) if handled.len() == 1 => format!("        if ({}) {{\n", test),
+            (Some(test), false) => format!("        }} else if ({}) {{\n", test),
+            (None, false) if handled.is_empty() => "        {\n".to_string(),
+            (None, false) => "        } else {\n".to_string(),
+        };
+        code.push_str(&opening);
+        for line in arm_code.lines() {
+            code.push_str("        ");
+            code.push_str(line);
+            code.push('\n');
+        }
+        if use_switch {
+            code.push_str("            break;\n        }\n");
+        }
+
+        // Any later arm is unreachable
+        if test.is_none() {
+            break;
+        }
+    }
+    if !use_switch && !arms.is_empty() {
+        code.push_str("        }\n");
+    }
+    if use_switch {
+        code.push_str("        }\n");
+    }
+    code.push_str("    }\n");
+    Ok(code)
+}
+
+/// Checks whether statements contain a 'break' that leaves a loop enclosing
+/// them, as opposed to one inside a nested loop.
+fn contains_loop_break(statements: &[Statement]) -> bool {
+    statements.iter().any(|stmt| match stmt {
+        Statement::Break => true,
+        Statement::If(branches, else_body) => {
+            branches.iter().any(|(_, body)| contains_loop_break(body))
+                || else_body.as_ref().is_some_and(|body| contains_loop_break(body))
+        }
+        Statement::Match(_, arms, _, _) => arms.iter().any(|arm| contains_loop_break(&arm.body)),
+        _ => false,
+    })
+}
+
+/// Generates C code looking up a map value, producing an `__item` whose
+/// member for the value type holds the value.
+fn generate_map_value(
+    value: &MapValue,
+    variables: &HashMap<String, Type>,
+) -> Result<String, String> {
+    let key_code = generate_expression(&value.key, variables)?;
+    Ok(format!("__map_get({}, {}, {})", value.map, key_code, value.line))
+}
+
+/// Generates C code wrapping a value in an `__item`, for storing it in a list or map.
+fn generate_item(
+    value: &TypedExpression,
+    variables: &HashMap<String, Type>,
+) -> Result<String, String> {
+    let member = match value {
+        TypedExpression::Str(_) => "str",
+        TypedExpression::Num(_) => "num",
+        TypedExpression::Int(_) => "integer",
+        TypedExpression::Bool(_) => "boolean",
+        TypedExpression::List(_) | TypedExpression::Map(_) | TypedExpression::Struct(_, _) | TypedExpression::Enum(_, _) => {
+            return Err("Lists and maps can only hold 'num', 'int', 'str' or 'bool' values".to_string());
+        }
+    };
+    Ok(format!("(__item){{.{} = {}}}", member, generate_typed_expression(value, variables)?))
+}
+
+/// Generates C code for a function call expression (without a trailing semicolon).
+///
+/// # Arguments
+///
+/// * `name` - The name of the called function
+/// * `args` - The arguments, already checked against the function's parameters
+/// * `variables` - Map of declared variables to their types
+///
+/// # Returns
+///
+/// A Result containing either:
+/// * The generated C code for the call
+/// * An error message if code generation fails
+fn generate_call(
+    name: &str,
+    args: &[TypedExpression],
+    variables: &HashMap<String, Type>,
+) -> Result<String, String> {
+    let args_code = args
+        .iter()
+        .map(|arg| generate_typed_expression(arg, variables))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(format!("{}({})", name, args_code.join(", ")))
+}
+
+/// Builds the C signature of a function, used for both its prototype and its definition.
+fn function_signature(name: &str, func_type: &FunctionType) -> String {
+    match func_type {
+        FunctionType::Regular(params, return_type, _) => {
+            let return_code = return_type.as_ref().map_or("void".to_string(), c_type);
+            let params_code = if params.is_empty() {
+                "void".to_string()
+            } else {
+                params
+                    .iter()
+                    .map(|(param, param_type)| format!("{} {}", c_type(param_type), param))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            format!("{} {}({})", return_code, name, params_code)
+        }
+        FunctionType::CCode(_) => format!("void {}(void)", name),
+    }
+}
+
+/// Generates C code for a numerical expression.
+///
 /// # Arguments
 ///
 /// * `expr` - The numerical expression to generate code for
-/// * `variables` - Set of declared numerical variables
+/// * `variables` - Map of declared variables to their types
 ///
 /// # Returns
 ///
@@ -298,15 +2209,16 @@ fn generate_statement(
 /// * An error message if code generation fails
 fn generate_num_expression(
     expr: &NumExpression,
-    variables: &HashSet<String>,
+    variables: &HashMap<String, Type>,
 ) -> Result<String, String> {
     match expr {
         NumExpression::NumberLiteral(value) => {
-            // Format the number with enough precision
-            Ok(format!("{}", value))
+            // Debug formatting keeps a decimal point or exponent, so the literal
+            // is a double in C even when its value is a whole number
+            Ok(format!("{:?}", value))
         }
         NumExpression::Variable(name) => {
-            if variables.contains(name) {
+            if is_variable_of_type(variables, name, Type::Num) {
                 Ok(name.clone())
             } else {
                 Err(format!("Numerical variable '{}' used before declaration", name))
@@ -317,12 +2229,14 @@ fn generate_num_expression(
             let left_code = generate_num_expression(left, variables)?;
             let right_code = generate_num_expression(right, variables)?;
 
-            // Apply the operator
+            // Modulo and exponentiation map to the math.h functions
             let operator = match op {
                 BinaryOperator::Add => "+",
                 BinaryOperator::Subtract => "-",
                 BinaryOperator::Multiply => "*",
                 BinaryOperator::Divide => "/",
+                BinaryOperator::Modulo => return Ok(format!("fmod({}, {})", left_code, right_code)),
+                BinaryOperator::Power => return Ok(format!("pow({}, {})", left_code, right_code)),
             };
 
             // Wrap in parentheses to preserve operator precedence
@@ -333,6 +2247,163 @@ fn generate_num_expression(
             let inner_code = generate_num_expression(inner, variables)?;
             Ok(format!("({})", inner_code))
         }
+        NumExpression::Negate(inner) => {
+            // The space keeps a nested negation from turning into '--'
+            let inner_code = generate_num_expression(inner, variables)?;
+            Ok(format!("(- {})", inner_code))
+        }
+        NumExpression::FunctionCall(name, args) => generate_call(name, args, variables),
+        NumExpression::MathCall(function, args) => {
+            let args = args
+                .iter()
+                .map(|arg| generate_num_expression(arg, variables))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(format!("{}({})", math_function_name(*function), args.join(", ")))
+        }
+        NumExpression::FromInt(inner) => {
+            let inner_code = generate_int_expression(inner, variables)?;
+            Ok(format!("((double) {})", inner_code))
+        }
+        NumExpression::ListElement(element) => Ok(format!("{}.num", generate_list_element(element, variables)?)),
+        NumExpression::MapValue(value) => Ok(format!("{}.num", generate_map_value(value, variables)?)),
+        NumExpression::Field(access) => Ok(field_code(access)),
+        NumExpression::InputNum(prompt, line) => {
+            Ok(format!("__input_num({}, {})", generate_prompt(prompt, variables)?, line))
+        }
+        NumExpression::ToNum(text, line) => {
+            Ok(format!("__str_to_num({}, {})", generate_expression(text, variables)?, line))
+        }
+    }
+}
+
+/// Generates C code for an integer expression.
+///
+/// Arithmetic goes through the helpers of the integer runtime, which check for
+/// division by zero and, in checked mode, overflow.
+///
+/// # Arguments
+///
+/// * `expr` - The integer expression to generate code for
+/// * `variables` - Map of declared variables to their types
+///
+/// # Returns
+///
+/// A Result containing either:
+/// * The generated C code for the expression
+/// * An error message if code generation fails
+fn generate_int_expression(
+    expr: &IntExpression,
+    variables: &HashMap<String, Type>,
+) -> Result<String, String> {
+    match expr {
+        // C has no literal for the smallest 'int'; its magnitude doesn't fit in a long long
+        IntExpression::IntegerLiteral(i64::MIN) => Ok("(-9223372036854775807LL - 1)".to_string()),
+        IntExpression::IntegerLiteral(value) => Ok(format!("{}LL", value)),
+        IntExpression::Variable(name) => {
+            if is_variable_of_type(variables, name, Type::Int) {
+                Ok(name.clone())
+            } else {
+                Err(format!("Integer variable '{}' used before declaration", name))
+            }
+        }
+        IntExpression::BinaryOp(left, op, right, line_number) => {
+            let left_code = generate_int_expression(left, variables)?;
+            let right_code = generate_int_expression(right, variables)?;
+            let helper = match op {
+                BinaryOperator::Add => "__int_add",
+                BinaryOperator::Subtract => "__int_sub",
+                BinaryOperator::Multiply => "__int_mul",
+                BinaryOperator::Divide => "__int_div",
+                BinaryOperator::Modulo => "__int_mod",
+                BinaryOperator::Power => "__int_pow",
+            };
+            Ok(format!("{}({}, {}, {})", helper, left_code, right_code, line_number))
+        }
+        IntExpression::Grouping(inner) => {
+            let inner_code = generate_int_expression(inner, variables)?;
+            Ok(format!("({})", inner_code))
+        }
+        IntExpression::Negate(inner, line_number) => {
+            let inner_code = generate_int_expression(inner, variables)?;
+            Ok(format!("__int_neg({}, {})", inner_code, line_number))
+        }
+        IntExpression::FunctionCall(name, args) => generate_call(name, args, variables),
+        IntExpression::FromNum(inner, line_number) => {
+            let inner_code = generate_num_expression(inner, variables)?;
+            Ok(format!("__int_from_num({}, {})", inner_code, line_number))
+        }
+        IntExpression::ListElement(element) => Ok(format!("{}.integer", generate_list_element(element, variables)?)),
+        IntExpression::ListLength(list) => Ok(format!("{}->length", generate_list_expression(list, variables)?)),
+        IntExpression::MapValue(value) => Ok(format!("{}.integer", generate_map_value(value, variables)?)),
+        IntExpression::MapLength(map) => Ok(format!("{}->length", generate_map_expression(map, variables)?)),
+        IntExpression::StringCall(function, args, line) => generate_string_call(*function, args, *line, variables),
+        IntExpression::ArgCount => Ok("((long long) __args_count)".to_string()),
+        IntExpression::Field(access) => Ok(field_code(access)),
+    }
+}
+
+/// Returns the name of the C math library function implementing a built-in.
+fn math_function_name(function: MathFunction) -> &'static str {
+    match function {
+        MathFunction::Sqrt => "sqrt",
+        MathFunction::Abs => "fabs",
+        MathFunction::Floor => "floor",
+        MathFunction::Ceil => "ceil",
+        MathFunction::Round => "round",
+        MathFunction::Sin => "sin",
+        MathFunction::Cos => "cos",
+        MathFunction::Tan => "tan",
+        MathFunction::Log => "log",
+        MathFunction::Exp => "exp",
+        MathFunction::Min => "fmin",
+        MathFunction::Max => "fmax",
+    }
+}
+
+/// Returns the name of the C runtime helper implementing a string function.
+fn string_function_name(function: StringFunction) -> &'static str {
+    match function {
+        StringFunction::Length => "__str_length",
+        StringFunction::Upper => "__str_upper",
+        StringFunction::Lower => "__str_lower",
+        StringFunction::Trim => "__str_trim",
+        StringFunction::Contains => "__str_contains",
+        StringFunction::StartsWith => "__str_starts_with",
+        StringFunction::EndsWith => "__str_ends_with",
+        StringFunction::IndexOf => "__str_index_of",
+        StringFunction::Substr => "__str_substr",
+        StringFunction::Replace => "__str_replace",
+        StringFunction::Split => "__str_split",
+        StringFunction::Join => "__str_join",
+    }
+}
+
+/// Checks whether a variable has been declared with the given type.
+fn is_variable_of_type(variables: &HashMap<String, Type>, name: &str, var_type: Type) -> bool {
+    variables.get(name) == Some(&var_type)
+}
+
+/// Returns the C type used to store a variable of the given type.
+fn c_type(var_type: &Type) -> String {
+    match var_type {
+        Type::Str => "char*".to_string(),
+        Type::Num => "double".to_string(),
+        Type::Int => "long long".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::List(_) => "__list*".to_string(),
+        Type::Map(_) => "__map*".to_string(),
+        Type::Struct(name) | Type::Enum(name) => format!("struct {}", name),
+    }
+}
+
+/// Returns the runtime kind of a list or map with the given element or value
+/// type, used to convert it to text.
+fn list_kind(element_type: &Type) -> char {
+    match element_type {
+        Type::Num => 'n',
+        Type::Int => 'i',
+        Type::Bool => 'b',
+        _ => 's',
     }
 }
 
@@ -1,6 +1,7 @@
 // errors.rs
 use std::cmp::{max, min};
 
+#[derive(Clone, Copy)]
 pub struct ErrorPosition {
     pub line: usize,
     pub column: usize,
@@ -9,6 +9,8 @@
 // detailed lexical errors.
 
 use crate::errors::{ErrorPosition, format_error};
+use std::iter::Peekable;
+use std::str::Chars;
 
 /// Represents the different types of tokens in the Vortlang language.
 ///
@@ -22,11 +24,14 @@ pub enum TokenType {
     /// An identifier (variable name, function name, etc.)
     Identifier(String),
     
-    /// A string literal enclosed in double quotes
+    /// A string literal: `"..."`, triple-quoted `"""..."""` or raw `r"..."`/`r#"..."#`
     StringLiteral(String),
     
-    /// A numerical literal (integer or floating-point)
+    /// A numerical literal with a decimal point, or an integer too large for an 'int'
     NumberLiteral(f64),
+
+    /// An integer literal without a decimal point
+    IntegerLiteral(i64),
     
     /// Left parenthesis '('
     OpenParen,
@@ -54,15 +59,130 @@ pub enum TokenType {
     
     /// Division operator '/'
     Slash,
+
+    /// Modulo operator '%'
+    Percent,
+
+    /// Exponentiation operator '**'
+    StarStar,
+
+    /// Equality comparison operator '=='
+    EqualEqual,
+
+    /// Inequality comparison operator '!='
+    NotEqual,
+
+    /// Less-than comparison operator '<'
+    Less,
+
+    /// Less-than-or-equal comparison operator '<='
+    LessEqual,
+
+    /// Greater-than comparison operator '>'
+    Greater,
+
+    /// Greater-than-or-equal comparison operator '>='
+    GreaterEqual,
+
+    /// The 'if' keyword for conditional statements
+    If,
+
+    /// The 'else' keyword for alternative branches of an 'if'
+    Else,
+
+    /// The 'while' keyword for conditional loops
+    While,
+
+    /// The 'break' keyword for leaving the innermost loop
+    Break,
+
+    /// The 'continue' keyword for skipping to the next loop iteration
+    Continue,
+
+    /// The 'for' keyword for counted loops
+    For,
+
+    /// The 'in' keyword separating a 'for' loop variable from its range
+    In,
+
+    /// Range operator '..' (end is exclusive)
+    DotDot,
+
+    /// The 'step' keyword for the increment of a 'for' loop
+    Step,
+
+    /// The 'bool' keyword for boolean variable declaration
+    Bool,
+
+    /// The 'int' keyword for integer variable declaration
+    Int,
+
+    /// The 'list' keyword for list variable declaration
+    List,
+
+    /// The 'map' keyword for map variable declaration
+    Map,
+
+    /// The 'struct' keyword for struct type definition
+    Struct,
+
+    /// The 'enum' keyword for enum type definition
+    Enum,
+
+    /// The 'const' keyword for compile-time constant declaration
+    Const,
+
+    /// The 'match' keyword starting a match statement
+    Match,
+
+    /// Dot '.' accessing a field of a struct
+    Dot,
+
+    /// Left bracket '[' opening a list literal, an index or a map key
+    OpenBracket,
+
+    /// Right bracket ']' closing a list literal, an index or a map key
+    CloseBracket,
+
+    /// The boolean literal 'true'
+    True,
+
+    /// The boolean literal 'false'
+    False,
+
+    /// Logical conjunction operator 'and' (or '&&')
+    And,
+
+    /// Logical disjunction operator 'or' (or '||')
+    Or,
+
+    /// Logical negation operator 'not' (or '!')
+    Not,
+
+    /// Comma ',' separating function parameters, arguments and list elements
+    Comma,
+
+    /// Colon ':' separating a parameter or field name from its type, or a map
+    /// key from its value
+    Colon,
+
+    /// Arrow '->' introducing a function's return type
+    Arrow,
+
+    /// Fat arrow '=>' separating the pattern of a match arm from its body
+    FatArrow,
+
+    /// The 'return' keyword for leaving a function with an optional value
+    Return,
     
-    /// Format string prefix marker 'o' (used in print(o"..."))
+    /// Format string prefix marker 'o' (as in o"Hello {name}")
     FormatStringPrefix,
     
     /// Newline character (important for line counting and statement separation)
     Newline,
     
     /// End of file marker
-    EOF,
+    Eof,
     
     /// The 'newfn' keyword for function definitions
     NewFn,
@@ -116,10 +236,39 @@ pub struct Token {
 /// * A vector of Token objects if tokenization was successful
 /// * A formatted error message if a lexical error was encountered
 pub fn tokenize(source: &str, source_path: &str) -> Result<Vec<Token>, String> {
+    tokenize_fragment(source, source, source_path, 1, 1)
+}
+
+/// Converts a fragment of the source code into a sequence of tokens.
+///
+/// Used to tokenize code embedded in another token, such as the expressions
+/// inside the braces of a format string. Positions are counted from where the
+/// fragment starts, so tokens and errors point into the original source.
+///
+/// # Arguments
+///
+/// * `text` - The code to tokenize
+/// * `source` - The complete source code (for error reporting)
+/// * `source_path` - The path to the source file (for error reporting)
+/// * `start_line` - The line where the fragment starts (1-based)
+/// * `start_column` - The column where the fragment starts (1-based)
+///
+/// # Returns
+///
+/// A Result containing either:
+/// * A vector of Token objects if tokenization was successful
+/// * A formatted error message if a lexical error was encountered
+pub fn tokenize_fragment(
+    text: &str,
+    source: &str,
+    source_path: &str,
+    start_line: usize,
+    start_column: usize,
+) -> Result<Vec<Token>, String> {
     let mut tokens = Vec::new();
-    let mut line = 1;
-    let mut column = 1;
-    let mut chars = source.chars().peekable();
+    let mut line = start_line;
+    let mut column = start_column;
+    let mut chars = text.chars().peekable();
 
     // Process the source code character by character
     while let Some(&c) = chars.peek() {
@@ -186,6 +335,25 @@ pub fn tokenize(source: &str, source_path: &str) -> Result<Vec<Token>, String> {
                     ));
                 }
             }
+            '.' => {
+                chars.next();
+                if let Some('.') = chars.peek() {
+                    chars.next();
+                    tokens.push(Token {
+                        token_type: TokenType::DotDot,
+                        line,
+                        column,
+                    });
+                    column += 2;
+                } else {
+                    tokens.push(Token {
+                        token_type: TokenType::Dot,
+                        line,
+                        column,
+                    });
+                    column += 1;
+                }
+            }
             '(' => {
                 tokens.push(Token {
                     token_type: TokenType::OpenParen,
@@ -205,13 +373,95 @@ pub fn tokenize(source: &str, source_path: &str) -> Result<Vec<Token>, String> {
                 column += 1;
             }
             '=' => {
+                chars.next();
+                if let Some('=') = chars.peek() {
+                    // '==' is the equality comparison operator
+                    chars.next();
+                    tokens.push(Token {
+                        token_type: TokenType::EqualEqual,
+                        line,
+                        column,
+                    });
+                    column += 2;
+                } else if let Some('>') = chars.peek() {
+                    chars.next();
+                    tokens.push(Token {
+                        token_type: TokenType::FatArrow,
+                        line,
+                        column,
+                    });
+                    column += 2;
+                } else {
+                    tokens.push(Token {
+                        token_type: TokenType::Equals,
+                        line,
+                        column,
+                    });
+                    column += 1;
+                }
+            }
+            '!' => {
+                chars.next();
+                if let Some('=') = chars.peek() {
+                    chars.next();
+                    tokens.push(Token {
+                        token_type: TokenType::NotEqual,
+                        line,
+                        column,
+                    });
+                    column += 2;
+                } else {
+                    // A standalone '!' is the logical negation operator
+                    tokens.push(Token {
+                        token_type: TokenType::Not,
+                        line,
+                        column,
+                    });
+                    column += 1;
+                }
+            }
+            '&' | '|' => {
+                // Logical operators '&&' and '||' are the symbolic forms of 'and' and 'or'
+                chars.next();
+                if chars.peek() == Some(&c) {
+                    chars.next();
+                    tokens.push(Token {
+                        token_type: if c == '&' { TokenType::And } else { TokenType::Or },
+                        line,
+                        column,
+                    });
+                    column += 2;
+                } else {
+                    return Err(format_error(
+                        source_path,
+                        source,
+                        ErrorPosition { line, column },
+                        format!("Unexpected character '{}'", c),
+                        format!("Use '{}{}' (or '{}') for the logical operator", c, c, if c == '&' { "and" } else { "or" }),
+                    ));
+                }
+            }
+            '<' | '>' => {
+                // Relational operators, optionally followed by '=' ('<=' and '>=')
+                chars.next();
+                let or_equal = chars.peek() == Some(&'=');
+                let token_type = match (c, or_equal) {
+                    ('<', false) => TokenType::Less,
+                    ('<', true) => TokenType::LessEqual,
+                    ('>', false) => TokenType::Greater,
+                    _ => TokenType::GreaterEqual,
+                };
                 tokens.push(Token {
-                    token_type: TokenType::Equals,
+                    token_type,
                     line,
                     column,
                 });
-                chars.next();
-                column += 1;
+                if or_equal {
+                    chars.next();
+                    column += 2;
+                } else {
+                    column += 1;
+                }
             }
             '+' => {
                 tokens.push(Token {
@@ -223,8 +473,55 @@ pub fn tokenize(source: &str, source_path: &str) -> Result<Vec<Token>, String> {
                 column += 1;
             }
             '-' => {
+                chars.next();
+                if let Some('>') = chars.peek() {
+                    // '->' introduces a function's return type
+                    chars.next();
+                    tokens.push(Token {
+                        token_type: TokenType::Arrow,
+                        line,
+                        column,
+                    });
+                    column += 2;
+                } else {
+                    tokens.push(Token {
+                        token_type: TokenType::Minus,
+                        line,
+                        column,
+                    });
+                    column += 1;
+                }
+            }
+            '[' => {
                 tokens.push(Token {
-                    token_type: TokenType::Minus,
+                    token_type: TokenType::OpenBracket,
+                    line,
+                    column,
+                });
+                chars.next();
+                column += 1;
+            }
+            ']' => {
+                tokens.push(Token {
+                    token_type: TokenType::CloseBracket,
+                    line,
+                    column,
+                });
+                chars.next();
+                column += 1;
+            }
+            ',' => {
+                tokens.push(Token {
+                    token_type: TokenType::Comma,
+                    line,
+                    column,
+                });
+                chars.next();
+                column += 1;
+            }
+            ':' => {
+                tokens.push(Token {
+                    token_type: TokenType::Colon,
                     line,
                     column,
                 });
@@ -232,8 +529,28 @@ pub fn tokenize(source: &str, source_path: &str) -> Result<Vec<Token>, String> {
                 column += 1;
             }
             '*' => {
+                chars.next();
+                if let Some('*') = chars.peek() {
+                    // '**' is the exponentiation operator
+                    chars.next();
+                    tokens.push(Token {
+                        token_type: TokenType::StarStar,
+                        line,
+                        column,
+                    });
+                    column += 2;
+                } else {
+                    tokens.push(Token {
+                        token_type: TokenType::Star,
+                        line,
+                        column,
+                    });
+                    column += 1;
+                }
+            }
+            '%' => {
                 tokens.push(Token {
-                    token_type: TokenType::Star,
+                    token_type: TokenType::Percent,
                     line,
                     column,
                 });
@@ -316,82 +633,25 @@ pub fn tokenize(source: &str, source_path: &str) -> Result<Vec<Token>, String> {
             }
             '"' => {
                 // Process string literals enclosed in double quotes
-                let start_column = column;
-                chars.next(); // Skip opening quote
-                column += 1;
-
-                let mut string_content = String::new();
-                let mut escaped = false;
-
-                while let Some(&c) = chars.peek() {
-                    if escaped {
-                        // Handle escape sequences
-                        match c {
-                            'n' => string_content.push('\n'),
-                            't' => string_content.push('\t'),
-                            'r' => string_content.push('\r'),
-                            '\\' => string_content.push('\\'),
-                            '"' => string_content.push('"'),
-                            _ => {
-                                return Err(format_error(
-                                    source_path,
-                                    source,
-                                    ErrorPosition { line, column },
-                                    format!("Invalid escape sequence '\\{}'", c),
-                                    "Valid escape sequences are: \\n, \\t, \\r, \\\", \\\\"
-                                        .to_string(),
-                                ));
-                            }
-                        }
-                        escaped = false;
-                    } else if c == '\\' {
-                        // Start of escape sequence
-                        escaped = true;
-                    } else if c == '"' {
-                        // End of string literal
-                        break;
-                    } else if c == '\n' {
-                        // String literals cannot span multiple lines
-                        return Err(format_error(
-                            source_path,
-                            source,
-                            ErrorPosition {
-                                line,
-                                column: start_column,
-                            },
-                            "Unterminated string literal".to_string(),
-                            "Add a closing quote to complete the string".to_string(),
-                        ));
-                    } else {
-                        // Regular character in string
-                        string_content.push(c);
-                    }
-
-                    chars.next();
-                    column += 1;
-                }
-
-                // Check if the string was properly terminated
-                if chars.peek().is_none() || chars.peek().unwrap() != &'"' {
-                    return Err(format_error(
-                        source_path,
-                        source,
-                        ErrorPosition {
-                            line,
-                            column: start_column,
-                        },
-                        "Unterminated string literal".to_string(),
-                        "Add a closing quote to complete the string".to_string(),
-                    ));
-                }
-
-                chars.next(); // Skip closing quote
-                column += 1;
+                let start = ErrorPosition { line, column };
+                let kind = LiteralKind {
+                    raw: false,
+                    format: matches!(tokens.last(), Some(Token { token_type: TokenType::FormatStringPrefix, .. })),
+                };
+                let (string_content, _) = scan_string_literal(
+                    &mut chars,
+                    kind,
+                    start,
+                    &mut line,
+                    &mut column,
+                    source,
+                    source_path,
+                )?;
 
                 tokens.push(Token {
                     token_type: TokenType::StringLiteral(string_content),
-                    line,
-                    column: start_column,
+                    line: start.line,
+                    column: start.column,
                 });
             }
             '0'..='9' => {
@@ -407,6 +667,12 @@ pub fn tokenize(source: &str, source_path: &str) -> Result<Vec<Token>, String> {
                         chars.next();
                         column += 1;
                     } else if c == '.' && !has_decimal {
+                        // A second '.' means this is a range operator ('0..10'), not a decimal point
+                        let mut lookahead = chars.clone();
+                        lookahead.next();
+                        if lookahead.peek() == Some(&'.') {
+                            break;
+                        }
                         number_str.push(c);
                         has_decimal = true;
                         chars.next();
@@ -416,6 +682,19 @@ pub fn tokenize(source: &str, source_path: &str) -> Result<Vec<Token>, String> {
                     }
                 }
 
+                // Literals without a decimal point are integers, unless they are
+                // too large for 64 bits (they can still be used as a 'num')
+                if !has_decimal
+                    && let Ok(value) = number_str.parse::<i64>()
+                {
+                    tokens.push(Token {
+                        token_type: TokenType::IntegerLiteral(value),
+                        line,
+                        column: start_column,
+                    });
+                    continue;
+                }
+
                 // Parse the collected string as a floating-point number
                 match number_str.parse::<f64>() {
                     Ok(value) => {
@@ -458,51 +737,49 @@ pub fn tokenize(source: &str, source_path: &str) -> Result<Vec<Token>, String> {
                 // Check if the identifier is a reserved keyword
                 match identifier.as_str() {
                     "print" => {
-                        // Handle 'print' keyword and check for format string prefix
                         tokens.push(Token {
                             token_type: TokenType::Print,
                             line,
                             column: start_column,
                         });
-
-                        // Check for '(' and potential format string prefix 'o'
-                        if let Some(&c) = chars.peek() {
-                            if c == '(' {
-                                chars.next();
-                                column += 1;
-
-                                tokens.push(Token {
-                                    token_type: TokenType::OpenParen,
-                                    line,
-                                    column: column - 1,
-                                });
-
-                                if let Some(&c) = chars.peek() {
-                                    if c == 'o' {
-                                        chars.next();
-                                        column += 1;
-
-                                        if let Some(&c) = chars.peek() {
-                                            if c == '"' {
-                                                tokens.push(Token {
-                                                    token_type: TokenType::FormatStringPrefix,
-                                                    line,
-                                                    column: column - 1,
-                                                });
-                                            } else {
-                                                return Err(format_error(
-                                                    source_path,
-                                                    source,
-                                                    ErrorPosition { line, column },
-                                                    "Expected '\"' after 'o' prefix".to_string(),
-                                                    "Format strings should be written as: print(o\"...\")".to_string(),
-                                                ));
-                                            }
-                                        }
-                                    }
-                                }
-                            }
+                    }
+                    "o" if chars.peek() == Some(&'"') => {
+                        // Only an 'o' directly followed by '"' is a format string prefix;
+                        // anything else (e.g. a variable named 'o') is a regular identifier
+                        tokens.push(Token {
+                            token_type: TokenType::FormatStringPrefix,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "r" | "ro" if matches!(chars.peek(), Some('"' | '#')) => {
+                        // An 'r' directly followed by '"' or '#' starts a raw string,
+                        // and 'ro' starts a raw format string
+                        let format = identifier == "ro";
+                        if format {
+                            tokens.push(Token {
+                                token_type: TokenType::FormatStringPrefix,
+                                line,
+                                column: start_column,
+                            });
                         }
+                        let start = ErrorPosition { line, column: start_column };
+                        let kind = LiteralKind { raw: true, format };
+                        let (string_content, _) = scan_string_literal(
+                            &mut chars,
+                            kind,
+                            start,
+                            &mut line,
+                            &mut column,
+                            source,
+                            source_path,
+                        )?;
+
+                        tokens.push(Token {
+                            token_type: TokenType::StringLiteral(string_content),
+                            line: start.line,
+                            column: start.column,
+                        });
                     }
                     "let" => {
                         tokens.push(Token {
@@ -518,6 +795,100 @@ pub fn tokenize(source: &str, source_path: &str) -> Result<Vec<Token>, String> {
                             column: start_column,
                         });
                     }
+                    "int" => {
+                        tokens.push(Token {
+                            token_type: TokenType::Int,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "list" => {
+                        tokens.push(Token {
+                            token_type: TokenType::List,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "map" => {
+                        tokens.push(Token {
+                            token_type: TokenType::Map,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "struct" => {
+                        tokens.push(Token {
+                            token_type: TokenType::Struct,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "enum" => {
+                        tokens.push(Token {
+                            token_type: TokenType::Enum,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "const" => {
+                        tokens.push(Token {
+                            token_type: TokenType::Const,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "match" => {
+                        tokens.push(Token {
+                            token_type: TokenType::Match,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "bool" => {
+                        tokens.push(Token {
+                            token_type: TokenType::Bool,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "true" => {
+                        tokens.push(Token {
+                            token_type: TokenType::True,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "false" => {
+                        tokens.push(Token {
+                            token_type: TokenType::False,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "and" => {
+                        // Readable logical operator keyword 'and'
+                        tokens.push(Token {
+                            token_type: TokenType::And,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "or" => {
+                        // Readable logical operator keyword 'or'
+                        tokens.push(Token {
+                            token_type: TokenType::Or,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "not" => {
+                        // Readable logical operator keyword 'not'
+                        tokens.push(Token {
+                            token_type: TokenType::Not,
+                            line,
+                            column: start_column,
+                        });
+                    }
                     "plus" => {
                         // Support for readable operator keyword 'plus'
                         tokens.push(Token {
@@ -550,6 +921,22 @@ pub fn tokenize(source: &str, source_path: &str) -> Result<Vec<Token>, String> {
                             column: start_column,
                         });
                     }
+                    "mod" => {
+                        // Support for readable operator keyword 'mod'
+                        tokens.push(Token {
+                            token_type: TokenType::Percent,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "pow" => {
+                        // Support for readable operator keyword 'pow'
+                        tokens.push(Token {
+                            token_type: TokenType::StarStar,
+                            line,
+                            column: start_column,
+                        });
+                    }
                     "newfn" => {
                         tokens.push(Token {
                             token_type: TokenType::NewFn,
@@ -557,6 +944,69 @@ pub fn tokenize(source: &str, source_path: &str) -> Result<Vec<Token>, String> {
                             column: start_column,
                         });
                     }
+                    "if" => {
+                        tokens.push(Token {
+                            token_type: TokenType::If,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "else" => {
+                        tokens.push(Token {
+                            token_type: TokenType::Else,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "while" => {
+                        tokens.push(Token {
+                            token_type: TokenType::While,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "break" => {
+                        tokens.push(Token {
+                            token_type: TokenType::Break,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "continue" => {
+                        tokens.push(Token {
+                            token_type: TokenType::Continue,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "for" => {
+                        tokens.push(Token {
+                            token_type: TokenType::For,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "in" => {
+                        tokens.push(Token {
+                            token_type: TokenType::In,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "step" => {
+                        tokens.push(Token {
+                            token_type: TokenType::Step,
+                            line,
+                            column: start_column,
+                        });
+                    }
+                    "return" => {
+                        tokens.push(Token {
+                            token_type: TokenType::Return,
+                            line,
+                            column: start_column,
+                        });
+                    }
                     "callfn" => {
                         tokens.push(Token {
                             token_type: TokenType::CallFn,
@@ -589,10 +1039,377 @@ pub fn tokenize(source: &str, source_path: &str) -> Result<Vec<Token>, String> {
 
     // Add EOF token to mark the end of input
     tokens.push(Token {
-        token_type: TokenType::EOF,
+        token_type: TokenType::Eof,
         line,
         column,
     });
 
     Ok(tokens)
 }
+
+/// The kind of a string literal, as given by its prefix.
+#[derive(Clone, Copy)]
+struct LiteralKind {
+    /// Whether escape sequences are left unprocessed (an 'r' prefix)
+    raw: bool,
+
+    /// Whether the literal is a format string (an 'o' prefix), whose braces may
+    /// hold expressions with string literals of their own
+    format: bool,
+}
+
+/// Scans a string literal, from its opening quotes (or the '#'s of a raw
+/// string) up to and including its closing delimiter.
+///
+/// Regular strings stay on one line and process escape sequences. Triple-quoted
+/// strings may span lines and have their common indentation stripped. Raw
+/// strings keep backslashes as written, and may be wrapped in '#'s so that they
+/// can contain '"'. The 'r' of a raw string must already have been consumed.
+///
+/// In a format string, quotes inside the braces of an expression belong to
+/// string literals in the expression, so they don't end the format string.
+/// Those literals are kept as written, to be read when the expression is.
+///
+/// # Arguments
+///
+/// * `chars` - The characters of the source, positioned after any prefix
+/// * `kind` - The kind of literal, as given by its prefix
+/// * `start` - The position of the literal, including its prefix (for error reporting)
+/// * `line` - The current line, advanced past the literal
+/// * `column` - The current column, advanced past the literal
+/// * `source` - The complete source code (for error reporting)
+/// * `source_path` - The path to the source file (for error reporting)
+///
+/// # Returns
+///
+/// A Result containing either:
+/// * The string's content, with the source position of each of its characters
+/// * A formatted error message if the literal is malformed
+fn scan_string_literal(
+    chars: &mut Peekable<Chars>,
+    kind: LiteralKind,
+    start: ErrorPosition,
+    line: &mut usize,
+    column: &mut usize,
+    source: &str,
+    source_path: &str,
+) -> Result<(String, Vec<ErrorPosition>), String> {
+    let LiteralKind { raw, format } = kind;
+    let mut hashes = 0;
+    while raw && chars.peek() == Some(&'#') {
+        chars.next();
+        *column += 1;
+        hashes += 1;
+    }
+    if chars.peek() != Some(&'"') {
+        return Err(format_error(
+            source_path,
+            source,
+            start,
+            "Invalid raw string literal".to_string(),
+            "Raw strings are written r\"...\" or r#\"...\"#".to_string(),
+        ));
+    }
+
+    let mut ahead = chars.clone();
+    ahead.next();
+    let triple = ahead.next() == Some('"') && ahead.next() == Some('"');
+    let quotes = if triple { "\"\"\"" } else { "\"" };
+    let closing = format!("{}{}", quotes, "#".repeat(hashes));
+    for _ in 0..quotes.len() {
+        chars.next();
+        *column += 1;
+    }
+
+    // Collect the characters as written, each with whether it is part of a
+    // string literal nested in a format string's expression; an escaped quote
+    // doesn't end the string
+    let mut written = Vec::new();
+    let mut escaped = false;
+    let mut depth = 0;
+    let mut nested = false;
+    let mut open_brace = start;
+    while let Some(&c) = chars.peek() {
+        if c == '"'
+            && !escaped
+            && !nested
+            && depth == 0
+            && chars.clone().take(closing.len()).eq(closing.chars())
+        {
+            break;
+        }
+        if c == '\n' && !triple {
+            // Only triple-quoted strings can span multiple lines
+            break;
+        }
+        let position = ErrorPosition {
+            line: *line,
+            column: *column,
+        };
+        chars.next();
+        if c == '\n' {
+            *line += 1;
+            *column = 1;
+        } else {
+            *column += 1;
+        }
+        if c == '\r' && chars.peek() == Some(&'\n') {
+            // Windows line endings become plain line breaks
+            continue;
+        }
+        written.push((c, position, nested));
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' if nested || !raw => escaped = true,
+            '"' if nested => nested = false,
+            _ if nested || !format => {}
+            '"' if depth > 0 => nested = true,
+            '{' if depth == 0 && chars.peek() == Some(&'{') => {
+                // '{{' is a literal brace
+                written.push(('{', ErrorPosition { line: *line, column: *column }, false));
+                chars.next();
+                *column += 1;
+            }
+            '{' => {
+                if depth == 0 {
+                    open_brace = position;
+                }
+                depth += 1;
+            }
+            '}' if depth > 0 => depth -= 1,
+            _ => {}
+        }
+    }
+
+    // Check if the string was properly terminated
+    if depth > 0 && (chars.peek().is_none() || chars.peek() == Some(&'\n') && !triple) {
+        return Err(format_error(
+            source_path,
+            source,
+            open_brace,
+            "Unclosed '{' in format string".to_string(),
+            "Ensure all braces are properly closed, or write '{{' to print a literal '{'".to_string(),
+        ));
+    }
+    if chars.peek().is_none() || chars.peek() == Some(&'\n') && !triple {
+        let hint = if closing == "\"" {
+            "Add a closing quote to complete the string".to_string()
+        } else {
+            format!("Add a closing '{}' to complete the string", closing)
+        };
+        return Err(format_error(
+            source_path,
+            source,
+            start,
+            "Unterminated string literal".to_string(),
+            hint,
+        ));
+    }
+    for _ in 0..closing.len() {
+        chars.next();
+        *column += 1;
+    }
+
+    if triple {
+        written = strip_indentation(written);
+    }
+
+    let mut content = String::new();
+    let mut positions = Vec::new();
+    let mut written = written.into_iter();
+    while let Some((c, position, verbatim)) = written.next() {
+        if c != '\\' || raw || verbatim {
+            content.push(c);
+            positions.push(position);
+            continue;
+        }
+
+        // Handle escape sequences
+        match written.next() {
+            Some(('n', _, _)) => content.push('\n'),
+            Some(('t', _, _)) => content.push('\t'),
+            Some(('r', _, _)) => content.push('\r'),
+            Some(('\\', _, _)) => content.push('\\'),
+            Some(('"', _, _)) => content.push('"'),
+            escape => {
+                // A backslash at the end of a line escapes nothing printable
+                let (sequence, escape_position) = match escape {
+                    Some((escape, escape_position, _)) if escape != '\n' => (escape.to_string(), escape_position),
+                    _ => (String::new(), position),
+                };
+                return Err(format_error(
+                    source_path,
+                    source,
+                    escape_position,
+                    format!("Invalid escape sequence '\\{}'", sequence),
+                    "Valid escape sequences are: \\n, \\t, \\r, \\\", \\\\ (raw strings don't need escapes)"
+                        .to_string(),
+                ));
+            }
+        }
+        positions.push(position);
+    }
+
+    Ok((content, positions))
+}
+
+/// Strips the common indentation from the lines of a triple-quoted string.
+///
+/// Blank lines directly after the opening quotes and before the closing quotes
+/// are dropped, so the quotes can sit on lines of their own. Text on the
+/// opening line is kept as written, and blank lines don't count towards the
+/// common indentation.
+fn strip_indentation(written: Vec<(char, ErrorPosition, bool)>) -> Vec<(char, ErrorPosition, bool)> {
+    // Split into lines, keeping each line break to put back between them
+    let mut lines = vec![Vec::new()];
+    let mut breaks = Vec::new();
+    for (c, position, verbatim) in written {
+        if c == '\n' {
+            breaks.push((c, position, verbatim));
+            lines.push(Vec::new());
+        } else if let Some(current) = lines.last_mut() {
+            current.push((c, position, verbatim));
+        }
+    }
+    if breaks.is_empty() {
+        return lines.remove(0);
+    }
+
+    let is_blank = |line: &Vec<(char, ErrorPosition, bool)>| line.iter().all(|(c, _, _)| *c == ' ' || *c == '\t');
+    let indented_from = if is_blank(&lines[0]) {
+        lines.remove(0);
+        breaks.remove(0);
+        0
+    } else {
+        1
+    };
+    if lines.len() > 1 && lines.last().is_some_and(is_blank) {
+        lines.pop();
+        breaks.pop();
+    }
+
+    // The longest run of leading whitespace shared by all non-blank lines
+    let mut common: Option<Vec<char>> = None;
+    for line in lines[indented_from..].iter().filter(|line| !is_blank(line)) {
+        let indentation = line.iter().map(|(c, _, _)| *c).take_while(|c| *c == ' ' || *c == '\t');
+        common = Some(match common {
+            None => indentation.collect(),
+            Some(common) => common.into_iter().zip(indentation).take_while(|(a, b)| a == b).map(|(a, _)| a).collect(),
+        });
+    }
+    let width = common.map_or(0, |common| common.len());
+
+    let mut result = Vec::new();
+    for (index, line) in lines.into_iter().enumerate() {
+        if index >= indented_from && !is_blank(&line) {
+            result.extend(line.into_iter().skip(width));
+        } else if index < indented_from {
+            result.extend(line);
+        }
+        if let Some(line_break) = breaks.get(index) {
+            result.push(*line_break);
+        }
+    }
+    result
+}
+
+/// Maps each character of the string literal that starts at the given
+/// position to its position in the source.
+///
+/// Escape sequences and stripped indentation mean that a literal's content
+/// doesn't line up with its source text, so the literal is scanned again.
+/// Returns an empty list if no string literal starts at the position.
+pub fn literal_positions(source: &str, start: ErrorPosition, format: bool) -> Vec<ErrorPosition> {
+    let offset: usize = source.split_inclusive('\n').take(start.line - 1).map(str::len).sum();
+    let mut chars = source[offset..].chars();
+    for _ in 1..start.column {
+        chars.next();
+    }
+    let mut chars = chars.peekable();
+    let mut line = start.line;
+    let mut column = start.column;
+    let raw = chars.peek() == Some(&'r');
+    if raw {
+        chars.next();
+        column += 1;
+        // The 'o' of a raw format string 'ro"..."'
+        if format && chars.peek() == Some(&'o') {
+            chars.next();
+            column += 1;
+        }
+    }
+    let kind = LiteralKind { raw, format };
+    scan_string_literal(&mut chars, kind, start, &mut line, &mut column, source, "")
+        .map(|(_, positions)| positions)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAIN: LiteralKind = LiteralKind { raw: false, format: false };
+
+    /// Scans a literal written at the start of the source, after any prefix,
+    /// returning its content and the (line, column) of each character.
+    fn scan(source: &str, kind: LiteralKind) -> Result<(String, Vec<(usize, usize)>), String> {
+        let start = ErrorPosition { line: 1, column: 1 };
+        let (mut line, mut column) = (1, 1);
+        let mut chars = source.chars().peekable();
+        let (content, positions) = scan_string_literal(&mut chars, kind, start, &mut line, &mut column, source, "test.vl")?;
+        Ok((content, positions.iter().map(|position| (position.line, position.column)).collect()))
+    }
+
+    #[test]
+    fn escapes_map_to_their_backslash() {
+        let (content, positions) = scan("\"a\\tb\"", PLAIN).unwrap();
+        assert_eq!(content, "a\tb");
+        assert_eq!(positions, [(1, 2), (1, 3), (1, 5)]);
+    }
+
+    #[test]
+    fn triple_quoted_strings_strip_common_indentation() {
+        let (content, positions) = scan("\"\"\"\n    a\n\n      b\n    \"\"\"", PLAIN).unwrap();
+        assert_eq!(content, "a\n\n  b");
+        assert_eq!(positions[0], (2, 5));
+        assert_eq!(positions[3], (4, 5));
+
+        // Text on the opening line is kept as written
+        let (content, _) = scan("\"\"\"x\n  y\n  \"\"\"", PLAIN).unwrap();
+        assert_eq!(content, "x\ny");
+    }
+
+    #[test]
+    fn windows_line_endings_become_line_breaks() {
+        let (content, _) = scan("\"\"\"\r\n  a\r\n  b\r\n  \"\"\"", PLAIN).unwrap();
+        assert_eq!(content, "a\nb");
+    }
+
+    #[test]
+    fn hashed_raw_strings_keep_quotes_and_backslashes() {
+        let raw = LiteralKind { raw: true, format: false };
+        let (content, positions) = scan("#\"say \"hi\" \\n\"#", raw).unwrap();
+        assert_eq!(content, "say \"hi\" \\n");
+        assert_eq!(positions[0], (1, 3));
+        assert!(scan("#\"unfinished\"", raw).unwrap_err().contains("Add a closing '\"#'"));
+    }
+
+    #[test]
+    fn format_strings_keep_nested_string_literals() {
+        let format = LiteralKind { raw: false, format: true };
+        let (content, _) = scan("\"{a + \" \\\"\" + b}!\"", format).unwrap();
+        assert_eq!(content, "{a + \" \\\"\" + b}!");
+        assert!(scan("\"{a + \"}\"", format).unwrap_err().contains("Unclosed '{' in format string"));
+    }
+
+    #[test]
+    fn literal_positions_skip_prefixes() {
+        let source = "print(ro\"C:\\{name}\")";
+        let positions = literal_positions(source, ErrorPosition { line: 1, column: 7 }, true);
+        assert_eq!(positions.len(), "C:\\{name}".len());
+        assert_eq!((positions[0].line, positions[0].column), (1, 10));
+    }
+}
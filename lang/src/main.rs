@@ -38,14 +38,26 @@ fn main() {
     // Collect command-line arguments
     let args: Vec<String> = env::args().collect();
 
-    // Check if a source file was provided
-    if args.len() < 2 {
-        println!("Usage: vortlang <source_file>");
-        exit(1);
+    // Separate options from the source file path
+    let mut checked = false;
+    let mut source_path = None;
+    for arg in &args[1..] {
+        match arg.as_str() {
+            // Checked mode turns integer overflow into a runtime error
+            "--checked" => checked = true,
+            option if option.starts_with("--") => {
+                eprintln!("Unknown option '{}'", option);
+                exit(1);
+            }
+            path => source_path = Some(path),
+        }
     }
 
-    // Get the source file path from arguments
-    let source_path = &args[1];
+    // Check if a source file was provided
+    let Some(source_path) = source_path else {
+        println!("Usage: vortlang <source_file> [--checked]");
+        exit(1);
+    };
     
     // Read the source code from the file
     let source_code = match fs::read_to_string(source_path) {
@@ -74,7 +86,7 @@ fn main() {
     let start_time = Instant::now();
 
     // Compile the source code
-    match compile(&source_code, source_path_stem, output_path) {
+    match compile(&source_code, source_path_stem, output_path, checked) {
         Ok(_) => {
             let duration = start_time.elapsed();
             let formatted_duration = format_duration(duration);
@@ -102,28 +114,23 @@ fn main() {
 /// * `source` - The source code to compile
 /// * `source_path` - The path to the source file (for error reporting)
 /// * `output_path` - The path where the output executable should be placed
+/// * `checked` - Whether integer overflow should be a runtime error
 ///
 /// # Returns
 ///
 /// A Result indicating success or an error message
-fn compile(source: &str, source_path: &str, output_path: &str) -> Result<(), String> {
+fn compile(source: &str, source_path: &str, output_path: &str, checked: bool) -> Result<(), String> {
     // Step 1: Lexical analysis (tokenization)
     // Convert the source code into a stream of tokens
-    let tokens = match lexer::tokenize(source, source_path) {
-        Ok(tokens) => tokens,
-        Err(e) => return Err(e),
-    };
+    let tokens = lexer::tokenize(source, source_path)?;
 
     // Step 2: Parsing
     // Convert the token stream into an Abstract Syntax Tree (AST)
-    let ast = match parser::parse(tokens) {
-        Ok(ast) => ast,
-        Err(e) => return Err(e),
-    };
+    let ast = parser::parse(tokens, source, source_path)?;
 
     // Step 3: Static analysis
     // Check for semantic errors, dead code, and optimization opportunities
-    let (ast, warnings) = ast::analyze(ast);
+    let (ast, warnings) = ast::analyze(ast, source, source_path)?;
     
     // Display any warnings that were found
     for warning in warnings {
@@ -140,10 +147,16 @@ fn compile(source: &str, source_path: &str, output_path: &str) -> Result<(), Str
         .map_err(|e| format!("Failed to write temporary C file: {}", e))?;
 
     // Step 6: Compile the C code to an executable using GCC
-    let output = Command::new("gcc")
-        .arg(&temp_c_file)
+    let mut gcc = Command::new("gcc");
+    gcc.arg(&temp_c_file)
         .arg("-o")
         .arg(format!("{}.exe", output_path))
+        .arg("-lm"); // Link the math library used by operators and math built-ins
+    if checked {
+        // Enables the overflow checks of the integer runtime
+        gcc.arg("-DVORTLANG_CHECKED=1");
+    }
+    let output = gcc
         .output()
         .map_err(|e| format!("Failed to execute gcc: {}", e))?;
 
@@ -11,9 +11,20 @@
 // for each non-terminal in the grammar. Error reporting includes contextual
 // information to help users understand and fix syntax issues.
 
-use crate::ast::{BinaryOperator, Expression, NumExpression, Statement, FormatPart};
+use crate::ast::{Alignment, BinaryOperator, ComparisonOperator, Condition, Expression, IntExpression, ListElement, ListExpression, MapExpression, MapValue, MatchArm, MathFunction, Pattern, StringFunction, StructExpression, EnumExpression, FieldAccess, NumExpression, Statement, FormatPart, FormatSpec, NumberStyle, Type, TypedExpression};
 use crate::errors::{ErrorPosition, format_error};
-use crate::lexer::{Token, TokenType};
+use crate::lexer::{Token, TokenType, literal_positions, tokenize_fragment};
+use std::collections::{HashMap, HashSet};
+
+/// The parameter list and return type of a function, used to check calls.
+#[derive(Clone)]
+struct FunctionSignature {
+    /// Names and types of the parameters, in declaration order
+    parameters: Vec<(String, Type)>,
+
+    /// The declared return type, or `None` if the function returns nothing
+    return_type: Option<Type>,
+}
 
 /// The parser structure that manages the token stream and builds the AST.
 ///
@@ -35,6 +46,55 @@ pub struct Parser {
     
     /// Flag to indicate if parsing is currently inside a function body
     in_function: bool,
+
+    /// Number of enclosing `if`/`else`/`while`/`for` blocks and match arms at the current position
+    block_depth: usize,
+
+    /// Flag to indicate if parsing is currently inside a loop body
+    in_loop: bool,
+
+    /// Variables visible at the current position, one map of names to types
+    /// per scope with the global scope first. Types are used to tell string
+    /// and numerical operands apart where the syntax alone is ambiguous.
+    scopes: Vec<HashMap<String, Type>>,
+
+    /// Names of local variables whose scope has already ended, used to explain
+    /// why such a variable can't be referenced
+    ended_locals: HashSet<String>,
+
+    /// Signatures of all defined functions, used to check calls
+    functions: HashMap<String, FunctionSignature>,
+
+    /// Names of the functions whose definition has been parsed so far, used to
+    /// reject a second definition of the same function
+    defined_functions: HashSet<String>,
+
+    /// Fields of all defined structs, used to check struct literals and field accesses
+    structs: HashMap<String, Vec<(String, Type)>>,
+
+    /// Variants of all defined enums with the types of the values they carry,
+    /// used to check variants and match patterns
+    enums: HashMap<String, Vec<(String, Vec<Type>)>>,
+
+    /// Names of the structs and enums whose definition has been parsed so far.
+    /// A field or variant can only hold a struct or enum defined before it.
+    defined_types: HashSet<String>,
+
+    /// Return type of the function currently being parsed, if any
+    return_type: Option<Type>,
+
+    /// Names of the constants declared so far, which can't be assigned or redeclared
+    constants: HashSet<String>,
+
+    /// Names of all constants in the program, including those declared
+    /// further down, which function bodies can already use
+    all_constants: HashSet<String>,
+
+    /// Names of the global variables and constants whose declaration has been
+    /// parsed so far. The global scope also holds globals declared further
+    /// down, for function bodies; top-level code can only use the globals
+    /// declared before it, and only conflicts with those are reported.
+    declared_globals: HashSet<String>,
 }
 
 impl Parser {
@@ -50,12 +110,48 @@ impl Parser {
     ///
     /// A new Parser instance ready to begin parsing
     pub fn new(tokens: Vec<Token>, source: String, source_path: String) -> Self {
+        let mut globals = collect_global_types(&tokens);
+        let mut functions = collect_function_signatures(&tokens);
+        let mut structs = collect_struct_definitions(&tokens);
+        let mut enums = collect_enum_definitions(&tokens);
+
+        // The scans read every type name other than the built-in ones as a
+        // struct; turn the names of enums into enum types
+        let enum_names: HashSet<String> = enums.keys().cloned().collect();
+        let resolve = |var_type: &mut Type| {
+            if let Type::Struct(name) = var_type
+                && enum_names.contains(name)
+            {
+                *var_type = Type::Enum(name.clone());
+            }
+        };
+        globals.values_mut().for_each(resolve);
+        for signature in functions.values_mut() {
+            signature.parameters.iter_mut().map(|(_, param_type)| param_type).for_each(resolve);
+            signature.return_type.iter_mut().for_each(resolve);
+        }
+        structs.values_mut().flatten().map(|(_, field_type)| field_type).for_each(resolve);
+        enums.values_mut().flatten().flat_map(|(_, value_types)| value_types).for_each(resolve);
+
         Parser {
             tokens,
             current: 0,
             source,
             source_path,
             in_function: false,
+            block_depth: 0,
+            in_loop: false,
+            scopes: vec![globals],
+            ended_locals: HashSet::new(),
+            functions,
+            defined_functions: HashSet::new(),
+            structs,
+            enums,
+            defined_types: HashSet::new(),
+            return_type: None,
+            constants: HashSet::new(),
+            all_constants: HashSet::new(),
+            declared_globals: HashSet::new(),
         }
     }
 
@@ -166,7 +262,7 @@ impl Parser {
     ///
     /// `true` if at the end of the token stream, `false` otherwise
     fn is_at_end(&self) -> bool {
-        matches!(self.peek().token_type, TokenType::EOF)
+        matches!(self.peek().token_type, TokenType::Eof)
     }
 
     /// Skips any consecutive newline tokens in the token stream.
@@ -178,6 +274,154 @@ impl Parser {
         }
     }
 
+    /// Looks up the type of a variable visible at the current position.
+    ///
+    /// Scopes are searched from the innermost outwards, so locals shadow
+    /// variables of the same name in enclosing scopes. Outside of function
+    /// bodies, a global is only visible once its declaration has been parsed.
+    fn variable_type(&self, name: &str) -> Option<Type> {
+        self.scopes.iter().enumerate().rev().find_map(|(index, scope)| {
+            if index == 0 && !self.in_function && !self.declared_globals.contains(name) {
+                return None;
+            }
+            scope.get(name).cloned()
+        })
+    }
+
+    /// Opens a new scope for a block, function body or loop.
+    fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    /// Closes the innermost scope, making its variables inaccessible.
+    fn pop_scope(&mut self) {
+        if let Some(scope) = self.scopes.pop() {
+            self.ended_locals.extend(scope.into_keys());
+        }
+    }
+
+    /// Declares a variable in the innermost scope.
+    ///
+    /// A declaration may shadow a variable from an enclosing scope, but a
+    /// local can't be declared twice in the same scope. Globals may be
+    /// redeclared (which acts as an assignment) as long as the type is unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// * `name_token` - The identifier token naming the variable
+    /// * `var_type` - The declared type
+    ///
+    /// # Returns
+    ///
+    /// A Result indicating success or a formatted error message
+    fn declare_variable(&mut self, name_token: &Token, var_type: Type) -> Result<(), String> {
+        let name = match &name_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+        let is_global = self.scopes.len() == 1;
+        if is_global && self.constants.contains(&name) {
+            return Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: name_token.line,
+                    column: name_token.column,
+                },
+                format!("'{}' is a constant and can't be redeclared", name),
+                "Choose a different name for the variable".to_string(),
+            ));
+        }
+        let scope = self.scopes.last_mut().unwrap();
+
+        let conflict = match scope.get(&name) {
+            Some(existing) if !is_global => Some(format!(
+                "Variable '{}' is already declared in this scope as '{}'",
+                name,
+                type_name(existing)
+            )),
+            Some(existing) if *existing != var_type && self.declared_globals.contains(&name) => Some(format!(
+                "Global variable '{}' is already declared as '{}'",
+                name,
+                type_name(existing)
+            )),
+            _ => None,
+        };
+        if let Some(message) = conflict {
+            return Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: name_token.line,
+                    column: name_token.column,
+                },
+                message,
+                "Assign to the existing variable or choose a different name".to_string(),
+            ));
+        }
+
+        scope.insert(name.clone(), var_type);
+        if is_global {
+            self.declared_globals.insert(name);
+        }
+        Ok(())
+    }
+
+    /// Resolves a variable reference, reporting an error if no variable of that
+    /// name is visible at the current position.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The referenced variable name
+    /// * `token` - The token of the reference (for the error position)
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * The type of the variable
+    /// * A formatted error message if the variable is not in scope
+    fn resolve_variable(&self, name: &str, token: &Token) -> Result<Type, String> {
+        if let Some(var_type) = self.variable_type(name) {
+            return Ok(var_type);
+        }
+
+        let (message, hint) = if self.ended_locals.contains(name) {
+            (
+                format!("Variable '{}' is not in scope here", name),
+                "Variables declared inside a block or function can only be used within it; declare it before the block to use it afterwards".to_string(),
+            )
+        } else {
+            (
+                format!("Undefined variable '{}'", name),
+                "Declare the variable with 'let', 'num', 'int' or 'bool' before using it".to_string(),
+            )
+        };
+        Err(format_error(
+            &self.source_path,
+            &self.source,
+            ErrorPosition {
+                line: token.line,
+                column: token.column,
+            },
+            message,
+            hint,
+        ))
+    }
+
+    /// Reports a variable that is used where a value of another type is required.
+    fn variable_type_error(&self, name: &str, found: &Type, expected: &Type, token: &Token) -> String {
+        format_error(
+            &self.source_path,
+            &self.source,
+            ErrorPosition {
+                line: token.line,
+                column: token.column,
+            },
+            format!("Variable '{}' is {}, but {} is expected here", name, a_type_name(found), a_type_name(expected)),
+            conversion_hint(found, expected),
+        )
+    }
+
     /// Parses the entire token stream into an AST.
     ///
     /// # Returns
@@ -188,6 +432,8 @@ impl Parser {
     pub fn parse(&mut self) -> Result<Vec<Statement>, String> {
         let mut statements = Vec::new();
 
+        self.collect_constant_types();
+
         self.skip_newlines();
 
         // Parse statements until we reach the end of the file
@@ -199,6 +445,43 @@ impl Parser {
         Ok(statements)
     }
 
+    /// Adds every constant of the program to the global scope before parsing,
+    /// so that function bodies can use constants declared after the function,
+    /// like they can use globals.
+    ///
+    /// The type of a constant depends on its value, so each top-level 'const'
+    /// statement is parsed ahead of time. Errors are left to be reported when
+    /// the statement is parsed in order.
+    fn collect_constant_types(&mut self) {
+        let globals = self.scopes[0].clone();
+        let mut depth = 0usize;
+        for index in 0..self.tokens.len() {
+            match self.tokens[index].token_type {
+                TokenType::OpenBrace => depth += 1,
+                TokenType::CloseBrace => depth = depth.saturating_sub(1),
+                TokenType::Const if depth == 0 => {
+                    self.current = index + 1;
+                    let _ = self.const_statement();
+                }
+                _ => {}
+            }
+        }
+
+        let constant_types: Vec<(String, Type)> = self
+            .constants
+            .iter()
+            .filter_map(|name| Some((name.clone(), self.scopes[0].get(name)?.clone())))
+            .collect();
+        self.scopes[0] = globals;
+        for (name, const_type) in constant_types {
+            self.all_constants.insert(name.clone());
+            self.scopes[0].insert(name, const_type);
+        }
+        self.constants.clear();
+        self.declared_globals.clear();
+        self.current = 0;
+    }
+
     /// Parses a single statement.
     ///
     /// # Returns
@@ -208,10 +491,23 @@ impl Parser {
     /// * A formatted error message if parsing fails
     fn statement(&mut self) -> Result<Statement, String> {
         // Check for standalone assignments first
-        if let TokenType::Identifier(_) = self.peek().token_type {
-            let next_pos = self.current + 1;
-            if next_pos < self.tokens.len() && matches!(self.tokens[next_pos].token_type, TokenType::Equals) {
-                return self.assignment_statement();
+        if let TokenType::Identifier(name) = &self.peek().token_type {
+            match self.tokens.get(self.current + 1).map(|token| &token.token_type) {
+                Some(TokenType::Equals) => return self.assignment_statement(),
+                Some(TokenType::Dot) => return self.field_assignment_statement(),
+                Some(TokenType::Identifier(_)) if self.structs.contains_key(name) => return self.struct_variable_statement(),
+                Some(TokenType::Identifier(_)) if self.enums.contains_key(name) => return self.enum_variable_statement(),
+                Some(TokenType::OpenBracket) if matches!(self.variable_type(name), Some(Type::Map(_))) => {
+                    return self.map_insert_statement();
+                }
+                Some(TokenType::OpenBracket) => return self.list_element_assignment(),
+                Some(TokenType::OpenParen) if name == "push" || name == "pop" => return self.list_call_statement(),
+                Some(TokenType::OpenParen) if name == "remove" => return self.map_remove_statement(),
+                Some(TokenType::OpenParen) if matches!(name.as_str(), "write_file" | "append_file" | "delete_file") => {
+                    return self.file_statement();
+                }
+                Some(TokenType::OpenParen) if name == "exit" => return self.exit_statement(),
+                _ => {}
             }
         }
         
@@ -221,17 +517,38 @@ impl Parser {
             self.let_statement()
         } else if self.match_token(TokenType::Num) {
             self.num_statement()
+        } else if self.match_token(TokenType::Int) {
+            self.int_statement()
+        } else if self.match_token(TokenType::Bool) {
+            self.bool_statement()
+        } else if self.match_token(TokenType::List) {
+            self.list_statement()
+        } else if self.match_token(TokenType::Map) {
+            self.map_statement()
+        } else if self.match_token(TokenType::Struct) {
+            self.struct_definition()
+        } else if self.match_token(TokenType::Enum) {
+            self.enum_definition()
+        } else if self.match_token(TokenType::Const) {
+            self.const_statement()
+        } else if self.match_token(TokenType::Match) {
+            self.match_statement()
+        } else if self.match_token(TokenType::If) {
+            self.if_statement()
+        } else if self.match_token(TokenType::While) {
+            self.while_statement()
+        } else if self.match_token(TokenType::For) {
+            self.for_statement()
+        } else if self.match_token(TokenType::Break) || self.match_token(TokenType::Continue) {
+            self.loop_control_statement()
         } else if self.match_token(TokenType::NewFn) {
             self.function_definition()
+        } else if self.match_token(TokenType::Return) {
+            self.return_statement()
         } else if self.match_token(TokenType::CallFn) {
-            let name_token = self.consume(TokenType::Identifier("".to_string()), "Expected function name after 'callfn'")?;
-            let name = match &name_token.token_type {
-                TokenType::Identifier(name) => name.clone(),
-                _ => unreachable!(),
-            };
-            self.consume(TokenType::OpenParen, "Expected '(' after function name")?;
-            self.consume(TokenType::CloseParen, "Expected ')' after '('")?;
-            Ok(Statement::FunctionCall(name))
+            // A call used as a statement may return any type; the value is discarded
+            let (name, args, _) = self.function_call()?;
+            Ok(Statement::FunctionCall(name, args))
         } else {
             let token = self.peek().clone();
             Err(format_error(
@@ -242,14 +559,15 @@ impl Parser {
                     column: token.column,
                 },
                 "Expected statement".to_string(),
-                "Valid statements are 'print', 'let', 'num', 'newfn', or 'callfn'".to_string(),
+                "Valid statements are 'print', 'let', 'num', 'int', 'bool', 'const', 'list', 'push', 'pop', 'map', 'remove', 'write_file', 'append_file', 'delete_file', 'struct', 'enum', 'match', 'if', 'while', 'for', 'break', 'continue', 'return', 'exit', 'newfn', or 'callfn'".to_string(),
             ))
         }
     }
 
     /// Parses a function definition statement.
     /// Supports two forms:
-    /// - Regular: 'newfn fn functionname() { ... }'
+    /// - Regular: 'newfn fn functionname(a: num, b: str) -> num { ... }', where the
+    ///   parameters and the return type are optional
     /// - C code: 'newfn $c functionname() {{{ C code }}}'
     fn function_definition(&mut self) -> Result<Statement, String> {
         if self.in_function {
@@ -265,13 +583,27 @@ impl Parser {
             ));
         }
 
+        if self.block_depth > 0 {
+            return Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: self.previous().line,
+                    column: self.previous().column,
+                },
+                "Function definitions are not allowed inside blocks".to_string(),
+                "Move the function definition to the top level of the program".to_string(),
+            ));
+        }
+
         if self.match_token(TokenType::DollarC) {
             // C code function: 'newfn $c fnname() {{{ C code }}}'
-            let name_token = self.consume(TokenType::Identifier("".to_string()), "Expected function name after '$c'")?;
+            let name_token = self.consume(TokenType::Identifier("".to_string()), "Expected function name after '$c'")?.clone();
             let name = match &name_token.token_type {
                 TokenType::Identifier(name) => name.clone(),
                 _ => unreachable!(),
             };
+            self.define_function(&name, &name_token)?;
             self.consume(TokenType::OpenParen, "Expected '(' after function name")?;
             self.consume(TokenType::CloseParen, "Expected ')' after '('")?;
             self.skip_newlines(); // Allow newlines before C code block
@@ -284,449 +616,5357 @@ impl Parser {
         } else {
             // Regular function: 'newfn fn functionname() { ... }'
             self.consume(TokenType::Identifier("fn".to_string()), "Expected 'fn' after 'newfn'")?;
-            let name_token = self.consume(TokenType::Identifier("".to_string()), "Expected function name")?;
+            let name_token = self.consume(TokenType::Identifier("".to_string()), "Expected function name")?.clone();
             let name = match &name_token.token_type {
                 TokenType::Identifier(name) => name.clone(),
                 _ => unreachable!(),
             };
+            self.define_function(&name, &name_token)?;
             self.consume(TokenType::OpenParen, "Expected '(' after function name")?;
-            self.consume(TokenType::CloseParen, "Expected ')' after '('")?;
-            self.consume(TokenType::OpenBrace, "Expected '{' to start function body")?;
+            let mut parameters: Vec<(String, Type)> = Vec::new();
+            if !self.check(&TokenType::CloseParen) {
+                loop {
+                    let param_token = self.consume(TokenType::Identifier("".to_string()), "Expected parameter name")?.clone();
+                    let param_name = match &param_token.token_type {
+                        TokenType::Identifier(name) => name.clone(),
+                        _ => unreachable!(),
+                    };
+                    if parameters.iter().any(|(existing, _)| *existing == param_name) {
+                        return Err(format_error(
+                            &self.source_path,
+                            &self.source,
+                            ErrorPosition {
+                                line: param_token.line,
+                                column: param_token.column,
+                            },
+                            format!("Duplicate parameter '{}'", param_name),
+                            "Each parameter of a function must have a unique name".to_string(),
+                        ));
+                    }
+                    self.consume(TokenType::Colon, "Expected ':' after parameter name")?;
+                    let param_type = self.parse_type()?;
+                    parameters.push((param_name, param_type));
+                    if !self.match_token(TokenType::Comma) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::CloseParen, "Expected ')' after parameters")?;
+
+            let return_type = if self.match_token(TokenType::Arrow) {
+                Some(self.parse_type()?)
+            } else {
+                None
+            };
+
+            // Parameters share the scope of the function body and shadow any global of the same name
+            self.push_scope();
+            for (param, param_type) in &parameters {
+                self.scopes.last_mut().unwrap().insert(param.clone(), param_type.clone());
+            }
             
             self.in_function = true;
-            let mut body = Vec::new();
-            while !self.check(&TokenType::CloseBrace) && !self.is_at_end() {
-                self.skip_newlines();
-                body.push(self.statement()?);
-                self.skip_newlines();
-            }
-            self.consume(TokenType::CloseBrace, "Expected '}' to end function body")?;
+            self.return_type = return_type.clone();
+            let body = self.block("function body");
+            self.return_type = None;
             self.in_function = false;
+            self.pop_scope();
             
-            Ok(Statement::FunctionDefinition(name, body))
+            Ok(Statement::FunctionDefinition(name, parameters, return_type, body?))
         }
     }
 
-    fn assignment_statement(&mut self) -> Result<Statement, String> {
-        let line_number = self.peek().line;
-        
-        // Get the variable name
-        let name_token = self.advance();
-        let name = match &name_token.token_type {
-            TokenType::Identifier(name) => name.clone(),
-            _ => unreachable!(),
-        };
-    
-        self.consume(TokenType::Equals, "Expected '=' after variable name")?;
-    
-        // Try parsing as numeric expression first
-        match self.num_expression() {
-            Ok(num_expr) => {
-                Ok(Statement::NumAssignment(name, num_expr, line_number))
-            }
-            Err(_) => {
-                // If numeric parsing fails, try string expression
-                match self.expression() {
-                    Ok(str_expr) => {
-                        Ok(Statement::VariableAssignment(name, str_expr, line_number))
-                    }
-                    Err(_) => {
-                        Err(format_error(
-                            &self.source_path,
-                            &self.source,
-                            ErrorPosition {
-                                line: line_number,
-                                column: self.peek().column,
-                            },
-                            format!("Invalid assignment to variable '{}'", name),
-                            "Variables can only be assigned string or numeric values".to_string(),
-                        ))
-                    }
-                }
+    /// Parses a type name: 'num', 'int', 'str', 'bool', 'list<type>', 'map<type>'
+    /// or the name of a struct or enum.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * The parsed Type
+    /// * A formatted error message if the current token is not a type name
+    fn parse_type(&mut self) -> Result<Type, String> {
+        match type_from_tokens(&self.tokens, self.current) {
+            Some((Type::Struct(name), length)) if self.enums.contains_key(&name) => {
+                self.current += length;
+                Ok(Type::Enum(name))
+            }
+            Some((Type::Struct(name), _)) if !self.structs.contains_key(&name) => {
+                let token = self.peek().clone();
+                Err(format_error(
+                    &self.source_path,
+                    &self.source,
+                    ErrorPosition {
+                        line: token.line,
+                        column: token.column,
+                    },
+                    format!("Unknown type '{}'", name),
+                    "Define the type with 'struct' or 'enum', or use 'num', 'int', 'str' or 'bool'".to_string(),
+                ))
+            }
+            Some((parsed, length)) => {
+                self.current += length;
+                Ok(parsed)
+            }
+            None => {
+                let token = self.peek().clone();
+                Err(format_error(
+                    &self.source_path,
+                    &self.source,
+                    ErrorPosition {
+                        line: token.line,
+                        column: token.column,
+                    },
+                    "Expected type name".to_string(),
+                    "Valid types are 'num', 'int', 'str', 'bool', lists or maps of them such as 'list<num>' or 'map<int>', structs and enums".to_string(),
+                ))
             }
         }
     }
 
-    /// Parses a print statement, supporting both regular and format strings.
+    /// Parses a return statement, whose 'return' keyword has already been consumed.
+    ///
+    /// The value is parsed according to the return type of the enclosing function.
     ///
     /// # Returns
     ///
     /// A Result containing either:
-    /// * A Print or PrintFormat Statement object
+    /// * A Return Statement object
     /// * A formatted error message if parsing fails
-    fn print_statement(&mut self) -> Result<Statement, String> {
-        self.consume(TokenType::OpenParen, "Expected '(' after 'print'")?;
-        
-        let format_string = matches!(self.peek().token_type, TokenType::FormatStringPrefix);
-        if format_string {
-            self.advance(); // Consume the format string prefix
-        }
-        
-        let expr_token = self.consume(TokenType::StringLiteral("".to_string()), "Expected string literal")?;
-        let expr = match &expr_token.token_type {
-            TokenType::StringLiteral(value) => value.clone(),
-            _ => unreachable!(),
+    fn return_statement(&mut self) -> Result<Statement, String> {
+        let keyword = self.previous().clone();
+        let error = |parser: &Self, message: &str, hint: &str| {
+            format_error(
+                &parser.source_path,
+                &parser.source,
+                ErrorPosition {
+                    line: keyword.line,
+                    column: keyword.column,
+                },
+                message.to_string(),
+                hint.to_string(),
+            )
         };
-        
-        self.consume(TokenType::CloseParen, "Expected ')' after expression")?;
-        
-        if format_string {
-            let parts = self.parse_format_string(&expr)?;
-            Ok(Statement::PrintFormat(parts))
-        } else {
-            Ok(Statement::Print(Expression::StringLiteral(expr)))
+
+        let has_value = !matches!(
+            self.peek().token_type,
+            TokenType::Newline | TokenType::CloseBrace | TokenType::Eof
+        );
+        if !self.in_function {
+            // At the top level, 'return' ends the program with an exit status
+            let status = if has_value {
+                self.typed_expression(&Type::Int, "exit status")?
+            } else {
+                TypedExpression::Int(IntExpression::IntegerLiteral(0))
+            };
+            return Ok(Statement::Return(Some(status)));
+        }
+
+        match (self.return_type.clone(), has_value) {
+            (Some(return_type), true) => {
+                let value = self.typed_expression(&return_type, "return value")?;
+                Ok(Statement::Return(Some(value)))
+            }
+            (None, false) => Ok(Statement::Return(None)),
+            (Some(return_type), false) => Err(error(
+                self,
+                &format!("Missing return value of type '{}'", type_name(&return_type)),
+                "Add the value to return after 'return'",
+            )),
+            (None, true) => Err(error(
+                self,
+                "Cannot return a value from a function without a return type",
+                "Declare a return type with '-> type' after the parameter list",
+            )),
         }
     }
 
-    /// Parses the content of a format string into a vector of FormatPart.
-    ///
-    /// # Arguments
+    /// Parses a function call after the 'callfn' keyword: 'name(arg, ...)'.
     ///
-    /// * `s` - The format string content to parse
+    /// Each argument is parsed according to the type of the matching parameter,
+    /// and the number of arguments is checked against the function's signature.
     ///
     /// # Returns
     ///
     /// A Result containing either:
-    /// * A vector of FormatPart representing literals and expressions
+    /// * The function name, the arguments and the function's return type
     /// * A formatted error message if parsing fails
-    fn parse_format_string(&self, s: &str) -> Result<Vec<FormatPart>, String> {
-        let mut parts = Vec::new();
-        let mut current_literal = String::new();
-        let mut i = 0;
-        let chars: Vec<char> = s.chars().collect();
-        
-        while i < chars.len() {
-            if chars[i] == '{' {
-                if !current_literal.is_empty() {
-                    parts.push(FormatPart::Literal(current_literal.clone()));
-                    current_literal.clear();
-                }
-                i += 1;
-                let mut expr_str = String::new();
-                while i < chars.len() && chars[i] != '}' {
-                    expr_str.push(chars[i]);
-                    i += 1;
-                }
-                if i >= chars.len() || chars[i] != '}' {
-                    return Err(format_error(
-                        &self.source_path,
-                        &self.source,
-                        ErrorPosition {
-                            line: self.peek().line,
-                            column: self.peek().column,
-                        },
-                        "Unclosed '{' in format string".to_string(),
-                        "Ensure all braces are properly closed".to_string(),
+    fn function_call(&mut self) -> Result<(String, Vec<TypedExpression>, Option<Type>), String> {
+        let name_token = self.consume(TokenType::Identifier("".to_string()), "Expected function name after 'callfn'")?.clone();
+        let name = match &name_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+        let error_at_name = |parser: &Self, message: String, hint: String| {
+            format_error(
+                &parser.source_path,
+                &parser.source,
+                ErrorPosition {
+                    line: name_token.line,
+                    column: name_token.column,
+                },
+                message,
+                hint,
+            )
+        };
+
+        let signature = match self.functions.get(&name) {
+            Some(signature) => signature.clone(),
+            None => {
+                return Err(error_at_name(
+                    self,
+                    format!("Undefined function '{}'", name),
+                    "Define the function with 'newfn fn' before calling it".to_string(),
+                ));
+            }
+        };
+
+        self.consume(TokenType::OpenParen, "Expected '(' after function name")?;
+        let mut args = Vec::new();
+        if !self.check(&TokenType::CloseParen) {
+            loop {
+                if args.len() == signature.parameters.len() {
+                    // More arguments than the function has parameters
+                    return Err(error_at_name(
+                        self,
+                        format!("Function '{}' expects {} argument(s)", name, signature.parameters.len()),
+                        "Remove the extra arguments from the call".to_string(),
                     ));
                 }
-                i += 1;
-                let expr = self.parse_format_expression(&expr_str)?;
-                parts.push(FormatPart::Expression(expr));
-            } else {
-                current_literal.push(chars[i]);
-                i += 1;
+                let (param_name, param_type) = &signature.parameters[args.len()];
+                let context = format!("parameter '{}' of '{}'", param_name, name);
+                args.push(self.typed_expression(param_type, &context)?);
+                if !self.match_token(TokenType::Comma) {
+                    break;
+                }
             }
         }
-        if !current_literal.is_empty() {
-            parts.push(FormatPart::Literal(current_literal));
+        self.consume(TokenType::CloseParen, "Expected ')' after arguments")?;
+
+        if args.len() != signature.parameters.len() {
+            return Err(error_at_name(
+                self,
+                format!(
+                    "Function '{}' expects {} argument(s) but {} were given",
+                    name,
+                    signature.parameters.len(),
+                    args.len()
+                ),
+                "Pass one argument for each parameter of the function".to_string(),
+            ));
         }
-        Ok(parts)
+
+        Ok((name, args, signature.return_type))
     }
 
-    /// Parses an expression within a format string's braces.
+    /// Parses an expression that must have the given type.
     ///
     /// # Arguments
     ///
-    /// * `s` - The string content within '{...}'
+    /// * `expected` - The type the expression must have
+    /// * `context` - Description of where the value is used (for error messages)
     ///
     /// # Returns
     ///
     /// A Result containing either:
-    /// * An Expression (Variable or FunctionCall)
-    /// * A formatted error message if parsing fails
-    fn parse_format_expression(&self, s: &str) -> Result<Expression, String> {
-        let trimmed = s.trim();
-        if trimmed.starts_with("callfn ") {
-            let fn_name = trimmed[7..].trim();
-            if fn_name.ends_with("()") {
-                let name = &fn_name[..fn_name.len() - 2];
-                if name.chars().all(|c| c.is_alphanumeric() || c == '_') {
-                    Ok(Expression::FunctionCall(name.to_string()))
-                } else {
-                    Err(format_error(
-                        &self.source_path,
-                        &self.source,
-                        ErrorPosition {
-                            line: self.peek().line,
-                            column: self.peek().column,
-                        },
-                        format!("Invalid function name '{}'", name),
-                        "Function names must be alphanumeric with underscores".to_string(),
-                    ))
-                }
-            } else {
-                Err(format_error(
-                    &self.source_path,
-                    &self.source,
-                    ErrorPosition {
-                        line: self.peek().line,
-                        column: self.peek().column,
-                    },
-                    "Expected '()' after function name".to_string(),
-                    "Function calls in format strings must end with '()'".to_string(),
-                ))
-            }
-        } else if trimmed.chars().all(|c| c.is_alphanumeric() || c == '_') {
-            Ok(Expression::Variable(trimmed.to_string()))
-        } else {
-            Err(format_error(
+    /// * A TypedExpression of the expected type
+    /// * A formatted error message if parsing fails or the type doesn't match
+    fn typed_expression(&mut self, expected: &Type, context: &str) -> Result<TypedExpression, String> {
+        if *expected != Type::Str && self.is_string_operand() {
+            let token = self.peek().clone();
+            return Err(format_error(
                 &self.source_path,
                 &self.source,
                 ErrorPosition {
-                    line: self.peek().line,
-                    column: self.peek().column,
+                    line: token.line,
+                    column: token.column,
                 },
-                format!("Invalid expression in format string: '{}'", trimmed),
-                "Use a variable name or 'callfn functionname()'".to_string(),
-            ))
+                format!("Type mismatch for {}: expected '{}', found 'str'", context, type_name(expected)),
+                "Pass a value of the declared type".to_string(),
+            ));
         }
-    }
 
-    /// Parses a string variable declaration statement.
+        match expected {
+            Type::Str => Ok(TypedExpression::Str(self.expression()?)),
+            Type::Num => Ok(TypedExpression::Num(self.num_expression()?)),
+            Type::Bool => Ok(TypedExpression::Bool(self.condition()?)),
+            Type::Int => Ok(TypedExpression::Int(self.int_expression()?)),
+            Type::List(element_type) => {
+                let (_, expr) = self.list_expression(Some(element_type))?;
+                Ok(TypedExpression::List(expr))
+            }
+            Type::Map(value_type) => {
+                let (_, expr) = self.map_expression(Some(value_type))?;
+                Ok(TypedExpression::Map(expr))
+            }
+            Type::Struct(name) => {
+                let (name, expr) = self.struct_expression(Some(name))?;
+                Ok(TypedExpression::Struct(name, expr))
+            }
+            Type::Enum(name) => {
+                let (name, expr) = self.enum_expression(Some(name))?;
+                Ok(TypedExpression::Enum(name, expr))
+            }
+        }
+    }
+
+    /// Reports that a function call yields the wrong type for where it's used.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The function name
+    /// * `return_type` - The function's declared return type
+    /// * `expected` - The type required at the call site
+    /// * `token` - The 'callfn' token (for the error position)
+    fn call_type_error(&self, name: &str, return_type: Option<&Type>, expected: &Type, token: &Token) -> String {
+        let found = match return_type {
+            Some(return_type) => format!("returns '{}'", type_name(return_type)),
+            None => "does not return a value".to_string(),
+        };
+        format_error(
+            &self.source_path,
+            &self.source,
+            ErrorPosition {
+                line: token.line,
+                column: token.column,
+            },
+            format!("Function '{}' {}, but {} is expected here", name, found, a_type_name(expected)),
+            match return_type {
+                Some(return_type) if conversion_function(return_type, expected).is_some() => {
+                    conversion_hint(return_type, expected)
+                }
+                _ => "Call a function whose return type matches how its result is used".to_string(),
+            },
+        )
+    }
+
+    /// Records that a function's definition has been parsed, rejecting a second
+    /// definition with the same name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the function
+    /// * `name_token` - The token of the name (for error positions)
+    ///
+    /// # Returns
+    ///
+    /// A Result that is an error if the function is already defined
+    fn define_function(&mut self, name: &str, name_token: &Token) -> Result<(), String> {
+        if self.defined_functions.insert(name.to_string()) {
+            return Ok(());
+        }
+        Err(format_error(
+            &self.source_path,
+            &self.source,
+            ErrorPosition {
+                line: name_token.line,
+                column: name_token.column,
+            },
+            format!("Function '{}' is already defined", name),
+            "Give the function a name that isn't used by another function".to_string(),
+        ))
+    }
+
+    /// Parses a brace-delimited block of statements.
+    ///
+    /// # Arguments
+    ///
+    /// * `context` - Description of the block used in error messages (e.g. "function body")
     ///
     /// # Returns
     ///
     /// A Result containing either:
-    /// * A VariableDeclaration Statement object
+    /// * The statements inside the block
     /// * A formatted error message if parsing fails
-    fn let_statement(&mut self) -> Result<Statement, String> {
-        // Store the current line number for error reporting
-        let line_number = self.peek().line;
-        
-        let name_token = self.consume(
-            TokenType::Identifier("".to_string()),
-            "Expected variable name",
-        )?;
-        let name = match &name_token.token_type {
-            TokenType::Identifier(name) => name.clone(),
-            _ => unreachable!(),
-        };
+    fn block(&mut self, context: &str) -> Result<Vec<Statement>, String> {
+        self.consume(TokenType::OpenBrace, &format!("Expected '{{' to start {}", context))?;
 
-        self.consume(TokenType::Equals, "Expected '=' after variable name")?;
+        let mut body = Vec::new();
+        self.skip_newlines();
+        while !self.check(&TokenType::CloseBrace) && !self.is_at_end() {
+            body.push(self.statement()?);
+            self.skip_newlines();
+        }
+        self.consume(TokenType::CloseBrace, &format!("Expected '}}' to end {}", context))?;
 
-        let initializer = self.expression()?;
+        Ok(body)
+    }
 
-        Ok(Statement::VariableDeclaration(name, initializer, line_number))
+    /// Parses an if statement with any number of 'else if' branches and an
+    /// optional final 'else' branch.
+    ///
+    /// The 'if' keyword has already been consumed. An 'else' may appear on the
+    /// same line as the closing brace or on a following line.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * An If Statement object
+    /// * A formatted error message if parsing fails
+    fn if_statement(&mut self) -> Result<Statement, String> {
+        let mut branches = Vec::new();
+        let mut else_body = None;
+
+        self.block_depth += 1;
+        loop {
+            let condition = self.condition()?;
+            self.push_scope();
+            let body = self.block("if body");
+            self.pop_scope();
+            branches.push((condition, body?));
+
+            self.skip_newlines();
+            if !self.match_token(TokenType::Else) {
+                break;
+            }
+            if !self.match_token(TokenType::If) {
+                self.push_scope();
+                let body = self.block("else body");
+                self.pop_scope();
+                else_body = Some(body?);
+                break;
+            }
+        }
+        self.block_depth -= 1;
+
+        Ok(Statement::If(branches, else_body))
     }
 
-    /// Parses a numerical variable declaration statement.
+    /// Parses a while loop.
+    ///
+    /// The 'while' keyword has already been consumed.
     ///
     /// # Returns
     ///
     /// A Result containing either:
-    /// * A NumDeclaration Statement object
+    /// * A While Statement object
     /// * A formatted error message if parsing fails
-    fn num_statement(&mut self) -> Result<Statement, String> {
-        // Store the current line number for error reporting
-        let line_number = self.peek().line;
-        
-        // Get the variable name
+    fn while_statement(&mut self) -> Result<Statement, String> {
+        let condition = self.condition()?;
+
+        // Remember whether we were already in a loop so nested loops restore it correctly
+        let was_in_loop = self.in_loop;
+        self.in_loop = true;
+        self.block_depth += 1;
+        self.push_scope();
+        let body = self.block("while body");
+        self.pop_scope();
+        self.block_depth -= 1;
+        self.in_loop = was_in_loop;
+
+        Ok(Statement::While(condition, body?))
+    }
+
+    /// Parses a counted for loop: 'for i in start..end' with an optional 'step'.
+    ///
+    /// The 'for' keyword has already been consumed. The end of the range is
+    /// exclusive and the step defaults to 1; a negative step counts down.
+    ///
+    /// The loop variable is a 'num', unless the start, end and step are all
+    /// 'int' expressions and at least one of them is more than an integer
+    /// literal (as in 'for i in 0..len(items)'); then it is an 'int', so it
+    /// can index a list.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A For or IntFor Statement object
+    /// * A formatted error message if parsing fails
+    fn for_statement(&mut self) -> Result<Statement, String> {
+        let line_number = self.previous().line;
+
         let name_token = self.consume(
             TokenType::Identifier("".to_string()),
-            "Expected variable name after 'num'",
-        )?;
+            "Expected loop variable name after 'for'",
+        )?.clone();
         let name = match &name_token.token_type {
             TokenType::Identifier(name) => name.clone(),
             _ => unreachable!(),
         };
 
-        // Expect assignment operator
-        self.consume(TokenType::Equals, "Expected '=' after variable name")?;
+        self.consume(TokenType::In, "Expected 'in' after loop variable")?;
+        if let Some((start, end, step)) = self.int_range() {
+            let body = self.loop_body(&name_token, Type::Int)?;
+            return Ok(Statement::IntFor(name, start, end, step, body, line_number));
+        }
+        let start = self.num_expression()?;
+        self.consume(TokenType::DotDot, "Expected '..' between the start and end of the range")?;
+        let end = self.num_expression()?;
 
-        // Parse the numerical expression
-        let initializer = self.num_expression()?;
+        let step = if self.match_token(TokenType::Step) {
+            self.num_expression()?
+        } else {
+            NumExpression::NumberLiteral(1.0)
+        };
 
-        Ok(Statement::NumDeclaration(name, initializer, line_number))
+        let body = self.loop_body(&name_token, Type::Num)?;
+        Ok(Statement::For(name, start, end, step, body, line_number))
     }
 
-    /// Parses a string expression.
+    /// Tries to parse the range of a for loop as 'int' expressions, up to the
+    /// '{' of the body.
     ///
     /// # Returns
     ///
-    /// A Result containing either:
-    /// * An Expression object
-    /// * A formatted error message if parsing fails
-    fn expression(&mut self) -> Result<Expression, String> {
-        match &self.peek().token_type {
-            TokenType::StringLiteral(_) => {
-                let token = self.advance();
-                if let TokenType::StringLiteral(value) = &token.token_type {
-                    Ok(Expression::StringLiteral(value.clone()))
-                } else {
-                    unreachable!()
-                }
+    /// The start, end and step if the range is an integer range with at least
+    /// one expression that is not just a literal; otherwise None, with the
+    /// parser back at the start of the range.
+    fn int_range(&mut self) -> Option<(IntExpression, IntExpression, IntExpression)> {
+        let start = self.current;
+        let range = (|| {
+            let from = self.int_expression().ok()?;
+            if !self.match_token(TokenType::DotDot) {
+                return None;
             }
-            TokenType::Identifier(_) => {
-                let token = self.advance();
-                if let TokenType::Identifier(name) = &token.token_type {
-                    Ok(Expression::Variable(name.clone()))
-                } else {
-                    unreachable!()
-                }
+            let to = self.int_expression().ok()?;
+            let step = if self.match_token(TokenType::Step) {
+                self.int_expression().ok()?
+            } else {
+                IntExpression::IntegerLiteral(1)
+            };
+            self.check(&TokenType::OpenBrace).then_some((from, to, step))
+        })();
+        match range {
+            Some((from, to, step))
+                if !(is_int_literal(&from) && is_int_literal(&to) && is_int_literal(&step)) =>
+            {
+                Some((from, to, step))
             }
             _ => {
-                let token = self.peek().clone();
-                Err(format_error(
-                    &self.source_path,
-                    &self.source,
-                    ErrorPosition {
-                        line: token.line,
-                        column: token.column,
-                    },
-                    "Expected expression".to_string(),
-                    "Valid expressions are string literals and variable identifiers".to_string(),
-                ))
+                self.current = start;
+                None
             }
         }
     }
 
-    /// Parses a numerical expression using recursive descent parsing.
-    /// This handles precedence and associativity of mathematical operators.
+    /// Parses the body of a for loop, with the loop variable declared as
+    /// `var_type` in a scope local to the loop.
+    fn loop_body(&mut self, name_token: &Token, var_type: Type) -> Result<Vec<Statement>, String> {
+        let was_in_loop = self.in_loop;
+        self.in_loop = true;
+        // The loop variable is local to the loop
+        self.block_depth += 1;
+        self.push_scope();
+        let body = self
+            .declare_variable(name_token, var_type)
+            .and_then(|_| self.block("for body"));
+        self.pop_scope();
+        self.block_depth -= 1;
+        self.in_loop = was_in_loop;
+        body
+    }
+
+    /// Parses a 'break' or 'continue' statement, whose keyword has already been consumed.
     ///
     /// # Returns
     ///
     /// A Result containing either:
-    /// * A NumExpression object
-    /// * A formatted error message if parsing fails
-    fn num_expression(&mut self) -> Result<NumExpression, String> {
-        // Start with the lowest precedence: addition and subtraction
-        self.num_addition()
+    /// * A Break or Continue Statement object
+    /// * A formatted error message if the keyword appears outside a loop
+    fn loop_control_statement(&mut self) -> Result<Statement, String> {
+        let keyword = self.previous().clone();
+        let (statement, name) = match keyword.token_type {
+            TokenType::Break => (Statement::Break, "break"),
+            _ => (Statement::Continue, "continue"),
+        };
+
+        if !self.in_loop {
+            return Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: keyword.line,
+                    column: keyword.column,
+                },
+                format!("'{}' outside of a loop", name),
+                format!("'{}' can only be used inside a 'while' or 'for' loop body", name),
+            ));
+        }
+
+        Ok(statement)
     }
 
-    /// Parses an addition or subtraction expression.
-    /// Addition and subtraction have the same precedence level.
+    /// Parses a match statement, whose 'match' keyword has already been consumed:
+    /// 'match value { pattern => statement ... }'.
+    ///
+    /// Each arm's body is either a single statement on the same line as its
+    /// pattern or a brace-delimited block. Whether the arms handle every value
+    /// is checked by the analysis pass.
     ///
     /// # Returns
     ///
     /// A Result containing either:
-    /// * A NumExpression object
+    /// * A Match Statement object
     /// * A formatted error message if parsing fails
-    fn num_addition(&mut self) -> Result<NumExpression, String> {
-        // Start with the next higher precedence
-        let mut expr = self.num_multiplication()?;
+    fn match_statement(&mut self) -> Result<Statement, String> {
+        let keyword = self.previous().clone();
+        let (subject_type, subject) = self.match_subject()?;
+        self.consume(TokenType::OpenBrace, "Expected '{' after the matched value")?;
 
-        // Keep consuming addition and subtraction operators
-        while self.match_token(TokenType::Plus) || self.match_token(TokenType::Minus) {
-            let operator = match self.previous().token_type {
-                TokenType::Plus => BinaryOperator::Add,
-                TokenType::Minus => BinaryOperator::Subtract,
-                _ => unreachable!(),
-            };
-            
-            // Parse the right operand with higher precedence
-            let right = self.num_multiplication()?;
-            
-            // Build the binary operation expression
-            expr = NumExpression::BinaryOp(Box::new(expr), operator, Box::new(right));
+        let mut arms = Vec::new();
+        self.block_depth += 1;
+        self.skip_newlines();
+        while !self.check(&TokenType::CloseBrace) && !self.is_at_end() {
+            arms.push(self.match_arm(&subject_type)?);
+            self.skip_newlines();
         }
+        self.consume(TokenType::CloseBrace, "Expected '}' to end match")?;
+        self.block_depth -= 1;
 
-        Ok(expr)
+        Ok(Statement::Match(subject, arms, keyword.line, keyword.column))
     }
 
-    /// Parses a multiplication or division expression.
-    /// Multiplication and division have the same precedence level,
-    /// which is higher than addition and subtraction.
+    /// Parses the value of a match statement, which may be an enum, a number,
+    /// an integer, a string or a boolean.
     ///
     /// # Returns
     ///
     /// A Result containing either:
-    /// * A NumExpression object
-    /// * A formatted error message if parsing fails
-    fn num_multiplication(&mut self) -> Result<NumExpression, String> {
-        // Start with the highest precedence: primary expressions
-        let mut expr = self.num_primary()?;
-
-        // Keep consuming multiplication and division operators
-        while self.match_token(TokenType::Star) || self.match_token(TokenType::Slash) {
-            let operator = match self.previous().token_type {
-                TokenType::Star => BinaryOperator::Multiply,
-                TokenType::Slash => BinaryOperator::Divide,
-                _ => unreachable!(),
-            };
-            
-            // Parse the right operand
-            let right = self.num_primary()?;
-            
-            // Build the binary operation expression
-            expr = NumExpression::BinaryOp(Box::new(expr), operator, Box::new(right));
+    /// * The type of the value and the TypedExpression for it
+    /// * A formatted error message if parsing fails or the value can't be matched
+    fn match_subject(&mut self) -> Result<(Type, TypedExpression), String> {
+        if self.is_string_operand() {
+            return Ok((Type::Str, TypedExpression::Str(self.expression()?)));
+        }
+        if self.is_enum_operand() {
+            let (name, expr) = self.enum_expression(None)?;
+            return Ok((Type::Enum(name.clone()), TypedExpression::Enum(name, expr)));
+        }
+        if self.is_bool_operand() {
+            return Ok((Type::Bool, TypedExpression::Bool(self.condition()?)));
+        }
+        if self.is_list_operand() || self.is_map_operand() || self.is_struct_operand() {
+            let token = self.peek().clone();
+            return Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: token.line,
+                    column: token.column,
+                },
+                "Only enums, numbers, integers, strings and booleans can be matched".to_string(),
+                "Match on a field or element of the value instead".to_string(),
+            ));
         }
 
-        Ok(expr)
+        // Integer values are tried first so that they keep their exact value
+        let start = self.current;
+        if let Ok(expr) = self.int_expression()
+            && self.check(&TokenType::OpenBrace)
+        {
+            return Ok((Type::Int, TypedExpression::Int(expr)));
+        }
+        self.current = start;
+        Ok((Type::Num, TypedExpression::Num(self.num_expression()?)))
     }
 
-    /// Parses a primary numerical expression (literals, variables, and parenthesized expressions).
-    /// This is the highest precedence level in the expression grammar.
+    /// Parses a single arm of a match statement: 'pattern => body'.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject_type` - The type of the matched value
     ///
     /// # Returns
     ///
     /// A Result containing either:
-    /// * A NumExpression object
+    /// * The MatchArm
     /// * A formatted error message if parsing fails
-    fn num_primary(&mut self) -> Result<NumExpression, String> {
-        // Check each possible primary expression type
-        if self.match_token(TokenType::NumberLiteral(0.0)) {
-            // Handle numeric literals
-            if let TokenType::NumberLiteral(value) = self.previous().token_type {
-                Ok(NumExpression::NumberLiteral(value))
-            } else {
-                unreachable!()
-            }
-        } else if self.match_token(TokenType::Identifier("".to_string())) {
-            // Handle variable references
-            if let TokenType::Identifier(name) = &self.previous().token_type {
-                Ok(NumExpression::Variable(name.clone()))
+    fn match_arm(&mut self, subject_type: &Type) -> Result<MatchArm, String> {
+        let pattern_token = self.peek().clone();
+
+        // Values bound by the pattern share the scope of the arm's body
+        self.push_scope();
+        let body = self.match_pattern(subject_type).and_then(|pattern| {
+            self.consume(TokenType::FatArrow, "Expected '=>' after the pattern")?;
+            let body = if self.check(&TokenType::OpenBrace) {
+                self.block("match arm")?
             } else {
-                unreachable!()
-            }
-        } else if self.match_token(TokenType::OpenParen) {
-            // Handle parenthesized expressions
-            let expr = self.num_expression()?;
-            self.consume(TokenType::CloseParen, "Expected ')' after expression")?;
-            Ok(NumExpression::Grouping(Box::new(expr)))
-        } else {
-            // Error: unexpected token
-            let token = self.peek().clone();
-            Err(format_error(
-                &self.source_path,
-                &self.source,
+                vec![self.statement()?]
+            };
+            Ok((pattern, body))
+        });
+        self.pop_scope();
+        let (pattern, body) = body?;
+
+        Ok(MatchArm {
+            pattern,
+            body,
+            line: pattern_token.line,
+            column: pattern_token.column,
+        })
+    }
+
+    /// Parses the pattern of a match arm and declares the names it binds in
+    /// the innermost scope.
+    ///
+    /// Patterns are '_', which matches anything, a literal of the matched
+    /// type, or for enums the name of a variant, optionally qualified with the
+    /// enum name. A variant that carries values binds a name to each of them:
+    /// 'Circle(radius)', with '_' for values that aren't needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `subject_type` - The type of the matched value
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * The Pattern
+    /// * A formatted error message if the pattern doesn't fit the matched type
+    fn match_pattern(&mut self, subject_type: &Type) -> Result<Pattern, String> {
+        let token = self.peek().clone();
+        let error = |parser: &Self, message: String, hint: String| {
+            format_error(
+                &parser.source_path,
+                &parser.source,
                 ErrorPosition {
                     line: token.line,
                     column: token.column,
                 },
-                "Expected numerical expression".to_string(),
-                "Valid expressions are numbers, variables, or parenthesized expressions".to_string(),
-            ))
+                message,
+                hint,
+            )
+        };
+
+        if token.token_type == TokenType::Identifier("_".to_string()) {
+            self.advance();
+            return Ok(Pattern::Wildcard);
         }
-    }
 
+        let negative = matches!(subject_type, Type::Num | Type::Int) && self.match_token(TokenType::Minus);
+        let sign = if negative { -1 } else { 1 };
+        let pattern = match (subject_type, &self.peek().token_type) {
+            (Type::Int, TokenType::IntegerLiteral(value)) => Some(Pattern::Int(sign * value)),
+            (Type::Num, TokenType::IntegerLiteral(value)) => Some(Pattern::Num((sign * value) as f64)),
+            (Type::Num, TokenType::NumberLiteral(value)) => Some(Pattern::Num(sign as f64 * value)),
+            (Type::Str, TokenType::StringLiteral(value)) => Some(Pattern::Str(value.clone())),
+            (Type::Bool, TokenType::True) => Some(Pattern::Bool(true)),
+            (Type::Bool, TokenType::False) => Some(Pattern::Bool(false)),
+            _ => None,
+        };
+        if let Some(pattern) = pattern {
+            self.advance();
+            return Ok(pattern);
+        }
 
-}
+        let Type::Enum(enum_name) = subject_type else {
+            let expected = match subject_type {
+                Type::Int => "an integer literal",
+                Type::Num => "a number literal",
+                Type::Str => "a string literal",
+                _ => "'true' or 'false'",
+            };
+            return Err(error(
+                self,
+                format!("Expected {} or '_' as the pattern for {} value", expected, a_type_name(subject_type)),
+                "Patterns must have the type of the matched value".to_string(),
+            ));
+        };
 
-/// Convenience function to parse a token stream into an AST.
-///
-/// # Arguments
-///
-/// * `tokens` - The token stream to parse
-///
-/// # Returns
-///
+        // The enum name may qualify the variant: 'Color.Red'
+        if self.check(&TokenType::Identifier(enum_name.clone()))
+            && self.tokens.get(self.current + 1).is_some_and(|token| token.token_type == TokenType::Dot)
+        {
+            self.current += 2;
+        }
+        let variant_token = self.peek().clone();
+        let variants = self.enums[enum_name].clone();
+        let variant_names: Vec<&str> = variants.iter().map(|(variant, _)| variant.as_str()).collect();
+        let found = match &variant_token.token_type {
+            TokenType::Identifier(variant) => variants.iter().find(|(declared, _)| declared == variant),
+            _ => None,
+        };
+        let Some((variant, value_types)) = found else {
+            return Err(error(
+                self,
+                format!("Expected a variant of '{}' or '_' as the pattern", enum_name),
+                format!("The variants of '{}' are: {}", enum_name, variant_names.join(", ")),
+            ));
+        };
+        self.advance();
+
+        let mut binding_tokens = Vec::new();
+        if self.match_token(TokenType::OpenParen) {
+            loop {
+                binding_tokens.push(self.consume(TokenType::Identifier("".to_string()), "Expected a name to bind the value to")?.clone());
+                if !self.match_token(TokenType::Comma) {
+                    break;
+                }
+            }
+            self.consume(TokenType::CloseParen, "Expected ')' after the bound names")?;
+        }
+        if binding_tokens.len() != value_types.len() {
+            return Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: variant_token.line,
+                    column: variant_token.column,
+                },
+                format!(
+                    "Variant '{}' of '{}' carries {} value(s), but the pattern binds {}",
+                    variant,
+                    enum_name,
+                    value_types.len(),
+                    binding_tokens.len()
+                ),
+                format!("Bind each value to a name, using '_' for values that aren't needed, e.g. '{}'", variant_pattern(variant, value_types.len())),
+            ));
+        }
+
+        let mut bindings = Vec::new();
+        for (binding_token, value_type) in binding_tokens.iter().zip(value_types) {
+            let binding = match &binding_token.token_type {
+                TokenType::Identifier(binding) => binding.clone(),
+                _ => unreachable!(),
+            };
+            if binding != "_" {
+                self.declare_variable(binding_token, value_type.clone())?;
+            }
+            bindings.push((binding, value_type.clone()));
+        }
+        Ok(Pattern::Variant(variant.clone(), bindings))
+    }
+
+    /// Parses a boolean expression, starting at the lowest precedence: 'or'.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A Condition object
+    /// * A formatted error message if parsing fails
+    fn condition(&mut self) -> Result<Condition, String> {
+        let mut condition = self.and_condition()?;
+
+        while self.match_token(TokenType::Or) {
+            let right = self.and_condition()?;
+            condition = Condition::Or(Box::new(condition), Box::new(right));
+        }
+
+        Ok(condition)
+    }
+
+    /// Parses a conjunction of conditions. 'and' binds tighter than 'or'.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A Condition object
+    /// * A formatted error message if parsing fails
+    fn and_condition(&mut self) -> Result<Condition, String> {
+        let mut condition = self.not_condition()?;
+
+        while self.match_token(TokenType::And) {
+            let right = self.not_condition()?;
+            condition = Condition::And(Box::new(condition), Box::new(right));
+        }
+
+        Ok(condition)
+    }
+
+    /// Parses an optionally negated condition. 'not' binds tighter than 'and',
+    /// but looser than comparisons, so 'not a == b' negates the whole comparison.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A Condition object
+    /// * A formatted error message if parsing fails
+    fn not_condition(&mut self) -> Result<Condition, String> {
+        if self.match_token(TokenType::Not) {
+            let inner = self.not_condition()?;
+            Ok(Condition::Not(Box::new(inner)))
+        } else {
+            let bool_operand = self.is_bool_operand();
+            let condition = self.primary_condition()?;
+            if bool_operand && self.is_comparison_operator() {
+                return self.bool_comparison(condition);
+            }
+            Ok(condition)
+        }
+    }
+
+    /// Parses the rest of a comparison between two booleans, whose left side
+    /// has already been parsed: '== value' or '!= value'.
+    ///
+    /// # Arguments
+    ///
+    /// * `left` - The boolean on the left side of the comparison
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A Condition object
+    /// * A formatted error message if parsing fails
+    fn bool_comparison(&mut self, left: Condition) -> Result<Condition, String> {
+        let operator_token = self.peek().clone();
+        let operator = self.comparison_operator()?;
+        if !matches!(operator, ComparisonOperator::Equal | ComparisonOperator::NotEqual) {
+            return Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: operator_token.line,
+                    column: operator_token.column,
+                },
+                "Booleans can only be compared with '==' and '!='".to_string(),
+                "Booleans have no order; combine them with 'and', 'or' and 'not' instead".to_string(),
+            ));
+        }
+        if !self.is_bool_operand() && !self.check(&TokenType::OpenParen) {
+            let token = self.peek().clone();
+            return Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: token.line,
+                    column: token.column,
+                },
+                "Expected a 'bool' value to compare with".to_string(),
+                "Both sides of a comparison must have the same type".to_string(),
+            ));
+        }
+        let right = self.primary_condition()?;
+        Ok(Condition::BoolComparison(Box::new(left), operator, Box::new(right)))
+    }
+
+    /// Parses a boolean literal, a boolean variable, a parenthesized condition
+    /// or a comparison.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A Condition object
+    /// * A formatted error message if parsing fails
+    fn primary_condition(&mut self) -> Result<Condition, String> {
+        if self.match_token(TokenType::True) {
+            return Ok(Condition::Literal(true));
+        }
+        if self.match_token(TokenType::False) {
+            return Ok(Condition::Literal(false));
+        }
+        if self.list_element_type() == Some(Type::Bool)
+            && let Some(element) = self.list_element(&Type::Bool)?
+        {
+            return Ok(Condition::ListElement(element));
+        }
+        if self.map_value_type() == Some(Type::Bool)
+            && let Some(value) = self.map_value(&Type::Bool)?
+        {
+            return Ok(Condition::MapValue(value));
+        }
+        if self.field_type() == Some(Type::Bool)
+            && let Some(access) = self.field_access(&Type::Bool)?
+        {
+            return Ok(Condition::Field(access));
+        }
+        if let TokenType::Identifier(name) = &self.peek().token_type
+            && name == "has"
+            && self.tokens.get(self.current + 1).is_some_and(|next| next.token_type == TokenType::OpenParen)
+        {
+            // Whether a map holds a key: 'has(map, key)'
+            self.advance();
+            self.advance();
+            let (_, map) = self.map_expression(None)?;
+            self.consume(TokenType::Comma, "Expected ',' after the map")?;
+            let key = self.expression()?;
+            self.consume(TokenType::CloseParen, "Expected ')' after the key")?;
+            return Ok(Condition::MapHas(map, key));
+        }
+        if let TokenType::Identifier(name) = &self.peek().token_type
+            && name == "isnum"
+            && self.tokens.get(self.current + 1).is_some_and(|next| next.token_type == TokenType::OpenParen)
+        {
+            // Whether a string holds a number: 'isnum(text)'
+            self.advance();
+            self.advance();
+            let text = self.expression()?;
+            self.consume(TokenType::CloseParen, "Expected ')' after the string")?;
+            return Ok(Condition::IsNum(text));
+        }
+        if let TokenType::Identifier(name) = &self.peek().token_type
+            && name == "file_exists"
+            && self.tokens.get(self.current + 1).is_some_and(|next| next.token_type == TokenType::OpenParen)
+        {
+            // Whether a file exists: 'file_exists(path)'
+            self.advance();
+            self.advance();
+            let path = self.expression()?;
+            self.consume(TokenType::CloseParen, "Expected ')' after the path")?;
+            return Ok(Condition::FileExists(path));
+        }
+        if let TokenType::Identifier(name) = &self.peek().token_type
+            && self.is_builtin_call()
+            && builtin_return_type(name) == Some(Type::Bool)
+            && string_function(name).is_some()
+        {
+            // String tests such as 'contains(text, part)'
+            let name = name.clone();
+            let token = self.advance().clone();
+            let (function, args) = self.string_call(&name, &token)?;
+            return Ok(Condition::StringCall(function, args, token.line));
+        }
+        if let TokenType::Identifier(name) = &self.peek().token_type
+            && self.variable_type(name) == Some(Type::Bool)
+        {
+            let name = name.clone();
+            self.advance();
+            return Ok(Condition::Variable(name));
+        }
+
+        if let TokenType::CallFn = self.peek().token_type
+            && let Some(TokenType::Identifier(name)) = self.tokens.get(self.current + 1).map(|token| &token.token_type)
+            && self.functions.get(name).is_some_and(|signature| signature.return_type == Some(Type::Bool))
+        {
+            self.advance();
+            let (name, args, _) = self.function_call()?;
+            return Ok(Condition::FunctionCall(name, args));
+        }
+
+        if self.check(&TokenType::OpenParen) {
+            // '(' may start either a grouped condition like '(a < b) or c' or a
+            // grouped numerical operand like '(a + b) < c', so try the former and
+            // backtrack if the parentheses don't hold a complete condition
+            let start = self.current;
+            self.advance();
+            if let Ok(condition) = self.condition()
+                && self.match_token(TokenType::CloseParen)
+            {
+                // A grouped condition can itself be compared: '(a < b) == c'
+                if self.is_comparison_operator() {
+                    return self.bool_comparison(condition);
+                }
+                return Ok(condition);
+            }
+            self.current = start;
+        }
+
+        if self.is_void_builtin_call() {
+            // Unless a comparison follows the call, a boolean was expected in its place
+            let mut depth = 0usize;
+            let call_length = self.tokens[self.current + 1..].iter().position(|token| {
+                match token.token_type {
+                    TokenType::OpenParen => depth += 1,
+                    TokenType::CloseParen => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                depth == 0
+            });
+            let start = self.current;
+            self.current += call_length.map_or(0, |length| length + 2);
+            let compared = self.is_comparison_operator();
+            self.current = start;
+            if !compared {
+                self.reject_void_builtin_call(Some(&Type::Bool))?;
+            }
+        }
+
+        self.comparison()
+    }
+
+    /// Parses a comparison between two values of the same type.
+    ///
+    /// The type of the comparison is decided by the left operand: string
+    /// literals and string variables start a string comparison. Otherwise the
+    /// comparison is parsed as an integer comparison if both sides are integer
+    /// expressions, and as a numerical comparison if they are not.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A Condition object
+    /// * A formatted error message if parsing fails
+    fn comparison(&mut self) -> Result<Condition, String> {
+        if self.is_string_operand() {
+            let left = self.expression()?;
+            let operator = self.comparison_operator()?;
+            let right = self.expression()?;
+            Ok(Condition::StrComparison(left, operator, right))
+        } else {
+            // Integer literals are valid in both kinds of comparison, so try the
+            // stricter integer grammar first and backtrack if it doesn't fit
+            let start = self.current;
+            if let Ok(left) = self.int_expression()
+                && let Ok(operator) = self.comparison_operator()
+                && let Ok(right) = self.int_expression()
+            {
+                return Ok(Condition::IntComparison(left, operator, right));
+            }
+            self.current = start;
+
+            let left = self.num_expression()?;
+            let operator = self.comparison_operator()?;
+            let right = self.num_expression()?;
+            Ok(Condition::NumComparison(left, operator, right))
+        }
+    }
+
+    /// Checks whether the current token starts a string operand.
+    ///
+    /// # Returns
+    ///
+    /// `true` for string literals, format strings, variables declared with 'let',
+    /// elements of lists of strings, values of maps of strings, string fields,
+    /// calls to built-ins and functions returning 'str', `false` otherwise
+    fn is_string_operand(&self) -> bool {
+        if let Some(element_type) = self.list_element_type().or_else(|| self.map_value_type()).or_else(|| self.field_type()) {
+            return element_type == Type::Str;
+        }
+        match &self.peek().token_type {
+            TokenType::StringLiteral(_) | TokenType::FormatStringPrefix => true,
+            TokenType::Identifier(name) if self.is_builtin_call() => builtin_return_type(name) == Some(Type::Str),
+            TokenType::Identifier(name) => self.variable_type(name) == Some(Type::Str),
+            TokenType::CallFn => match self.tokens.get(self.current + 1).map(|token| &token.token_type) {
+                Some(TokenType::Identifier(name)) => self
+                    .functions
+                    .get(name)
+                    .is_some_and(|signature| signature.return_type == Some(Type::Str)),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Checks whether the current token starts a boolean operand.
+    ///
+    /// # Returns
+    ///
+    /// `true` for 'true', 'false', variables declared with 'bool', elements of
+    /// lists of booleans, values of maps of booleans, boolean fields, 'has(...)'
+    /// and calls to built-ins and functions returning 'bool', `false` otherwise
+    fn is_bool_operand(&self) -> bool {
+        if let Some(element_type) = self.list_element_type().or_else(|| self.map_value_type()).or_else(|| self.field_type()) {
+            return element_type == Type::Bool;
+        }
+        match &self.peek().token_type {
+            TokenType::True | TokenType::False => true,
+            TokenType::Identifier(name) if name == "has" => {
+                self.tokens.get(self.current + 1).is_some_and(|next| next.token_type == TokenType::OpenParen)
+            }
+            TokenType::Identifier(name) if self.is_builtin_call() => builtin_return_type(name) == Some(Type::Bool),
+            TokenType::Identifier(name) => self.variable_type(name) == Some(Type::Bool),
+            TokenType::CallFn => match self.tokens.get(self.current + 1).map(|token| &token.token_type) {
+                Some(TokenType::Identifier(name)) => self
+                    .functions
+                    .get(name)
+                    .is_some_and(|signature| signature.return_type == Some(Type::Bool)),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Checks whether the current token is a comparison operator.
+    fn is_comparison_operator(&self) -> bool {
+        matches!(
+            self.peek().token_type,
+            TokenType::EqualEqual
+                | TokenType::NotEqual
+                | TokenType::Less
+                | TokenType::LessEqual
+                | TokenType::Greater
+                | TokenType::GreaterEqual
+        )
+    }
+
+    /// Consumes a comparison operator.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * The matching ComparisonOperator
+    /// * A formatted error message if the current token is not a comparison operator
+    fn comparison_operator(&mut self) -> Result<ComparisonOperator, String> {
+        let operator = match self.peek().token_type {
+            TokenType::EqualEqual => ComparisonOperator::Equal,
+            TokenType::NotEqual => ComparisonOperator::NotEqual,
+            TokenType::Less => ComparisonOperator::Less,
+            TokenType::LessEqual => ComparisonOperator::LessEqual,
+            TokenType::Greater => ComparisonOperator::Greater,
+            TokenType::GreaterEqual => ComparisonOperator::GreaterEqual,
+            _ => {
+                let token = self.peek().clone();
+                return Err(format_error(
+                    &self.source_path,
+                    &self.source,
+                    ErrorPosition {
+                        line: token.line,
+                        column: token.column,
+                    },
+                    "Expected comparison operator".to_string(),
+                    "Valid comparison operators are '==', '!=', '<', '<=', '>' and '>='".to_string(),
+                ));
+            }
+        };
+        self.advance();
+        Ok(operator)
+    }
+
+    fn assignment_statement(&mut self) -> Result<Statement, String> {
+        let line_number = self.peek().line;
+        
+        // Get the variable name
+        let name_token = self.advance().clone();
+        let name = match &name_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+        let var_type = self.resolve_variable(&name, &name_token)?;
+        if self.is_constant(&name) {
+            return Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: name_token.line,
+                    column: name_token.column,
+                },
+                format!("Cannot assign to constant '{}'", name),
+                "Constants can't change; declare a variable with 'num', 'int' or 'let' to hold a changing value".to_string(),
+            ));
+        }
+    
+        self.consume(TokenType::Equals, "Expected '=' after variable name")?;
+
+        // Boolean values can't be told apart syntactically, so rely on the declared type
+        if var_type == Type::Bool {
+            let condition = self.condition()?;
+            return Ok(Statement::BoolAssignment(name, condition, line_number));
+        }
+        if var_type == Type::Int {
+            let value = self.int_expression()?;
+            return Ok(Statement::IntAssignment(name, value, line_number));
+        }
+        if let Type::List(element_type) = &var_type {
+            let (_, value) = self.list_expression(Some(element_type))?;
+            return Ok(Statement::ListAssignment(name, value, line_number));
+        }
+        if let Type::Map(value_type) = &var_type {
+            let (_, value) = self.map_expression(Some(value_type))?;
+            return Ok(Statement::MapAssignment(name, value, line_number));
+        }
+        if let Type::Struct(struct_name) = &var_type {
+            let (_, value) = self.struct_expression(Some(struct_name))?;
+            return Ok(Statement::StructAssignment(name, value, line_number));
+        }
+        if let Type::Enum(enum_name) = &var_type {
+            let (_, value) = self.enum_expression(Some(enum_name))?;
+            return Ok(Statement::EnumAssignment(name, value, line_number));
+        }
+        if var_type == Type::Num {
+            let value = self.num_expression()?;
+            return Ok(Statement::NumAssignment(name, value, line_number));
+        }
+
+        // Values are never converted implicitly: a string takes a string expression
+        let value = self.expression()?;
+        Ok(Statement::VariableAssignment(name, value, line_number))
+    }
+
+    /// Parses a print statement, supporting both regular and format strings.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A Print or PrintFormat Statement object
+    /// * A formatted error message if parsing fails
+    fn print_statement(&mut self) -> Result<Statement, String> {
+        self.consume(TokenType::OpenParen, "Expected '(' after 'print'")?;
+
+        // A format string on its own is printed part by part, so that it can also
+        // run functions without a return value for their output
+        let format_only = matches!(self.peek().token_type, TokenType::FormatStringPrefix)
+            && matches!(
+                self.tokens.get(self.current + 2).map(|token| &token.token_type),
+                Some(TokenType::CloseParen)
+            );
+        if format_only {
+            self.advance(); // Consume the format string prefix
+            let expr_token = self.consume(TokenType::StringLiteral("".to_string()), "Expected string literal after 'o'")?.clone();
+            let expr = match &expr_token.token_type {
+                TokenType::StringLiteral(value) => value.clone(),
+                _ => unreachable!(),
+            };
+            self.consume(TokenType::CloseParen, "Expected ')' after expression")?;
+
+            let parts = self.parse_format_string(&expr, &expr_token, true)?;
+            return Ok(Statement::PrintFormat(parts));
+        }
+
+        // Printing a variable of any other type on its own: print(name). Calls
+        // to built-ins and built-in constants are values like any other.
+        if let TokenType::Identifier(name) = &self.peek().token_type
+            && self.variable_type(name).is_some()
+            && matches!(
+                self.tokens.get(self.current + 1).map(|token| &token.token_type),
+                Some(TokenType::CloseParen)
+            )
+            && !self.is_string_operand()
+        {
+            let name = name.clone();
+            self.advance();
+            self.consume(TokenType::CloseParen, "Expected ')' after expression")?;
+            return Ok(Statement::Print(Expression::Variable(name)));
+        }
+
+        // Printing a string expression, print("Hello, " + name), or a value of
+        // any other type, print(true), which is printed like an interpolation
+        let value_token = self.peek().clone();
+        let value = self.typed_value(&TokenType::CloseParen)?;
+        if let TypedExpression::Str(Expression::FunctionCall(name, _)) = &value
+            && self.functions.get(name).is_some_and(|signature| signature.return_type.is_none())
+        {
+            return Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: value_token.line,
+                    column: value_token.column,
+                },
+                format!("Function '{}' does not return a value", name),
+                "Call the function on its own line to run it".to_string(),
+            ));
+        }
+        self.consume(TokenType::CloseParen, "Expected ')' after expression")?;
+        match value {
+            TypedExpression::Str(expr) => Ok(Statement::Print(expr)),
+            value => Ok(Statement::PrintFormat(vec![FormatPart::Expression(value, FormatSpec::default())])),
+        }
+    }
+
+    /// Parses the content of a format string into a vector of FormatPart.
+    ///
+    /// Expressions appear between '{' and '}', optionally followed by a ':' and
+    /// a format specifier. Literal braces are written as '{{' and '}}'.
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - The format string content to parse
+    /// * `string_token` - The string literal token (for error positions)
+    /// * `in_print` - Whether the format string is printed directly, which allows
+    ///   calls to functions without a return value
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A vector of FormatPart representing literals and expressions
+    /// * A formatted error message if parsing fails
+    fn parse_format_string(&mut self, s: &str, string_token: &Token, in_print: bool) -> Result<Vec<FormatPart>, String> {
+        let mut parts = Vec::new();
+        let mut current_literal = String::new();
+        let mut i = 0;
+        let chars: Vec<char> = s.chars().collect();
+        let start = ErrorPosition {
+            line: string_token.line,
+            column: string_token.column,
+        };
+        let positions = literal_positions(&self.source, start, true);
+        let position_of = |index: usize| positions.get(index).copied().unwrap_or(start);
+        let error_at = |parser: &Self, index: usize, message: &str, hint: &str| {
+            format_error(
+                &parser.source_path,
+                &parser.source,
+                position_of(index),
+                message.to_string(),
+                hint.to_string(),
+            )
+        };
+        
+        while i < chars.len() {
+            if chars[i] == '{' && chars.get(i + 1) == Some(&'{') {
+                // Escaped literal brace
+                current_literal.push('{');
+                i += 2;
+            } else if chars[i] == '}' {
+                if chars.get(i + 1) != Some(&'}') {
+                    return Err(error_at(self, i, "Unmatched '}' in format string", "Write '}}' to print a literal '}'"));
+                }
+                current_literal.push('}');
+                i += 2;
+            } else if chars[i] == '{' {
+                if !current_literal.is_empty() {
+                    parts.push(FormatPart::Literal(current_literal.clone()));
+                    current_literal.clear();
+                }
+                let open = i;
+                i += 1;
+
+                // Find the closing brace and the ':' before a format specifier,
+                // skipping over string literals in the expression
+                let mut in_string = false;
+                let mut colon = None;
+                while i < chars.len() && (in_string || chars[i] != '}') {
+                    match chars[i] {
+                        '\\' if in_string => i += 1,
+                        '"' => in_string = !in_string,
+                        ':' if !in_string && colon.is_none() => colon = Some(i),
+                        _ => {}
+                    }
+                    i += 1;
+                }
+                if i >= chars.len() {
+                    return Err(error_at(self, open, "Unclosed '{' in format string", "Ensure all braces are properly closed, or write '{{' to print a literal '{'"));
+                }
+
+                let expr_end = colon.unwrap_or(i);
+                let expr_str: String = chars[open + 1..expr_end].iter().collect();
+                let expr = self.parse_format_expression(
+                    &expr_str,
+                    &positions[(open + 1).min(positions.len())..],
+                    position_of(expr_end),
+                )?;
+                if !in_print
+                    && let TypedExpression::Str(Expression::FunctionCall(name, _)) = &expr
+                    && self.functions.get(name).is_some_and(|signature| signature.return_type.is_none())
+                {
+                    return Err(error_at(
+                        self,
+                        open + 1,
+                        &format!("Function '{}' does not return a value", name),
+                        "Functions without a return value can only be interpolated when the format string is printed directly",
+                    ));
+                }
+                let spec = match colon {
+                    Some(colon) => self.parse_format_spec(&chars[colon + 1..i], &expr, |index, message| {
+                        error_at(self, colon + 1 + index, message, FORMAT_SPEC_HINT)
+                    })?,
+                    None => FormatSpec::default(),
+                };
+                parts.push(FormatPart::Expression(expr, spec));
+                i += 1;
+            } else {
+                current_literal.push(chars[i]);
+                i += 1;
+            }
+        }
+        if !current_literal.is_empty() {
+            parts.push(FormatPart::Literal(current_literal));
+        }
+        Ok(parts)
+    }
+
+    /// Parses and validates the format specifier of an interpolated value.
+    ///
+    /// The specifier has the form '[align][0][width][.precision][type]', where
+    /// align is '<', '>' or '^' and type is 'f', 'e' or 'g' for numbers or 'x'
+    /// for integers.
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - The characters after the ':'
+    /// * `value` - The value being formatted, whose type the specifier must suit
+    /// * `error_at` - Builds the error with the given message for the character at
+    ///   the given index of `spec`
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * The parsed FormatSpec
+    /// * A formatted error message if the specifier is invalid
+    fn parse_format_spec(
+        &self,
+        spec: &[char],
+        value: &TypedExpression,
+        error_at: impl Fn(usize, &str) -> String,
+    ) -> Result<FormatSpec, String> {
+        let mut result = FormatSpec::default();
+        let mut i = 0;
+        // Reads the digits of a width or precision, failing with the index where
+        // they start if the number is too large
+        let read_number = |i: &mut usize| {
+            let start = *i;
+            while *i < spec.len() && spec[*i].is_ascii_digit() {
+                *i += 1;
+            }
+            if start == *i {
+                return Ok(None);
+            }
+            match spec[start..*i].iter().collect::<String>().parse::<usize>() {
+                Ok(value) if value <= MAX_FORMAT_NUMBER => Ok(Some(value)),
+                _ => Err(start),
+            }
+        };
+        let too_large = |what: &str, index: usize| error_at(index, &format!("{} can be at most {}", what, MAX_FORMAT_NUMBER));
+
+        result.align = match spec.first() {
+            Some('<') => Some(Alignment::Left),
+            Some('>') => Some(Alignment::Right),
+            Some('^') => Some(Alignment::Center),
+            _ => None,
+        };
+        if result.align.is_some() {
+            i += 1;
+        }
+        let zero_pad_index = i;
+        if spec.get(i) == Some(&'0') && spec.get(i + 1).is_some_and(|c| c.is_ascii_digit()) {
+            result.zero_pad = true;
+            i += 1;
+        }
+        result.width = read_number(&mut i).map_err(|index| too_large("Width", index))?;
+        let precision_index = i;
+        if spec.get(i) == Some(&'.') {
+            i += 1;
+            result.precision = read_number(&mut i).map_err(|index| too_large("Precision", index))?;
+            if result.precision.is_none() {
+                return Err(error_at(i, "Expected digits after '.' in format specifier"));
+            }
+        }
+        let style_index = i;
+        result.style = match spec.get(i) {
+            Some('f') => Some(NumberStyle::Fixed),
+            Some('e') => Some(NumberStyle::Scientific),
+            Some('g') => Some(NumberStyle::General),
+            Some('x') => Some(NumberStyle::Hex),
+            _ => None,
+        };
+        if result.style.is_some() {
+            i += 1;
+        }
+        if i < spec.len() {
+            return Err(error_at(i, "Invalid format specifier"));
+        }
+
+        // Check that the options suit the type of the value
+        let is_int = matches!(value, TypedExpression::Int(_));
+        let is_num = matches!(value, TypedExpression::Num(_));
+        if let TypedExpression::Str(Expression::FunctionCall(name, _)) = value
+            && self.functions.get(name).is_some_and(|signature| signature.return_type.is_none())
+        {
+            return Err(error_at(0, "Functions without a return value can't be formatted"));
+        }
+        if result.zero_pad && !(is_int || is_num) {
+            return Err(error_at(zero_pad_index, "Zero padding is only supported for numbers"));
+        }
+        if result.zero_pad && matches!(result.align, Some(Alignment::Left | Alignment::Center)) {
+            return Err(error_at(zero_pad_index, "Zero padding can't be combined with '<' or '^' alignment"));
+        }
+        if result.precision.is_some() && is_int {
+            return Err(error_at(precision_index, "Precision is not supported for 'int' values"));
+        }
+        match result.style {
+            Some(NumberStyle::Hex) if !is_int => {
+                return Err(error_at(style_index, "'x' is only supported for 'int' values"));
+            }
+            Some(NumberStyle::Fixed | NumberStyle::Scientific | NumberStyle::General) if !is_num => {
+                return Err(error_at(style_index, "'f', 'e' and 'g' are only supported for 'num' values"));
+            }
+            _ => {}
+        }
+
+        Ok(result)
+    }
+
+    /// Parses an expression within a format string's braces.
+    ///
+    /// The expression is tokenized on its own and parsed by the regular
+    /// expression parser, with token positions mapped back into the source so
+    /// that errors point inside the braces.
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - The string content within '{...}'
+    /// * `positions` - The source position of each character of `s`
+    /// * `end` - The source position of the closing '}'
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A TypedExpression for the value to print
+    /// * A formatted error message if parsing fails
+    fn parse_format_expression(
+        &mut self,
+        s: &str,
+        positions: &[ErrorPosition],
+        end: ErrorPosition,
+    ) -> Result<TypedExpression, String> {
+        let start = positions.first().copied().unwrap_or(end);
+        let mut tokens = tokenize_fragment(s, &self.source, &self.source_path, start.line, start.column)?;
+        // The index of the first character of each line of the fragment, since
+        // the braces of a multi-line string may span lines
+        let line_starts: Vec<usize> = std::iter::once(0)
+            .chain(s.chars().enumerate().filter(|(_, c)| *c == '\n').map(|(index, _)| index + 1))
+            .collect();
+        for token in &mut tokens {
+            let index = match token.line - start.line {
+                0 => token.column - start.column,
+                offset => line_starts[offset] + token.column - 1,
+            };
+            let position = positions.get(index).copied().unwrap_or(end);
+            token.line = position.line;
+            token.column = position.column;
+        }
+
+        if matches!(tokens[0].token_type, TokenType::Eof) {
+            return Err(format_error(
+                &self.source_path,
+                &self.source,
+                end,
+                "Empty expression in format string".to_string(),
+                "Put a variable or expression between '{' and '}'".to_string(),
+            ));
+        }
+
+        // Parse the fragment's tokens in place of the program's, then restore them
+        let saved_tokens = std::mem::replace(&mut self.tokens, tokens);
+        let saved_current = std::mem::replace(&mut self.current, 0);
+        let result = self.typed_value(&TokenType::Eof);
+        self.tokens = saved_tokens;
+        self.current = saved_current;
+        result
+    }
+
+    /// Parses a value of any type, such as a format string expression or the
+    /// argument of 'print', inferring its type.
+    ///
+    /// Strings, lists, maps, structs and enums are recognized from their first
+    /// token, and fall back to a condition if a comparison follows them. Other
+    /// values are tried as integer, numerical and boolean expressions in turn;
+    /// if none of them covers the whole expression, the error that got furthest
+    /// is reported.
+    ///
+    /// # Arguments
+    ///
+    /// * `end` - The token that follows the value (left unconsumed)
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A TypedExpression for the value to print
+    /// * A formatted error message if parsing fails
+    fn typed_value(&mut self, end: &TokenType) -> Result<TypedExpression, String> {
+        self.reject_void_builtin_call(None)?;
+
+        // A function without a return value is executed for its output
+        if let TokenType::CallFn = self.peek().token_type
+            && let Some(TokenType::Identifier(name)) = self.tokens.get(self.current + 1).map(|token| &token.token_type)
+            && self.functions.get(name).is_some_and(|signature| signature.return_type.is_none())
+        {
+            self.advance();
+            let (name, args, _) = self.function_call()?;
+            self.expect_value_end(end)?;
+            return Ok(TypedExpression::Str(Expression::FunctionCall(name, args)));
+        }
+
+        let start = self.current;
+        let recognized = if self.is_string_operand() {
+            Some(self.expression().map(TypedExpression::Str))
+        } else if self.is_list_operand() {
+            Some(self.list_expression(None).map(|(_, expr)| TypedExpression::List(expr)))
+        } else if self.is_map_operand() {
+            Some(self.map_expression(None).map(|(_, expr)| TypedExpression::Map(expr)))
+        } else if self.is_struct_operand() {
+            Some(self.struct_expression(None).map(|(name, expr)| TypedExpression::Struct(name, expr)))
+        } else if self.is_enum_operand() {
+            Some(self.enum_expression(None).map(|(name, expr)| TypedExpression::Enum(name, expr)))
+        } else {
+            None
+        };
+        if let Some(result) = recognized {
+            let error = match result.and_then(|value| self.expect_value_end(end).map(|_| value)) {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            // The value may be the left side of a comparison, as in print(s == "a")
+            let reached = self.current;
+            self.current = start;
+            return match self.condition().and_then(|value| self.expect_value_end(end).map(|_| value)) {
+                Ok(condition) => Ok(TypedExpression::Bool(condition)),
+                Err(condition_error) if self.current > reached => Err(condition_error),
+                Err(_) => Err(error),
+            };
+        }
+
+        let mut furthest: Option<(usize, String)> = None;
+        for attempt in [Type::Int, Type::Num, Type::Bool] {
+            self.current = start;
+            let result = match attempt {
+                Type::Int => self.int_expression().map(TypedExpression::Int),
+                Type::Num => self.num_expression().map(TypedExpression::Num),
+                _ => self.condition().map(TypedExpression::Bool),
+            };
+            let error = match result.and_then(|value| self.expect_value_end(end).map(|_| value)) {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            if furthest.as_ref().is_none_or(|(position, _)| self.current > *position) {
+                furthest = Some((self.current, error));
+            }
+        }
+        Err(furthest.map(|(_, error)| error).unwrap_or_default())
+    }
+
+    /// Checks that a value has been parsed completely, up to the token that
+    /// follows it.
+    fn expect_value_end(&self, end: &TokenType) -> Result<(), String> {
+        if self.is_at_end() || self.check(end) {
+            return Ok(());
+        }
+        let (message, hint) = if *end == TokenType::Eof {
+            ("Unexpected token in format string expression", "Only a single expression can appear between '{' and '}'")
+        } else {
+            ("Expected ')' after expression", "Only a single value can be printed; use an o-string to print several")
+        };
+        let token = self.peek();
+        Err(format_error(
+            &self.source_path,
+            &self.source,
+            ErrorPosition {
+                line: token.line,
+                column: token.column,
+            },
+            message.to_string(),
+            hint.to_string(),
+        ))
+    }
+
+    /// Parses a constant declaration statement, whose 'const' keyword has
+    /// already been consumed: 'const NAME = value'.
+    ///
+    /// The value is a string, integer or number expression; its type is that of
+    /// the value. Whether it can be computed at compile time is checked by the
+    /// analysis pass, which also computes it.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A ConstDeclaration Statement object
+    /// * A formatted error message if parsing fails
+    fn const_statement(&mut self) -> Result<Statement, String> {
+        let keyword = self.previous().clone();
+        if self.in_function || self.block_depth > 0 {
+            return Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: keyword.line,
+                    column: keyword.column,
+                },
+                "Constants can only be declared at the top level".to_string(),
+                "Move the constant declaration to the top level of the program".to_string(),
+            ));
+        }
+
+        let name_token = self.consume(TokenType::Identifier("".to_string()), "Expected constant name after 'const'")?.clone();
+        let name = match &name_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+        let name_error = |parser: &Self, message: String, hint: &str| {
+            format_error(
+                &parser.source_path,
+                &parser.source,
+                ErrorPosition {
+                    line: name_token.line,
+                    column: name_token.column,
+                },
+                message,
+                hint.to_string(),
+            )
+        };
+        if self.constants.contains(&name) {
+            return Err(name_error(
+                self,
+                format!("Constant '{}' is already declared", name),
+                "Choose a different name for the constant",
+            ));
+        }
+        self.consume(TokenType::Equals, "Expected '=' after constant name")?;
+
+        let value_token = self.peek().clone();
+        let value = if self.is_string_operand() {
+            TypedExpression::Str(self.expression()?)
+        } else if self.is_bool_operand() || self.is_list_operand() || self.is_map_operand() || self.is_struct_operand() || self.is_enum_operand() {
+            return Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: value_token.line,
+                    column: value_token.column,
+                },
+                "Constants can only hold numbers, integers and strings".to_string(),
+                "Declare a variable to hold other values".to_string(),
+            ));
+        } else {
+            // Integer values are tried first so that they keep their exact value
+            let start = self.current;
+            match self.int_expression() {
+                Ok(expr) if matches!(self.peek().token_type, TokenType::Newline | TokenType::Eof) => TypedExpression::Int(expr),
+                _ => {
+                    self.current = start;
+                    TypedExpression::Num(self.num_expression()?)
+                }
+            }
+        };
+        let const_type = match &value {
+            TypedExpression::Str(_) => Type::Str,
+            TypedExpression::Int(_) => Type::Int,
+            _ => Type::Num,
+        };
+
+        // A global declared before the constant conflicts with it; one declared
+        // after it is reported at that declaration
+        if self.declared_globals.contains(&name)
+            && let Some(existing) = self.scopes[0].get(&name)
+        {
+            return Err(name_error(
+                self,
+                format!("Global variable '{}' is already declared as '{}'", name, type_name(existing)),
+                "Choose a name for the constant that isn't used by a variable",
+            ));
+        }
+        self.scopes[0].insert(name.clone(), const_type);
+        self.constants.insert(name.clone());
+        self.declared_globals.insert(name.clone());
+
+        Ok(Statement::ConstDeclaration(name, value, name_token.line, name_token.column))
+    }
+
+    /// Checks whether a name refers to a constant rather than to a variable
+    /// shadowing it.
+    fn is_constant(&self, name: &str) -> bool {
+        self.all_constants.contains(name) && self.scopes.iter().rposition(|scope| scope.contains_key(name)) == Some(0)
+    }
+
+    /// Parses a string variable declaration statement.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A VariableDeclaration Statement object
+    /// * A formatted error message if parsing fails
+    fn let_statement(&mut self) -> Result<Statement, String> {
+        // Store the current line number for error reporting
+        let line_number = self.peek().line;
+        
+        let name_token = self.consume(
+            TokenType::Identifier("".to_string()),
+            "Expected variable name",
+        )?.clone();
+        let name = match &name_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+
+        self.consume(TokenType::Equals, "Expected '=' after variable name")?;
+
+        let initializer = self.expression()?;
+
+        // Declare after the initializer, which still sees any variable being shadowed
+        self.declare_variable(&name_token, Type::Str)?;
+
+        Ok(Statement::VariableDeclaration(name, initializer, line_number))
+    }
+
+    /// Parses a numerical variable declaration statement.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A NumDeclaration Statement object
+    /// * A formatted error message if parsing fails
+    fn num_statement(&mut self) -> Result<Statement, String> {
+        // Store the current line number for error reporting
+        let line_number = self.peek().line;
+        
+        // Get the variable name
+        let name_token = self.consume(
+            TokenType::Identifier("".to_string()),
+            "Expected variable name after 'num'",
+        )?.clone();
+        let name = match &name_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+
+        // Expect assignment operator
+        self.consume(TokenType::Equals, "Expected '=' after variable name")?;
+
+        // Parse the numerical expression
+        let initializer = self.num_expression()?;
+
+        // Declare after the initializer, which still sees any variable being shadowed
+        self.declare_variable(&name_token, Type::Num)?;
+
+        Ok(Statement::NumDeclaration(name, initializer, line_number))
+    }
+
+    /// Parses an integer variable declaration statement.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * An IntDeclaration Statement object
+    /// * A formatted error message if parsing fails
+    fn int_statement(&mut self) -> Result<Statement, String> {
+        // Store the current line number for error reporting
+        let line_number = self.peek().line;
+
+        let name_token = self.consume(
+            TokenType::Identifier("".to_string()),
+            "Expected variable name after 'int'",
+        )?.clone();
+        let name = match &name_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+
+        self.consume(TokenType::Equals, "Expected '=' after variable name")?;
+
+        let initializer = self.int_expression()?;
+
+        // Declare after the initializer, which still sees any variable being shadowed
+        self.declare_variable(&name_token, Type::Int)?;
+
+        Ok(Statement::IntDeclaration(name, initializer, line_number))
+    }
+
+    /// Parses a boolean variable declaration statement.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A BoolDeclaration Statement object
+    /// * A formatted error message if parsing fails
+    fn bool_statement(&mut self) -> Result<Statement, String> {
+        // Store the current line number for error reporting
+        let line_number = self.peek().line;
+
+        let name_token = self.consume(
+            TokenType::Identifier("".to_string()),
+            "Expected variable name after 'bool'",
+        )?.clone();
+        let name = match &name_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+
+        self.consume(TokenType::Equals, "Expected '=' after variable name")?;
+
+        let initializer = self.condition()?;
+
+        // Declare after the initializer, which still sees any variable being shadowed
+        self.declare_variable(&name_token, Type::Bool)?;
+
+        Ok(Statement::BoolDeclaration(name, initializer, line_number))
+    }
+
+    /// Parses a list variable declaration statement: 'list name = [...]', or
+    /// 'list<type> name = ...' with an explicit element type. Without one, the
+    /// element type is inferred from the initializer, so it can't be empty.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A ListDeclaration Statement object
+    /// * A formatted error message if parsing fails
+    fn list_statement(&mut self) -> Result<Statement, String> {
+        // Store the current line number for error reporting
+        let line_number = self.peek().line;
+
+        let declared_type = if self.match_token(TokenType::Less) {
+            let element_token = self.peek().clone();
+            let element_type = self.parse_type()?;
+            if !is_element_type(&element_type) {
+                return Err(format_error(
+                    &self.source_path,
+                    &self.source,
+                    ErrorPosition {
+                        line: element_token.line,
+                        column: element_token.column,
+                    },
+                    format!("Lists of '{}' values are not supported", type_name(&element_type)),
+                    "Lists can hold 'num', 'int', 'str' or 'bool' values".to_string(),
+                ));
+            }
+            self.consume(TokenType::Greater, "Expected '>' after the element type")?;
+            Some(element_type)
+        } else {
+            None
+        };
+
+        let name_token = self.consume(
+            TokenType::Identifier("".to_string()),
+            "Expected variable name after 'list'",
+        )?.clone();
+        let name = match &name_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+
+        self.consume(TokenType::Equals, "Expected '=' after variable name")?;
+
+        let (element_type, initializer) = self.list_expression(declared_type.as_ref())?;
+
+        // Declare after the initializer, which still sees any variable being shadowed
+        self.declare_variable(&name_token, Type::List(Box::new(element_type.clone())))?;
+
+        Ok(Statement::ListDeclaration(name, element_type, initializer, line_number))
+    }
+
+    /// Parses an assignment to an element of a list: 'list[index] = value'.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A ListElementAssignment Statement object
+    /// * A formatted error message if parsing fails
+    fn list_element_assignment(&mut self) -> Result<Statement, String> {
+        let name_token = self.advance().clone();
+        let name = match &name_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+        let element_type = self.list_variable_element_type(&name, &name_token)?;
+
+        self.consume(TokenType::OpenBracket, "Expected '[' after list name")?;
+        let index = self.int_expression()?;
+        self.consume(TokenType::CloseBracket, "Expected ']' after the index")?;
+        self.consume(TokenType::Equals, "Expected '=' after the list element")?;
+        let value = self.typed_expression(&element_type, &format!("element of '{}'", name))?;
+
+        Ok(Statement::ListElementAssignment(name, index, value, name_token.line))
+    }
+
+    /// Parses a 'push(list, value)' or 'pop(list)' statement, which adds an
+    /// element to the end of a list or removes the last one.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A ListPush or ListPop Statement object
+    /// * A formatted error message if parsing fails
+    fn list_call_statement(&mut self) -> Result<Statement, String> {
+        let function_token = self.advance().clone();
+        self.consume(TokenType::OpenParen, "Expected '(' after function name")?;
+        let name_token = self.consume(TokenType::Identifier("".to_string()), "Expected list variable")?.clone();
+        let name = match &name_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+        let element_type = self.list_variable_element_type(&name, &name_token)?;
+
+        let statement = if function_token.token_type == TokenType::Identifier("push".to_string()) {
+            self.consume(TokenType::Comma, "Expected ',' after the list")?;
+            let value = self.typed_expression(&element_type, &format!("element of '{}'", name))?;
+            Statement::ListPush(name, value)
+        } else {
+            Statement::ListPop(name, function_token.line)
+        };
+        self.consume(TokenType::CloseParen, "Expected ')' after arguments")?;
+
+        Ok(statement)
+    }
+
+    /// Parses an exit statement: 'exit(code)'.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * An Exit Statement object
+    /// * A formatted error message if parsing fails
+    fn exit_statement(&mut self) -> Result<Statement, String> {
+        let keyword = self.advance().clone();
+        self.consume(TokenType::OpenParen, "Expected '(' after 'exit'")?;
+        let status = self.int_expression()?;
+        self.consume(TokenType::CloseParen, "Expected ')' after the exit status")?;
+        Ok(Statement::Exit(status, keyword.line))
+    }
+
+    /// Parses a statement changing a file: 'write_file(path, text)',
+    /// 'append_file(path, text)' or 'delete_file(path)'.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A WriteFile, AppendFile or DeleteFile Statement object
+    /// * A formatted error message if parsing fails
+    fn file_statement(&mut self) -> Result<Statement, String> {
+        let function_token = self.advance().clone();
+        let TokenType::Identifier(function) = &function_token.token_type else {
+            unreachable!()
+        };
+        self.consume(TokenType::OpenParen, "Expected '(' after function name")?;
+        let path = self.expression()?;
+
+        let statement = if function == "delete_file" {
+            Statement::DeleteFile(path, function_token.line)
+        } else {
+            self.consume(TokenType::Comma, "Expected ',' after the path")?;
+            let text = self.expression()?;
+            if function == "write_file" {
+                Statement::WriteFile(path, text, function_token.line)
+            } else {
+                Statement::AppendFile(path, text, function_token.line)
+            }
+        };
+        self.consume(TokenType::CloseParen, "Expected ')' after arguments")?;
+
+        Ok(statement)
+    }
+
+    /// Parses a map variable declaration statement: 'map name = {...}', or
+    /// 'map<type> name = ...' with an explicit value type. Without one, the
+    /// value type is inferred from the initializer, so it can't be empty.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A MapDeclaration Statement object
+    /// * A formatted error message if parsing fails
+    fn map_statement(&mut self) -> Result<Statement, String> {
+        // Store the current line number for error reporting
+        let line_number = self.peek().line;
+
+        let declared_type = if self.match_token(TokenType::Less) {
+            let value_token = self.peek().clone();
+            let value_type = self.parse_type()?;
+            if !is_element_type(&value_type) {
+                return Err(format_error(
+                    &self.source_path,
+                    &self.source,
+                    ErrorPosition {
+                        line: value_token.line,
+                        column: value_token.column,
+                    },
+                    format!("Maps of '{}' values are not supported", type_name(&value_type)),
+                    "Maps can hold 'num', 'int', 'str' or 'bool' values".to_string(),
+                ));
+            }
+            self.consume(TokenType::Greater, "Expected '>' after the value type")?;
+            Some(value_type)
+        } else {
+            None
+        };
+
+        let name_token = self.consume(
+            TokenType::Identifier("".to_string()),
+            "Expected variable name after 'map'",
+        )?.clone();
+        let name = match &name_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+
+        self.consume(TokenType::Equals, "Expected '=' after variable name")?;
+
+        let (value_type, initializer) = self.map_expression(declared_type.as_ref())?;
+
+        // Declare after the initializer, which still sees any variable being shadowed
+        self.declare_variable(&name_token, Type::Map(Box::new(value_type.clone())))?;
+
+        Ok(Statement::MapDeclaration(name, value_type, initializer, line_number))
+    }
+
+    /// Parses a store into a map: 'map[key] = value'.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A MapInsert Statement object
+    /// * A formatted error message if parsing fails
+    fn map_insert_statement(&mut self) -> Result<Statement, String> {
+        let name_token = self.advance().clone();
+        let name = match &name_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+        let value_type = self.map_variable_value_type(&name, &name_token)?;
+
+        self.consume(TokenType::OpenBracket, "Expected '[' after map name")?;
+        let key = self.expression()?;
+        self.consume(TokenType::CloseBracket, "Expected ']' after the key")?;
+        self.consume(TokenType::Equals, "Expected '=' after the map key")?;
+        let value = self.typed_expression(&value_type, &format!("value of '{}'", name))?;
+
+        Ok(Statement::MapInsert(name, key, value))
+    }
+
+    /// Parses a 'remove(map, key)' statement, which removes a key and its
+    /// value from a map.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A MapRemove Statement object
+    /// * A formatted error message if parsing fails
+    fn map_remove_statement(&mut self) -> Result<Statement, String> {
+        let function_token = self.advance().clone();
+        self.consume(TokenType::OpenParen, "Expected '(' after function name")?;
+        let name_token = self.consume(TokenType::Identifier("".to_string()), "Expected map variable")?.clone();
+        let name = match &name_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+        self.map_variable_value_type(&name, &name_token)?;
+
+        self.consume(TokenType::Comma, "Expected ',' after the map")?;
+        let key = self.expression()?;
+        self.consume(TokenType::CloseParen, "Expected ')' after arguments")?;
+
+        Ok(Statement::MapRemove(name, key, function_token.line))
+    }
+
+    /// Parses a struct definition, whose 'struct' keyword has already been
+    /// consumed: 'struct Point { x: num, y: num }'. Fields are separated by
+    /// commas or newlines.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A StructDefinition Statement object
+    /// * A formatted error message if parsing fails
+    fn struct_definition(&mut self) -> Result<Statement, String> {
+        let keyword = self.previous().clone();
+        if self.in_function || self.block_depth > 0 {
+            return Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: keyword.line,
+                    column: keyword.column,
+                },
+                "Struct definitions are only allowed at the top level".to_string(),
+                "Move the struct definition to the top level of the program".to_string(),
+            ));
+        }
+
+        let name_token = self.consume(TokenType::Identifier("".to_string()), "Expected struct name after 'struct'")?.clone();
+        let name = match &name_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+        if name == "str" || self.defined_types.contains(&name) {
+            return Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: name_token.line,
+                    column: name_token.column,
+                },
+                format!("Type '{}' is already defined", name),
+                "Give the struct a name that isn't used by another type".to_string(),
+            ));
+        }
+        self.consume(TokenType::OpenBrace, "Expected '{' after struct name")?;
+
+        let mut fields: Vec<(String, Type)> = Vec::new();
+        loop {
+            while self.match_token(TokenType::Newline) || self.match_token(TokenType::Comma) {}
+            if self.match_token(TokenType::CloseBrace) {
+                break;
+            }
+
+            let field_token = self.consume(TokenType::Identifier("".to_string()), "Expected field name or '}'")?.clone();
+            let field = match &field_token.token_type {
+                TokenType::Identifier(field) => field.clone(),
+                _ => unreachable!(),
+            };
+            let field_error = |parser: &Self, message: String, hint: &str| {
+                format_error(
+                    &parser.source_path,
+                    &parser.source,
+                    ErrorPosition {
+                        line: field_token.line,
+                        column: field_token.column,
+                    },
+                    message,
+                    hint.to_string(),
+                )
+            };
+            if fields.iter().any(|(existing, _)| *existing == field) {
+                return Err(field_error(
+                    self,
+                    format!("Duplicate field '{}'", field),
+                    "Each field of a struct must have a unique name",
+                ));
+            }
+            self.consume(TokenType::Colon, "Expected ':' after field name")?;
+            let field_type = self.parse_type()?;
+            if let Type::Struct(field_struct) | Type::Enum(field_struct) = &field_type
+                && !self.defined_types.contains(field_struct)
+            {
+                return Err(field_error(
+                    self,
+                    format!("Field '{}' uses type '{}' before its definition", field, field_struct),
+                    "Define a struct or enum before the structs whose fields hold it",
+                ));
+            }
+            fields.push((field, field_type));
+        }
+
+        self.defined_types.insert(name.clone());
+        Ok(Statement::StructDefinition(name, fields))
+    }
+
+    /// Parses a struct variable declaration statement: 'Point p = ...', where
+    /// the name of the struct takes the place of the type keyword.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A StructDeclaration Statement object
+    /// * A formatted error message if parsing fails
+    fn struct_variable_statement(&mut self) -> Result<Statement, String> {
+        // Store the current line number for error reporting
+        let line_number = self.peek().line;
+
+        let struct_name = match &self.advance().token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+        let name_token = self.advance().clone();
+        let name = match &name_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+
+        self.consume(TokenType::Equals, "Expected '=' after variable name")?;
+
+        let (_, initializer) = self.struct_expression(Some(&struct_name))?;
+
+        // Declare after the initializer, which still sees any variable being shadowed
+        self.declare_variable(&name_token, Type::Struct(struct_name.clone()))?;
+
+        Ok(Statement::StructDeclaration(name, struct_name, initializer, line_number))
+    }
+
+    /// Parses an assignment to a field of a struct variable: 'point.x = value'.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A FieldAssignment Statement object
+    /// * A formatted error message if parsing fails
+    fn field_assignment_statement(&mut self) -> Result<Statement, String> {
+        let (access, field_type) = self.field_path()?;
+        self.consume(TokenType::Equals, "Expected '=' after the field")?;
+        let value = self.typed_expression(&field_type, &format!("field '{}'", field_name(&access)))?;
+        Ok(Statement::FieldAssignment(access, value))
+    }
+
+    /// Parses a string expression, which may concatenate several values with '+'.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * An Expression object
+    /// * A formatted error message if parsing fails
+    fn expression(&mut self) -> Result<Expression, String> {
+        let first = self.string_primary()?;
+        if !self.check(&TokenType::Plus) {
+            return Ok(first);
+        }
+
+        let mut operands = vec![TypedExpression::Str(first)];
+        while self.match_token(TokenType::Plus) {
+            operands.push(self.concat_operand()?);
+        }
+        Ok(Expression::Concat(operands))
+    }
+
+    /// Parses an operand of a string concatenation after a '+'.
+    ///
+    /// Strings are used as they are; booleans, integers, numbers, lists, maps,
+    /// structs and enums are converted to text. Arithmetic operators other than '+' bind tighter
+    /// than the concatenation, so '"n: " + n * 2' appends the product.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A TypedExpression for the operand
+    /// * A formatted error message if parsing fails
+    fn concat_operand(&mut self) -> Result<TypedExpression, String> {
+        if self.is_string_operand() {
+            return Ok(TypedExpression::Str(self.string_primary()?));
+        }
+        if self.is_bool_operand() {
+            return Ok(TypedExpression::Bool(self.primary_condition()?));
+        }
+        if self.is_list_operand() {
+            let (_, expr) = self.list_expression(None)?;
+            return Ok(TypedExpression::List(expr));
+        }
+        if self.is_map_operand() {
+            let (_, expr) = self.map_expression(None)?;
+            return Ok(TypedExpression::Map(expr));
+        }
+        if self.is_struct_operand() {
+            let (name, expr) = self.struct_expression(None)?;
+            return Ok(TypedExpression::Struct(name, expr));
+        }
+        if self.is_enum_operand() {
+            let (name, expr) = self.enum_expression(None)?;
+            return Ok(TypedExpression::Enum(name, expr));
+        }
+
+        // '(' may group a string expression or an arithmetic one
+        let start = self.current;
+        if self.check(&TokenType::OpenParen)
+            && let Ok(expr) = self.string_primary()
+        {
+            return Ok(TypedExpression::Str(expr));
+        }
+        self.current = start;
+
+        // Integer operands are tried first so that they keep their exact value
+        if let Ok(expr) = self.int_multiplication() {
+            return Ok(TypedExpression::Int(expr));
+        }
+        self.current = start;
+        Ok(TypedExpression::Num(self.num_multiplication()?))
+    }
+
+    /// Parses a single string value: a literal, a format string, a variable, a
+    /// function call or a parenthesized string expression.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * An Expression object
+    /// * A formatted error message if parsing fails
+    fn string_primary(&mut self) -> Result<Expression, String> {
+        if let Some(element) = self.list_element(&Type::Str)? {
+            return Ok(Expression::ListElement(element));
+        }
+        if let Some(value) = self.map_value(&Type::Str)? {
+            return Ok(Expression::MapValue(value));
+        }
+        if let Some(access) = self.field_access(&Type::Str)? {
+            return Ok(Expression::Field(access));
+        }
+        match &self.peek().token_type {
+            TokenType::StringLiteral(_) => {
+                let token = self.advance();
+                if let TokenType::StringLiteral(value) = &token.token_type {
+                    Ok(Expression::StringLiteral(value.clone()))
+                } else {
+                    unreachable!()
+                }
+            }
+            TokenType::FormatStringPrefix => {
+                self.advance();
+                let string_token = self.consume(TokenType::StringLiteral("".to_string()), "Expected string literal after 'o'")?.clone();
+                let content = match &string_token.token_type {
+                    TokenType::StringLiteral(value) => value.clone(),
+                    _ => unreachable!(),
+                };
+                let parts = self.parse_format_string(&content, &string_token, false)?;
+                Ok(Expression::Format(parts))
+            }
+            TokenType::Identifier(_) => {
+                let token = self.advance().clone();
+                if let TokenType::Identifier(name) = &token.token_type {
+                    if self.check(&TokenType::OpenParen) {
+                        match name.as_str() {
+                            // A line read from standard input: 'input("prompt")'
+                            "input" => return Ok(Expression::Input(self.input_prompt()?, token.line)),
+                            "tostr" => {
+                                self.advance();
+                                let value = self.tostr_argument()?;
+                                return Ok(Expression::ToStr(Box::new(value)));
+                            }
+                            "read_file" => {
+                                // The contents of a file: 'read_file(path)'
+                                self.advance();
+                                let path = self.expression()?;
+                                self.consume(TokenType::CloseParen, "Expected ')' after the path")?;
+                                return Ok(Expression::ReadFile(Box::new(path), token.line));
+                            }
+                            "arg" => {
+                                // A command-line argument: 'arg(index)'
+                                self.advance();
+                                let index = self.int_expression()?;
+                                self.consume(TokenType::CloseParen, "Expected ')' after the index")?;
+                                return Ok(Expression::Arg(Box::new(index), token.line));
+                            }
+                            "env" => {
+                                // An environment variable, with an optional default: 'env(name, default)'
+                                self.advance();
+                                let name = self.expression()?;
+                                let default = if self.match_token(TokenType::Comma) {
+                                    Some(Box::new(self.expression()?))
+                                } else {
+                                    None
+                                };
+                                self.consume(TokenType::CloseParen, "Expected ')' after arguments")?;
+                                return Ok(Expression::Env(Box::new(name), default, token.line));
+                            }
+                            _ => {
+                                self.check_builtin_type(name, &Type::Str, &token)?;
+                                if string_function(name).is_some() {
+                                    let (function, args) = self.string_call(name, &token)?;
+                                    return Ok(Expression::StringCall(function, args, token.line));
+                                }
+                            }
+                        }
+                    }
+                    let var_type = self.resolve_variable(name, &token)?;
+                    if var_type != Type::Str {
+                        return Err(self.variable_type_error(name, &var_type, &Type::Str, &token));
+                    }
+                    Ok(Expression::Variable(name.clone()))
+                } else {
+                    unreachable!()
+                }
+            }
+            TokenType::CallFn => {
+                let call_token = self.advance().clone();
+                let (name, args, return_type) = self.function_call()?;
+                if return_type != Some(Type::Str) {
+                    return Err(self.call_type_error(&name, return_type.as_ref(), &Type::Str, &call_token));
+                }
+                Ok(Expression::FunctionCall(name, args))
+            }
+            TokenType::OpenParen => {
+                self.advance();
+                let expr = self.expression()?;
+                self.consume(TokenType::CloseParen, "Expected ')' after expression")?;
+                Ok(expr)
+            }
+            _ => {
+                let token = self.peek().clone();
+                let hint = if matches!(token.token_type, TokenType::NumberLiteral(_) | TokenType::IntegerLiteral(_)) {
+                    conversion_hint(&Type::Num, &Type::Str)
+                } else {
+                    "Valid expressions are string literals, format strings, variable identifiers, function calls and concatenations with '+'".to_string()
+                };
+                Err(format_error(
+                    &self.source_path,
+                    &self.source,
+                    ErrorPosition {
+                        line: token.line,
+                        column: token.column,
+                    },
+                    "Expected expression".to_string(),
+                    hint,
+                ))
+            }
+        }
+    }
+
+    /// Parses an expression producing a list: a list literal, a list variable or
+    /// field, the keys of a map or a call to a function returning a list.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected` - The required element type, or None to infer it
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * The element type and the ListExpression
+    /// * A formatted error message if parsing fails or the element type doesn't match
+    fn list_expression(&mut self, expected: Option<&Type>) -> Result<(Type, ListExpression), String> {
+        let token = self.peek().clone();
+        let error = |parser: &Self, message: String, hint: &str| {
+            format_error(
+                &parser.source_path,
+                &parser.source,
+                ErrorPosition {
+                    line: token.line,
+                    column: token.column,
+                },
+                message,
+                hint.to_string(),
+            )
+        };
+
+        let (element_type, expr) = match &token.token_type {
+            TokenType::OpenBracket => return self.list_literal(expected),
+            TokenType::Identifier(_) if self.is_field_access() => {
+                let (access, field_type) = self.field_path()?;
+                let Type::List(element_type) = field_type else {
+                    return Err(error(
+                        self,
+                        format!("Field '{}' is {}, but a list is expected here", field_name(&access), a_type_name(&field_type)),
+                        "Use a field of the expected type",
+                    ));
+                };
+                (*element_type, ListExpression::Field(access))
+            }
+            TokenType::Identifier(name) if name == "keys"
+                && self.tokens.get(self.current + 1).is_some_and(|next| next.token_type == TokenType::OpenParen) =>
+            {
+                // The keys of a map: 'keys(map)'
+                self.advance();
+                self.advance();
+                let (_, map) = self.map_expression(None)?;
+                self.consume(TokenType::CloseParen, "Expected ')' after the map")?;
+                (Type::Str, ListExpression::MapKeys(map))
+            }
+            TokenType::Identifier(name) if self.is_builtin_call() => {
+                let name = name.clone();
+                let Some(return_type) = builtin_return_type(&name) else {
+                    unreachable!()
+                };
+                let Type::List(element_type) = return_type else {
+                    return Err(error(
+                        self,
+                        format!("Function '{}' returns '{}', but a list is expected here", name, type_name(&return_type)),
+                        "Call a function whose return type matches how its result is used",
+                    ));
+                };
+                // The parts of a string: 'split(text, separator)'
+                self.advance();
+                let (function, args) = self.string_call(&name, &token)?;
+                (*element_type, ListExpression::StringCall(function, args, token.line))
+            }
+            TokenType::Identifier(name) => {
+                self.advance();
+                let element_type = self.list_variable_element_type(name, &token)?;
+                (element_type, ListExpression::Variable(name.clone()))
+            }
+            TokenType::CallFn => {
+                self.advance();
+                let (name, args, return_type) = self.function_call()?;
+                let Some(Type::List(element_type)) = return_type else {
+                    let found = match return_type {
+                        Some(return_type) => format!("returns '{}'", type_name(&return_type)),
+                        None => "does not return a value".to_string(),
+                    };
+                    return Err(error(
+                        self,
+                        format!("Function '{}' {}, but a list is expected here", name, found),
+                        "Call a function whose return type matches how its result is used",
+                    ));
+                };
+                (*element_type, ListExpression::FunctionCall(name, args))
+            }
+            _ => {
+                return Err(error(
+                    self,
+                    "Expected list expression".to_string(),
+                    "Valid list expressions are list literals such as '[1, 2, 3]', list variables and fields, keys(...) and function calls",
+                ));
+            }
+        };
+
+        if let Some(expected) = expected
+            && element_type != *expected
+        {
+            return Err(error(
+                self,
+                format!(
+                    "Expected a list of '{}' values, found a list of '{}' values",
+                    type_name(expected),
+                    type_name(&element_type)
+                ),
+                "Lists can only be used where a list with the same element type is expected",
+            ));
+        }
+        Ok((element_type, expr))
+    }
+
+    /// Parses a list literal: '[' followed by comma-separated elements and ']'.
+    ///
+    /// Without an expected element type, the type is inferred from the
+    /// elements: strings are recognized from the first element, otherwise the
+    /// elements are tried as integers, numbers and booleans in turn.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected` - The required element type, or None to infer it
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * The element type and the ListExpression::Literal
+    /// * A formatted error message if parsing fails
+    fn list_literal(&mut self, expected: Option<&Type>) -> Result<(Type, ListExpression), String> {
+        let open = self.advance().clone();
+        self.skip_newlines();
+
+        let element_type = match expected {
+            Some(expected) => expected.clone(),
+            None if self.check(&TokenType::CloseBracket) => {
+                return Err(format_error(
+                    &self.source_path,
+                    &self.source,
+                    ErrorPosition {
+                        line: open.line,
+                        column: open.column,
+                    },
+                    "Cannot infer the element type of an empty list".to_string(),
+                    "Declare the element type, e.g. 'list<num> values = []'".to_string(),
+                ));
+            }
+            None if self.is_string_operand() => Type::Str,
+            None => {
+                let start = self.current;
+                let mut furthest: Option<(usize, String)> = None;
+                for attempt in [Type::Int, Type::Num, Type::Bool] {
+                    self.current = start;
+                    let error = match self.list_elements(&attempt) {
+                        Ok(elements) => return Ok((attempt.clone(), ListExpression::Literal(attempt, elements))),
+                        Err(error) => error,
+                    };
+                    if furthest.as_ref().is_none_or(|(position, _)| self.current > *position) {
+                        furthest = Some((self.current, error));
+                    }
+                }
+                return Err(furthest.map(|(_, error)| error).unwrap_or_default());
+            }
+        };
+
+        let elements = self.list_elements(&element_type)?;
+        Ok((element_type.clone(), ListExpression::Literal(element_type, elements)))
+    }
+
+    /// Parses the elements of a list literal after the '[', up to and including
+    /// the closing ']'. Elements may be spread over several lines.
+    ///
+    /// # Arguments
+    ///
+    /// * `element_type` - The type every element must have
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * The elements of the list
+    /// * A formatted error message if parsing fails
+    fn list_elements(&mut self, element_type: &Type) -> Result<Vec<TypedExpression>, String> {
+        let mut elements = Vec::new();
+        while !self.check(&TokenType::CloseBracket) {
+            elements.push(self.typed_expression(element_type, "list element")?);
+            self.skip_newlines();
+            if !self.match_token(TokenType::Comma) {
+                break;
+            }
+            self.skip_newlines();
+        }
+        self.consume(TokenType::CloseBracket, "Expected ']' after list elements")?;
+        Ok(elements)
+    }
+
+    /// Resolves a variable that must be a list.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The referenced variable name
+    /// * `token` - The token of the reference (for the error position)
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * The element type of the list
+    /// * A formatted error message if the variable is not in scope or not a list
+    fn list_variable_element_type(&self, name: &str, token: &Token) -> Result<Type, String> {
+        match self.resolve_variable(name, token)? {
+            Type::List(element_type) => Ok(*element_type),
+            var_type => Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: token.line,
+                    column: token.column,
+                },
+                format!("Variable '{}' is {}, but a list is expected here", name, a_type_name(&var_type)),
+                "Declare the variable with 'list' to use it as a list".to_string(),
+            )),
+        }
+    }
+
+    /// Parses a read of a list element, 'list[index]' or 'pop(list)', if the
+    /// current tokens start one.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected` - The type the element must have
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * The ListElement, or None if the current tokens don't read a list element
+    /// * A formatted error message if parsing fails or the element type doesn't match
+    fn list_element(&mut self, expected: &Type) -> Result<Option<ListElement>, String> {
+        let token = self.peek().clone();
+        let next = self.tokens.get(self.current + 1).map(|token| &token.token_type);
+        let is_pop = token.token_type == TokenType::Identifier("pop".to_string()) && next == Some(&TokenType::OpenParen);
+        let is_index = match &token.token_type {
+            TokenType::Identifier(name) => {
+                next == Some(&TokenType::OpenBracket) && !matches!(self.variable_type(name), Some(Type::Map(_)))
+            }
+            _ => false,
+        };
+        if !is_pop && !is_index {
+            return Ok(None);
+        }
+
+        self.advance();
+        self.advance();
+        let name_token = if is_pop {
+            self.consume(TokenType::Identifier("".to_string()), "Expected list variable")?.clone()
+        } else {
+            token.clone()
+        };
+        let name = match &name_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+        let element_type = self.list_variable_element_type(&name, &name_token)?;
+        if element_type != *expected {
+            return Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: name_token.line,
+                    column: name_token.column,
+                },
+                format!(
+                    "List '{}' holds '{}' values, but {} is expected here",
+                    name,
+                    type_name(&element_type),
+                    a_type_name(expected)
+                ),
+                conversion_hint(&element_type, expected),
+            ));
+        }
+
+        let element = if is_pop {
+            self.consume(TokenType::CloseParen, "Expected ')' after the list")?;
+            ListElement::Pop(name, token.line)
+        } else {
+            let index = self.int_expression()?;
+            self.consume(TokenType::CloseBracket, "Expected ']' after the index")?;
+            ListElement::Index(name, Box::new(index), token.line)
+        };
+        Ok(Some(element))
+    }
+
+    /// Returns the element type of the list read by the current tokens, if they
+    /// start a list element ('list[index]' or 'pop(list)').
+    fn list_element_type(&self) -> Option<Type> {
+        let token_at = |offset: usize| self.tokens.get(self.current + offset).map(|token| &token.token_type);
+        let name = match (token_at(0), token_at(1), token_at(2)) {
+            (Some(TokenType::Identifier(name)), Some(TokenType::OpenBracket), _) => name,
+            (Some(TokenType::Identifier(function)), Some(TokenType::OpenParen), Some(TokenType::Identifier(name)))
+                if function == "pop" => name,
+            _ => return None,
+        };
+        match self.variable_type(name) {
+            Some(Type::List(element_type)) => Some(*element_type),
+            _ => None,
+        }
+    }
+
+    /// Checks whether the current token starts a list operand.
+    ///
+    /// # Returns
+    ///
+    /// `true` for list literals, list variables that aren't indexed, list
+    /// fields, 'keys(...)' and calls to built-ins and functions returning a
+    /// list, `false` otherwise
+    fn is_list_operand(&self) -> bool {
+        if self.is_field_access() {
+            return matches!(self.field_type(), Some(Type::List(_)));
+        }
+        match &self.peek().token_type {
+            TokenType::OpenBracket => true,
+            TokenType::Identifier(name) if name == "keys" => {
+                self.tokens.get(self.current + 1).is_some_and(|next| next.token_type == TokenType::OpenParen)
+            }
+            TokenType::Identifier(name) if self.is_builtin_call() => {
+                matches!(builtin_return_type(name), Some(Type::List(_)))
+            }
+            TokenType::Identifier(name) => {
+                matches!(self.variable_type(name), Some(Type::List(_)))
+                    && !matches!(
+                        self.tokens.get(self.current + 1).map(|token| &token.token_type),
+                        Some(TokenType::OpenBracket)
+                    )
+            }
+            TokenType::CallFn => match self.tokens.get(self.current + 1).map(|token| &token.token_type) {
+                Some(TokenType::Identifier(name)) => self
+                    .functions
+                    .get(name)
+                    .is_some_and(|signature| matches!(signature.return_type, Some(Type::List(_)))),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Parses an expression producing a map: a map literal, a map variable or
+    /// field, or a call to a function returning a map.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected` - The required value type, or None to infer it
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * The value type and the MapExpression
+    /// * A formatted error message if parsing fails or the value type doesn't match
+    fn map_expression(&mut self, expected: Option<&Type>) -> Result<(Type, MapExpression), String> {
+        let token = self.peek().clone();
+        let error = |parser: &Self, message: String, hint: &str| {
+            format_error(
+                &parser.source_path,
+                &parser.source,
+                ErrorPosition {
+                    line: token.line,
+                    column: token.column,
+                },
+                message,
+                hint.to_string(),
+            )
+        };
+
+        let (value_type, expr) = match &token.token_type {
+            TokenType::OpenBrace => return self.map_literal(expected),
+            TokenType::Identifier(_) if self.is_field_access() => {
+                let (access, field_type) = self.field_path()?;
+                let Type::Map(value_type) = field_type else {
+                    return Err(error(
+                        self,
+                        format!("Field '{}' is {}, but a map is expected here", field_name(&access), a_type_name(&field_type)),
+                        "Use a field of the expected type",
+                    ));
+                };
+                (*value_type, MapExpression::Field(access))
+            }
+            TokenType::Identifier(name) => {
+                self.advance();
+                let value_type = self.map_variable_value_type(name, &token)?;
+                (value_type, MapExpression::Variable(name.clone()))
+            }
+            TokenType::CallFn => {
+                self.advance();
+                let (name, args, return_type) = self.function_call()?;
+                let Some(Type::Map(value_type)) = return_type else {
+                    let found = match return_type {
+                        Some(return_type) => format!("returns '{}'", type_name(&return_type)),
+                        None => "does not return a value".to_string(),
+                    };
+                    return Err(error(
+                        self,
+                        format!("Function '{}' {}, but a map is expected here", name, found),
+                        "Call a function whose return type matches how its result is used",
+                    ));
+                };
+                (*value_type, MapExpression::FunctionCall(name, args))
+            }
+            _ => {
+                return Err(error(
+                    self,
+                    "Expected map expression".to_string(),
+                    "Valid map expressions are map literals such as '{\"alice\": 30}', map variables and fields, and function calls",
+                ));
+            }
+        };
+
+        if let Some(expected) = expected
+            && value_type != *expected
+        {
+            return Err(error(
+                self,
+                format!(
+                    "Expected a map of '{}' values, found a map of '{}' values",
+                    type_name(expected),
+                    type_name(&value_type)
+                ),
+                "Maps can only be used where a map with the same value type is expected",
+            ));
+        }
+        Ok((value_type, expr))
+    }
+
+    /// Parses a map literal: '{' followed by comma-separated 'key: value'
+    /// entries and '}'. Keys are string expressions.
+    ///
+    /// Without an expected value type, the type is inferred from the values in
+    /// the same way as the element type of a list literal.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected` - The required value type, or None to infer it
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * The value type and the MapExpression::Literal
+    /// * A formatted error message if parsing fails
+    fn map_literal(&mut self, expected: Option<&Type>) -> Result<(Type, MapExpression), String> {
+        let open = self.advance().clone();
+        self.skip_newlines();
+
+        let value_type = match expected {
+            Some(expected) => expected.clone(),
+            None if self.check(&TokenType::CloseBrace) => {
+                return Err(format_error(
+                    &self.source_path,
+                    &self.source,
+                    ErrorPosition {
+                        line: open.line,
+                        column: open.column,
+                    },
+                    "Cannot infer the value type of an empty map".to_string(),
+                    "Declare the value type, e.g. 'map<num> scores = {}'".to_string(),
+                ));
+            }
+            None => {
+                // The first value decides between strings and the other types
+                let start = self.current;
+                self.expression()?;
+                self.consume(TokenType::Colon, "Expected ':' after the key")?;
+                let is_string = self.is_string_operand();
+                self.current = start;
+                if is_string {
+                    Type::Str
+                } else {
+                    let mut furthest: Option<(usize, String)> = None;
+                    for attempt in [Type::Int, Type::Num, Type::Bool] {
+                        self.current = start;
+                        let error = match self.map_entries(&attempt) {
+                            Ok(entries) => return Ok((attempt.clone(), MapExpression::Literal(attempt, entries))),
+                            Err(error) => error,
+                        };
+                        if furthest.as_ref().is_none_or(|(position, _)| self.current > *position) {
+                            furthest = Some((self.current, error));
+                        }
+                    }
+                    return Err(furthest.map(|(_, error)| error).unwrap_or_default());
+                }
+            }
+        };
+
+        let entries = self.map_entries(&value_type)?;
+        Ok((value_type.clone(), MapExpression::Literal(value_type, entries)))
+    }
+
+    /// Parses the entries of a map literal after the '{', up to and including
+    /// the closing '}'. Entries may be spread over several lines.
+    ///
+    /// # Arguments
+    ///
+    /// * `value_type` - The type every value must have
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * The key-value pairs of the map
+    /// * A formatted error message if parsing fails
+    fn map_entries(&mut self, value_type: &Type) -> Result<Vec<(Expression, TypedExpression)>, String> {
+        let mut entries = Vec::new();
+        while !self.check(&TokenType::CloseBrace) {
+            let key = self.expression()?;
+            self.consume(TokenType::Colon, "Expected ':' after the key")?;
+            let value = self.typed_expression(value_type, "map value")?;
+            entries.push((key, value));
+            self.skip_newlines();
+            if !self.match_token(TokenType::Comma) {
+                break;
+            }
+            self.skip_newlines();
+        }
+        self.consume(TokenType::CloseBrace, "Expected '}' after map entries")?;
+        Ok(entries)
+    }
+
+    /// Resolves a variable that must be a map.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The referenced variable name
+    /// * `token` - The token of the reference (for the error position)
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * The value type of the map
+    /// * A formatted error message if the variable is not in scope or not a map
+    fn map_variable_value_type(&self, name: &str, token: &Token) -> Result<Type, String> {
+        match self.resolve_variable(name, token)? {
+            Type::Map(value_type) => Ok(*value_type),
+            var_type => Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: token.line,
+                    column: token.column,
+                },
+                format!("Variable '{}' is {}, but a map is expected here", name, a_type_name(&var_type)),
+                "Declare the variable with 'map' to use it as a map".to_string(),
+            )),
+        }
+    }
+
+    /// Parses a map lookup, 'map[key]', if the current tokens start one.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected` - The type the value must have
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * The MapValue, or None if the current tokens don't look up a map value
+    /// * A formatted error message if parsing fails or the value type doesn't match
+    fn map_value(&mut self, expected: &Type) -> Result<Option<MapValue>, String> {
+        let Some(value_type) = self.map_value_type() else {
+            return Ok(None);
+        };
+        let token = self.advance().clone();
+        let name = match &token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+        if value_type != *expected {
+            return Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: token.line,
+                    column: token.column,
+                },
+                format!(
+                    "Map '{}' holds '{}' values, but {} is expected here",
+                    name,
+                    type_name(&value_type),
+                    a_type_name(expected)
+                ),
+                conversion_hint(&value_type, expected),
+            ));
+        }
+
+        self.advance(); // Consume the '['
+        let key = self.expression()?;
+        self.consume(TokenType::CloseBracket, "Expected ']' after the key")?;
+        Ok(Some(MapValue {
+            map: name,
+            key: Box::new(key),
+            line: token.line,
+        }))
+    }
+
+    /// Returns the value type of the map looked up by the current tokens, if
+    /// they start a map lookup ('map[key]').
+    fn map_value_type(&self) -> Option<Type> {
+        let token_at = |offset: usize| self.tokens.get(self.current + offset).map(|token| &token.token_type);
+        match (token_at(0), token_at(1)) {
+            (Some(TokenType::Identifier(name)), Some(TokenType::OpenBracket)) => match self.variable_type(name) {
+                Some(Type::Map(value_type)) => Some(*value_type),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Checks whether the current token starts a map operand.
+    ///
+    /// # Returns
+    ///
+    /// `true` for map variables that aren't looked up, map fields and calls to
+    /// functions returning a map, `false` otherwise
+    fn is_map_operand(&self) -> bool {
+        if self.is_field_access() {
+            return matches!(self.field_type(), Some(Type::Map(_)));
+        }
+        match &self.peek().token_type {
+            TokenType::Identifier(name) => {
+                matches!(self.variable_type(name), Some(Type::Map(_)))
+                    && !matches!(
+                        self.tokens.get(self.current + 1).map(|token| &token.token_type),
+                        Some(TokenType::OpenBracket)
+                    )
+            }
+            TokenType::CallFn => match self.tokens.get(self.current + 1).map(|token| &token.token_type) {
+                Some(TokenType::Identifier(name)) => self
+                    .functions
+                    .get(name)
+                    .is_some_and(|signature| matches!(signature.return_type, Some(Type::Map(_)))),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Parses an expression producing a struct: a struct literal, a struct
+    /// variable or field, or a call to a function returning a struct.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected` - The name of the required struct, or None to accept any struct
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * The name of the struct and the StructExpression
+    /// * A formatted error message if parsing fails or the struct doesn't match
+    fn struct_expression(&mut self, expected: Option<&str>) -> Result<(String, StructExpression), String> {
+        let token = self.peek().clone();
+        let error = |parser: &Self, message: String, hint: &str| {
+            format_error(
+                &parser.source_path,
+                &parser.source,
+                ErrorPosition {
+                    line: token.line,
+                    column: token.column,
+                },
+                message,
+                hint.to_string(),
+            )
+        };
+        let next = self.tokens.get(self.current + 1).map(|token| &token.token_type);
+
+        let (found_type, expr) = match &token.token_type {
+            TokenType::Identifier(name) if self.structs.contains_key(name) && next == Some(&TokenType::OpenBrace) => {
+                (Type::Struct(name.clone()), self.struct_literal()?)
+            }
+            TokenType::Identifier(_) if self.is_field_access() => {
+                let (access, field_type) = self.field_path()?;
+                (field_type, StructExpression::Field(access))
+            }
+            TokenType::Identifier(name) => {
+                self.advance();
+                (self.resolve_variable(name, &token)?, StructExpression::Variable(name.clone()))
+            }
+            TokenType::CallFn => {
+                self.advance();
+                let (name, args, return_type) = self.function_call()?;
+                let Some(return_type) = return_type else {
+                    return Err(error(
+                        self,
+                        format!("Function '{}' does not return a value, but a struct is expected here", name),
+                        "Call a function whose return type matches how its result is used",
+                    ));
+                };
+                (return_type, StructExpression::FunctionCall(name, args))
+            }
+            _ => {
+                return Err(error(
+                    self,
+                    "Expected struct expression".to_string(),
+                    "Valid struct expressions are struct literals such as 'Point { x: 1, y: 2 }', struct variables and fields, and function calls",
+                ));
+            }
+        };
+
+        let Type::Struct(found) = found_type else {
+            return Err(error(
+                self,
+                format!("Expected a struct, found {}", a_type_name(&found_type)),
+                "Use a value of the expected struct type",
+            ));
+        };
+        if let Some(expected) = expected
+            && found != expected
+        {
+            return Err(error(
+                self,
+                format!("Expected a '{}' value, found a '{}' value", expected, found),
+                "Use a value of the expected struct type",
+            ));
+        }
+        Ok((found, expr))
+    }
+
+    /// Parses a struct literal: the struct name followed by '{', a 'field: value'
+    /// pair for every field of the struct in any order, and '}'. Fields may be
+    /// separated by commas or newlines.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A StructExpression::Literal with the fields in declaration order
+    /// * A formatted error message if parsing fails or a field is unknown, repeated or missing
+    fn struct_literal(&mut self) -> Result<StructExpression, String> {
+        let name = match &self.advance().token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+        let declared = self.structs[&name].clone();
+        self.consume(TokenType::OpenBrace, "Expected '{' after struct name")?;
+
+        let mut values: Vec<(String, TypedExpression)> = Vec::new();
+        let close = loop {
+            while self.match_token(TokenType::Newline) || self.match_token(TokenType::Comma) {}
+            if self.check(&TokenType::CloseBrace) {
+                break self.advance().clone();
+            }
+
+            let field_token = self.consume(TokenType::Identifier("".to_string()), "Expected field name or '}'")?.clone();
+            let field = match &field_token.token_type {
+                TokenType::Identifier(field) => field.clone(),
+                _ => unreachable!(),
+            };
+            let field_error = |parser: &Self, message: String, hint: String| {
+                format_error(
+                    &parser.source_path,
+                    &parser.source,
+                    ErrorPosition {
+                        line: field_token.line,
+                        column: field_token.column,
+                    },
+                    message,
+                    hint,
+                )
+            };
+            let Some((_, field_type)) = declared.iter().find(|(declared_field, _)| *declared_field == field) else {
+                let names: Vec<&str> = declared.iter().map(|(field, _)| field.as_str()).collect();
+                return Err(field_error(
+                    self,
+                    format!("Struct '{}' has no field '{}'", name, field),
+                    format!("The fields of '{}' are: {}", name, names.join(", ")),
+                ));
+            };
+            if values.iter().any(|(existing, _)| *existing == field) {
+                return Err(field_error(
+                    self,
+                    format!("Field '{}' is given more than once", field),
+                    "Give each field a single value".to_string(),
+                ));
+            }
+            self.consume(TokenType::Colon, "Expected ':' after field name")?;
+            let value = self.typed_expression(field_type, &format!("field '{}'", field))?;
+            values.push((field, value));
+        };
+
+        // Every field needs a value; put them in declaration order
+        let mut fields = Vec::new();
+        for (field, _) in &declared {
+            match values.iter().position(|(given, _)| given == field) {
+                Some(index) => fields.push(values.swap_remove(index)),
+                None => {
+                    return Err(format_error(
+                        &self.source_path,
+                        &self.source,
+                        ErrorPosition {
+                            line: close.line,
+                            column: close.column,
+                        },
+                        format!("Missing field '{}' in '{}' literal", field, name),
+                        "Give a value for every field of the struct".to_string(),
+                    ));
+                }
+            }
+        }
+        Ok(StructExpression::Literal(name, fields))
+    }
+
+    /// Checks whether the current token starts a struct operand.
+    ///
+    /// # Returns
+    ///
+    /// `true` for struct literals, struct variables and fields, and calls to
+    /// functions returning a struct, `false` otherwise
+    fn is_struct_operand(&self) -> bool {
+        if self.is_field_access() {
+            return matches!(self.field_type(), Some(Type::Struct(_)));
+        }
+        let next = self.tokens.get(self.current + 1).map(|token| &token.token_type);
+        match &self.peek().token_type {
+            TokenType::Identifier(name) if next == Some(&TokenType::OpenBrace) => self.structs.contains_key(name),
+            TokenType::Identifier(name) => matches!(self.variable_type(name), Some(Type::Struct(_))),
+            TokenType::CallFn => match next {
+                Some(TokenType::Identifier(name)) => self
+                    .functions
+                    .get(name)
+                    .is_some_and(|signature| matches!(signature.return_type, Some(Type::Struct(_)))),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Checks whether the current tokens start a field access: a name followed
+    /// by '.', where the name isn't that of an enum (which starts a variant).
+    fn is_field_access(&self) -> bool {
+        matches!(self.tokens.get(self.current + 1).map(|token| &token.token_type), Some(TokenType::Dot))
+            && match &self.peek().token_type {
+                TokenType::Identifier(name) => !self.is_enum_name(name),
+                _ => false,
+            }
+    }
+
+    /// Parses a field access: a struct variable followed by one or more
+    /// '.field' names.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * The FieldAccess and the type of the accessed field
+    /// * A formatted error message if the variable isn't a struct or a field doesn't exist
+    fn field_path(&mut self) -> Result<(FieldAccess, Type), String> {
+        let variable_token = self.advance().clone();
+        let variable = match &variable_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+        let mut current_type = self.resolve_variable(&variable, &variable_token)?;
+        let mut access = FieldAccess {
+            variable,
+            fields: Vec::new(),
+        };
+
+        while self.match_token(TokenType::Dot) {
+            let field_token = self.consume(TokenType::Identifier("".to_string()), "Expected field name after '.'")?.clone();
+            let field = match &field_token.token_type {
+                TokenType::Identifier(field) => field.clone(),
+                _ => unreachable!(),
+            };
+            let error_position = ErrorPosition {
+                line: field_token.line,
+                column: field_token.column,
+            };
+            let Type::Struct(struct_name) = &current_type else {
+                let owner = if access.fields.is_empty() {
+                    format!("Variable '{}'", access.variable)
+                } else {
+                    format!("Field '{}'", field_name(&access))
+                };
+                return Err(format_error(
+                    &self.source_path,
+                    &self.source,
+                    error_position,
+                    format!("{} is {}, which has no fields", owner, a_type_name(&current_type)),
+                    "Only structs have fields".to_string(),
+                ));
+            };
+            let fields = &self.structs[struct_name];
+            let Some((_, field_type)) = fields.iter().find(|(declared, _)| *declared == field) else {
+                let names: Vec<&str> = fields.iter().map(|(field, _)| field.as_str()).collect();
+                return Err(format_error(
+                    &self.source_path,
+                    &self.source,
+                    error_position,
+                    format!("Struct '{}' has no field '{}'", struct_name, field),
+                    format!("The fields of '{}' are: {}", struct_name, names.join(", ")),
+                ));
+            };
+            current_type = field_type.clone();
+            access.fields.push(field);
+        }
+        Ok((access, current_type))
+    }
+
+    /// Returns the type of the field accessed by the current tokens, if they
+    /// start a valid field access.
+    fn field_type(&self) -> Option<Type> {
+        if !self.is_field_access() {
+            return None;
+        }
+        let mut current_type = match &self.peek().token_type {
+            TokenType::Identifier(name) => self.variable_type(name)?,
+            _ => return None,
+        };
+        let mut i = self.current + 1;
+        while self.tokens.get(i).is_some_and(|token| token.token_type == TokenType::Dot) {
+            let Some(TokenType::Identifier(field)) = self.tokens.get(i + 1).map(|token| &token.token_type) else {
+                return None;
+            };
+            let Type::Struct(struct_name) = &current_type else {
+                return None;
+            };
+            current_type = self.structs.get(struct_name)?.iter().find(|(declared, _)| declared == field)?.1.clone();
+            i += 2;
+        }
+        Some(current_type)
+    }
+
+    /// Parses a field of a struct variable, 'point.x', if the current tokens start one.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected` - The type the field must have
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * The FieldAccess, or None if the current tokens don't access a field
+    /// * A formatted error message if parsing fails or the field type doesn't match
+    fn field_access(&mut self, expected: &Type) -> Result<Option<FieldAccess>, String> {
+        if !self.is_field_access() {
+            return Ok(None);
+        }
+        let token = self.peek().clone();
+        let (access, field_type) = self.field_path()?;
+        if field_type != *expected {
+            return Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: token.line,
+                    column: token.column,
+                },
+                format!(
+                    "Field '{}' is {}, but {} is expected here",
+                    field_name(&access),
+                    a_type_name(&field_type),
+                    a_type_name(expected)
+                ),
+                conversion_hint(&field_type, expected),
+            ));
+        }
+        Ok(Some(access))
+    }
+
+    /// Parses an enum definition, whose 'enum' keyword has already been consumed:
+    /// 'enum Shape { Circle(num), Rect(num, num), Empty }'. Variants are separated
+    /// by commas or newlines, and may list the types of the values they carry.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * An EnumDefinition Statement object
+    /// * A formatted error message if parsing fails
+    fn enum_definition(&mut self) -> Result<Statement, String> {
+        let keyword = self.previous().clone();
+        if self.in_function || self.block_depth > 0 {
+            return Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: keyword.line,
+                    column: keyword.column,
+                },
+                "Enum definitions are only allowed at the top level".to_string(),
+                "Move the enum definition to the top level of the program".to_string(),
+            ));
+        }
+
+        let name_token = self.consume(TokenType::Identifier("".to_string()), "Expected enum name after 'enum'")?.clone();
+        let name = match &name_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+        if name == "str" || self.defined_types.contains(&name) {
+            return Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: name_token.line,
+                    column: name_token.column,
+                },
+                format!("Type '{}' is already defined", name),
+                "Give the enum a name that isn't used by another type".to_string(),
+            ));
+        }
+        self.consume(TokenType::OpenBrace, "Expected '{' after enum name")?;
+
+        let mut variants: Vec<(String, Vec<Type>)> = Vec::new();
+        loop {
+            while self.match_token(TokenType::Newline) || self.match_token(TokenType::Comma) {}
+            if self.check(&TokenType::CloseBrace) {
+                break;
+            }
+
+            let variant_token = self.consume(TokenType::Identifier("".to_string()), "Expected variant name or '}'")?.clone();
+            let variant = match &variant_token.token_type {
+                TokenType::Identifier(variant) => variant.clone(),
+                _ => unreachable!(),
+            };
+            let variant_error = |parser: &Self, message: String, hint: &str| {
+                format_error(
+                    &parser.source_path,
+                    &parser.source,
+                    ErrorPosition {
+                        line: variant_token.line,
+                        column: variant_token.column,
+                    },
+                    message,
+                    hint.to_string(),
+                )
+            };
+            if variants.iter().any(|(existing, _)| *existing == variant) {
+                return Err(variant_error(
+                    self,
+                    format!("Duplicate variant '{}'", variant),
+                    "Each variant of an enum must have a unique name",
+                ));
+            }
+
+            let mut value_types = Vec::new();
+            if self.match_token(TokenType::OpenParen) {
+                loop {
+                    let value_type = self.parse_type()?;
+                    if let Type::Struct(type_name) | Type::Enum(type_name) = &value_type
+                        && !self.defined_types.contains(type_name)
+                    {
+                        let message = if *type_name == name {
+                            format!("Variant '{}' can't hold a value of its own enum '{}'", variant, name)
+                        } else {
+                            format!("Variant '{}' uses type '{}' before its definition", variant, type_name)
+                        };
+                        return Err(variant_error(
+                            self,
+                            message,
+                            "Define a struct or enum before the enums whose variants hold it",
+                        ));
+                    }
+                    value_types.push(value_type);
+                    if !self.match_token(TokenType::Comma) {
+                        break;
+                    }
+                }
+                self.consume(TokenType::CloseParen, "Expected ')' after the variant's value types")?;
+            }
+            variants.push((variant, value_types));
+        }
+        let close = self.advance().clone();
+
+        if variants.is_empty() {
+            return Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: close.line,
+                    column: close.column,
+                },
+                format!("Enum '{}' has no variants", name),
+                "List the variants of the enum between the braces".to_string(),
+            ));
+        }
+
+        self.defined_types.insert(name.clone());
+        Ok(Statement::EnumDefinition(name, variants))
+    }
+
+    /// Parses an enum variable declaration statement: 'Color c = ...', where
+    /// the name of the enum takes the place of the type keyword.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * An EnumDeclaration Statement object
+    /// * A formatted error message if parsing fails
+    fn enum_variable_statement(&mut self) -> Result<Statement, String> {
+        // Store the current line number for error reporting
+        let line_number = self.peek().line;
+
+        let enum_name = match &self.advance().token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+        let name_token = self.advance().clone();
+        let name = match &name_token.token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+
+        self.consume(TokenType::Equals, "Expected '=' after variable name")?;
+
+        let (_, initializer) = self.enum_expression(Some(&enum_name))?;
+
+        // Declare after the initializer, which still sees any variable being shadowed
+        self.declare_variable(&name_token, Type::Enum(enum_name.clone()))?;
+
+        Ok(Statement::EnumDeclaration(name, enum_name, initializer, line_number))
+    }
+
+    /// Parses an expression that produces an enum value: a variant such as
+    /// 'Color.Red' or 'Shape.Circle(2.5)', an enum variable or field, or a function call.
+    ///
+    /// # Arguments
+    ///
+    /// * `expected` - The name of the required enum, or None to accept any enum
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * The name of the enum and the EnumExpression
+    /// * A formatted error message if parsing fails or the enum doesn't match
+    fn enum_expression(&mut self, expected: Option<&str>) -> Result<(String, EnumExpression), String> {
+        let token = self.peek().clone();
+        let error = |parser: &Self, message: String, hint: &str| {
+            format_error(
+                &parser.source_path,
+                &parser.source,
+                ErrorPosition {
+                    line: token.line,
+                    column: token.column,
+                },
+                message,
+                hint.to_string(),
+            )
+        };
+
+        let (found_type, expr) = match &token.token_type {
+            TokenType::Identifier(name) if self.is_enum_name(name) => (Type::Enum(name.clone()), self.enum_variant()?),
+            TokenType::Identifier(_) if self.is_field_access() => {
+                let (access, field_type) = self.field_path()?;
+                (field_type, EnumExpression::Field(access))
+            }
+            TokenType::Identifier(name) => {
+                self.advance();
+                (self.resolve_variable(name, &token)?, EnumExpression::Variable(name.clone()))
+            }
+            TokenType::CallFn => {
+                self.advance();
+                let (name, args, return_type) = self.function_call()?;
+                let Some(return_type) = return_type else {
+                    return Err(error(
+                        self,
+                        format!("Function '{}' does not return a value, but an enum is expected here", name),
+                        "Call a function whose return type matches how its result is used",
+                    ));
+                };
+                (return_type, EnumExpression::FunctionCall(name, args))
+            }
+            _ => {
+                return Err(error(
+                    self,
+                    "Expected enum expression".to_string(),
+                    "Valid enum expressions are variants such as 'Color.Red', enum variables and fields, and function calls",
+                ));
+            }
+        };
+
+        let Type::Enum(found) = found_type else {
+            return Err(error(
+                self,
+                format!("Expected an enum, found {}", a_type_name(&found_type)),
+                "Use a value of the expected enum type",
+            ));
+        };
+        if let Some(expected) = expected
+            && found != expected
+        {
+            return Err(error(
+                self,
+                format!("Expected a '{}' value, found a '{}' value", expected, found),
+                "Use a value of the expected enum type",
+            ));
+        }
+        Ok((found, expr))
+    }
+
+    /// Parses an enum variant: the enum name, '.', the variant name and, if the
+    /// variant carries values, one value for each of them in parentheses.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * An EnumExpression::Variant
+    /// * A formatted error message if the variant doesn't exist or its values don't match
+    fn enum_variant(&mut self) -> Result<EnumExpression, String> {
+        let name = match &self.advance().token_type {
+            TokenType::Identifier(name) => name.clone(),
+            _ => unreachable!(),
+        };
+        self.consume(TokenType::Dot, "Expected '.' and a variant name after the enum name")?;
+        let variant_token = self.consume(TokenType::Identifier("".to_string()), "Expected variant name after '.'")?.clone();
+        let variant = match &variant_token.token_type {
+            TokenType::Identifier(variant) => variant.clone(),
+            _ => unreachable!(),
+        };
+        let variant_error = |parser: &Self, message: String, hint: String| {
+            format_error(
+                &parser.source_path,
+                &parser.source,
+                ErrorPosition {
+                    line: variant_token.line,
+                    column: variant_token.column,
+                },
+                message,
+                hint,
+            )
+        };
+
+        let variants = &self.enums[&name];
+        let Some((_, value_types)) = variants.iter().find(|(declared, _)| *declared == variant) else {
+            let names: Vec<&str> = variants.iter().map(|(variant, _)| variant.as_str()).collect();
+            return Err(variant_error(
+                self,
+                format!("Enum '{}' has no variant '{}'", name, variant),
+                format!("The variants of '{}' are: {}", name, names.join(", ")),
+            ));
+        };
+        let value_types = value_types.clone();
+
+        let mut values = Vec::new();
+        if self.match_token(TokenType::OpenParen) && !self.match_token(TokenType::CloseParen) {
+            loop {
+                let Some(value_type) = value_types.get(values.len()) else {
+                    return Err(variant_error(
+                        self,
+                        format!("Variant '{}' of '{}' carries {} value(s)", variant, name, value_types.len()),
+                        "Remove the extra values".to_string(),
+                    ));
+                };
+                let context = format!("value {} of '{}.{}'", values.len() + 1, name, variant);
+                values.push(self.typed_expression(value_type, &context)?);
+                if !self.match_token(TokenType::Comma) {
+                    break;
+                }
+            }
+            self.consume(TokenType::CloseParen, "Expected ')' after the variant's values")?;
+        }
+        if values.len() != value_types.len() {
+            return Err(variant_error(
+                self,
+                format!(
+                    "Variant '{}' of '{}' carries {} value(s) but {} were given",
+                    variant,
+                    name,
+                    value_types.len(),
+                    values.len()
+                ),
+                format!("Give one value for each value the variant carries, e.g. '{}.{}'", name, variant_pattern(&variant, value_types.len())),
+            ));
+        }
+        Ok(EnumExpression::Variant(name, variant, values))
+    }
+
+    /// Checks whether the current token starts an enum operand.
+    ///
+    /// # Returns
+    ///
+    /// `true` for enum variants, enum variables and fields, and calls to
+    /// functions returning an enum, `false` otherwise
+    fn is_enum_operand(&self) -> bool {
+        if self.is_field_access() {
+            return matches!(self.field_type(), Some(Type::Enum(_)));
+        }
+        match &self.peek().token_type {
+            TokenType::Identifier(name) => {
+                self.is_enum_name(name) || matches!(self.variable_type(name), Some(Type::Enum(_)))
+            }
+            TokenType::CallFn => match self.tokens.get(self.current + 1).map(|token| &token.token_type) {
+                Some(TokenType::Identifier(name)) => self
+                    .functions
+                    .get(name)
+                    .is_some_and(|signature| matches!(signature.return_type, Some(Type::Enum(_)))),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Checks whether a name refers to an enum rather than to a variable.
+    fn is_enum_name(&self, name: &str) -> bool {
+        self.enums.contains_key(name) && self.variable_type(name).is_none()
+    }
+
+    /// Parses a numerical expression using recursive descent parsing.
+    /// This handles precedence and associativity of mathematical operators.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A NumExpression object
+    /// * A formatted error message if parsing fails
+    fn num_expression(&mut self) -> Result<NumExpression, String> {
+        // Start with the lowest precedence: addition and subtraction
+        self.num_addition()
+    }
+
+    /// Parses an addition or subtraction expression.
+    /// Addition and subtraction have the same precedence level.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A NumExpression object
+    /// * A formatted error message if parsing fails
+    fn num_addition(&mut self) -> Result<NumExpression, String> {
+        // Start with the next higher precedence
+        let mut expr = self.num_multiplication()?;
+
+        // Keep consuming addition and subtraction operators
+        while self.match_token(TokenType::Plus) || self.match_token(TokenType::Minus) {
+            let operator = match self.previous().token_type {
+                TokenType::Plus => BinaryOperator::Add,
+                TokenType::Minus => BinaryOperator::Subtract,
+                _ => unreachable!(),
+            };
+            
+            // Parse the right operand with higher precedence
+            let right = self.num_multiplication()?;
+            
+            // Build the binary operation expression
+            expr = NumExpression::BinaryOp(Box::new(expr), operator, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    /// Parses a multiplication, division or modulo expression.
+    /// Multiplication, division and modulo have the same precedence level,
+    /// which is higher than addition and subtraction.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A NumExpression object
+    /// * A formatted error message if parsing fails
+    fn num_multiplication(&mut self) -> Result<NumExpression, String> {
+        // Start with the next higher precedence: unary expressions
+        let mut expr = self.num_unary()?;
+
+        // Keep consuming multiplication, division and modulo operators
+        while self.match_token(TokenType::Star)
+            || self.match_token(TokenType::Slash)
+            || self.match_token(TokenType::Percent)
+        {
+            let operator = match self.previous().token_type {
+                TokenType::Star => BinaryOperator::Multiply,
+                TokenType::Slash => BinaryOperator::Divide,
+                TokenType::Percent => BinaryOperator::Modulo,
+                _ => unreachable!(),
+            };
+            
+            // Parse the right operand
+            let right = self.num_unary()?;
+            
+            // Build the binary operation expression
+            expr = NumExpression::BinaryOp(Box::new(expr), operator, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    /// Parses a unary plus or minus expression, such as '-x' or '-(a + b)'.
+    /// Unary operators bind tighter than multiplication but looser than
+    /// exponentiation, so '-2 ** 2' is '-(2 ** 2)'.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A NumExpression object
+    /// * A formatted error message if parsing fails
+    fn num_unary(&mut self) -> Result<NumExpression, String> {
+        if self.match_token(TokenType::Minus) {
+            let operand = self.num_unary()?;
+            Ok(NumExpression::Negate(Box::new(operand)))
+        } else if self.match_token(TokenType::Plus) {
+            // Unary plus leaves the value unchanged
+            self.num_unary()
+        } else {
+            self.num_power()
+        }
+    }
+
+    /// Parses an exponentiation expression.
+    /// Exponentiation has the highest precedence of the binary operators and is
+    /// right-associative, so '2 ** 3 ** 2' is '2 ** (3 ** 2)'.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A NumExpression object
+    /// * A formatted error message if parsing fails
+    fn num_power(&mut self) -> Result<NumExpression, String> {
+        // Start with the highest precedence: primary expressions
+        let mut expr = self.num_primary()?;
+
+        if self.match_token(TokenType::StarStar) {
+            // The exponent may itself be negated or raised to a power
+            let right = self.num_unary()?;
+            expr = NumExpression::BinaryOp(Box::new(expr), BinaryOperator::Power, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    /// Checks that a call to a built-in function other than a math function
+    /// returns the expected type. The file functions that return nothing are
+    /// always rejected, since they are statements.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the called function
+    /// * `expected` - The type required by the surrounding expression
+    /// * `token` - The token of the function name (for error positions)
+    ///
+    /// # Returns
+    ///
+    /// Ok if the function isn't such a built-in or returns the expected type,
+    /// or a formatted error message otherwise
+    fn check_builtin_type(&self, name: &str, expected: &Type, token: &Token) -> Result<(), String> {
+        if is_void_builtin(name) {
+            return Err(self.void_builtin_error(name, Some(expected), token));
+        }
+        match builtin_return_type(name) {
+            Some(return_type) if return_type != *expected => Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: token.line,
+                    column: token.column,
+                },
+                format!(
+                    "Function '{}' returns '{}', but {} is expected here",
+                    name,
+                    type_name(&return_type),
+                    a_type_name(expected)
+                ),
+                conversion_hint(&return_type, expected),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Builds the error for a call to a built-in without a return value, such as
+    /// 'write_file', where a value is expected.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the built-in
+    /// * `expected` - The type expected at the call, or None if any value fits
+    /// * `token` - The token of the built-in's name (for the error position)
+    fn void_builtin_error(&self, name: &str, expected: Option<&Type>, token: &Token) -> String {
+        let expected = expected.map_or_else(|| "a value".to_string(), a_type_name);
+        format_error(
+            &self.source_path,
+            &self.source,
+            ErrorPosition {
+                line: token.line,
+                column: token.column,
+            },
+            format!("Function '{}' does not return a value, but {} is expected here", name, expected),
+            format!("Call '{}' as a statement on its own line", name),
+        )
+    }
+
+    /// Checks whether the current token starts a call to a built-in without a
+    /// return value.
+    fn is_void_builtin_call(&self) -> bool {
+        matches!(&self.peek().token_type, TokenType::Identifier(name) if is_void_builtin(name))
+            && self.tokens.get(self.current + 1).is_some_and(|next| next.token_type == TokenType::OpenParen)
+    }
+
+    /// Reports an error if the current token starts a call to a built-in
+    /// without a return value, where `expected` (or any value, if None) is
+    /// expected instead.
+    fn reject_void_builtin_call(&self, expected: Option<&Type>) -> Result<(), String> {
+        match &self.peek().token_type {
+            TokenType::Identifier(name) if self.is_void_builtin_call() => {
+                Err(self.void_builtin_error(name, expected, self.peek()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Parses the argument of 'tostr' and the closing parenthesis, inferring the
+    /// type of the value.
+    ///
+    /// Strings, lists, maps, structs and enums are recognized from their first
+    /// token. Other values are tried as integer, numerical and boolean
+    /// expressions in turn; if none of them fits, the error that got furthest
+    /// is reported.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A TypedExpression for the value to convert
+    /// * A formatted error message if parsing fails
+    fn tostr_argument(&mut self) -> Result<TypedExpression, String> {
+        self.reject_void_builtin_call(None)?;
+        let value = if self.is_string_operand() {
+            Some(TypedExpression::Str(self.expression()?))
+        } else if self.is_list_operand() {
+            Some(TypedExpression::List(self.list_expression(None)?.1))
+        } else if self.is_map_operand() {
+            Some(TypedExpression::Map(self.map_expression(None)?.1))
+        } else if self.is_struct_operand() {
+            let (name, expr) = self.struct_expression(None)?;
+            Some(TypedExpression::Struct(name, expr))
+        } else if self.is_enum_operand() {
+            let (name, expr) = self.enum_expression(None)?;
+            Some(TypedExpression::Enum(name, expr))
+        } else {
+            None
+        };
+        if let Some(value) = value {
+            self.consume(TokenType::CloseParen, "Expected ')' after the value to convert")?;
+            return Ok(value);
+        }
+
+        let start = self.current;
+        let mut furthest: Option<(usize, String)> = None;
+        for attempt in [Type::Int, Type::Num, Type::Bool] {
+            self.current = start;
+            let result = match attempt {
+                Type::Int => self.int_expression().map(TypedExpression::Int),
+                Type::Num => self.num_expression().map(TypedExpression::Num),
+                _ => self.condition().map(TypedExpression::Bool),
+            };
+            let result = result.and_then(|value| {
+                self.consume(TokenType::CloseParen, "Expected ')' after the value to convert")?;
+                Ok(value)
+            });
+            let error = match result {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+            if furthest.as_ref().is_none_or(|(position, _)| self.current > *position) {
+                furthest = Some((self.current, error));
+            }
+        }
+        Err(furthest.map(|(_, error)| error).unwrap_or_default())
+    }
+
+    /// Checks whether the current token is the name of a built-in function for
+    /// input, conversions, strings, files or the program's environment,
+    /// followed by '('.
+    fn is_builtin_call(&self) -> bool {
+        matches!(&self.peek().token_type, TokenType::Identifier(name) if builtin_return_type(name).is_some())
+            && self.tokens.get(self.current + 1).is_some_and(|next| next.token_type == TokenType::OpenParen)
+    }
+
+    /// Parses a call to a built-in string function: 'name(arg, ...)'.
+    ///
+    /// The function name has already been consumed; the arguments are checked
+    /// against the types of the built-in's parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the called function
+    /// * `name_token` - The token of the function name (for error positions)
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * The function and its arguments
+    /// * A formatted error message if parsing fails
+    fn string_call(&mut self, name: &str, name_token: &Token) -> Result<(StringFunction, Vec<TypedExpression>), String> {
+        let Some((function, parameters, _)) = string_function(name) else {
+            unreachable!()
+        };
+
+        let error_at_name = |parser: &Self, message: String, hint: String| {
+            format_error(
+                &parser.source_path,
+                &parser.source,
+                ErrorPosition {
+                    line: name_token.line,
+                    column: name_token.column,
+                },
+                message,
+                hint,
+            )
+        };
+
+        self.consume(TokenType::OpenParen, "Expected '(' after function name")?;
+        let mut args = Vec::new();
+        if !self.check(&TokenType::CloseParen) {
+            loop {
+                let Some(parameter) = parameters.get(args.len()) else {
+                    return Err(error_at_name(
+                        self,
+                        format!("Function '{}' expects {} argument(s)", name, parameters.len()),
+                        "Remove the extra arguments from the call".to_string(),
+                    ));
+                };
+                let context = format!("argument {} of '{}'", args.len() + 1, name);
+                args.push(self.typed_expression(parameter, &context)?);
+                if !self.match_token(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::CloseParen, "Expected ')' after arguments")?;
+
+        if args.len() != parameters.len() {
+            let parameter_types: Vec<String> = parameters.iter().map(type_name).collect();
+            return Err(error_at_name(
+                self,
+                format!("Function '{}' expects {} argument(s) but {} were given", name, parameters.len(), args.len()),
+                format!("Pass arguments of these types: {}({})", name, parameter_types.join(", ")),
+            ));
+        }
+        Ok((function, args))
+    }
+
+    /// Parses the parenthesized, optional prompt of 'input' and 'inputnum'.
+    ///
+    /// The function name has already been consumed.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * The prompt string expression, or None for '()'
+    /// * A formatted error message if parsing fails
+    fn input_prompt(&mut self) -> Result<Option<Box<Expression>>, String> {
+        self.consume(TokenType::OpenParen, "Expected '(' after function name")?;
+        if self.match_token(TokenType::CloseParen) {
+            return Ok(None);
+        }
+        let prompt = self.expression()?;
+        self.consume(TokenType::CloseParen, "Expected ')' after the prompt")?;
+        Ok(Some(Box::new(prompt)))
+    }
+
+    /// Parses a call to a built-in math function: 'name(arg, ...)'.
+    ///
+    /// The function name has already been consumed; the number of arguments is
+    /// checked against the built-in's arity.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the called function
+    /// * `name_token` - The token of the function name (for error positions)
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A NumExpression::MathCall
+    /// * A formatted error message if parsing fails
+    fn math_call(&mut self, name: &str, name_token: &Token) -> Result<NumExpression, String> {
+        let error_at_name = |parser: &Self, message: String, hint: String| {
+            format_error(
+                &parser.source_path,
+                &parser.source,
+                ErrorPosition {
+                    line: name_token.line,
+                    column: name_token.column,
+                },
+                message,
+                hint,
+            )
+        };
+
+        let Some((function, arity)) = math_function(name) else {
+            let hint = if self.functions.contains_key(name) {
+                format!("Use 'callfn {}(...)' to call a user-defined function", name)
+            } else {
+                "Built-in math functions are sqrt, abs, floor, ceil, round, sin, cos, tan, log, exp, min and max".to_string()
+            };
+            return Err(error_at_name(self, format!("Unknown function '{}'", name), hint));
+        };
+
+        self.consume(TokenType::OpenParen, "Expected '(' after function name")?;
+        let mut args = Vec::new();
+        if !self.check(&TokenType::CloseParen) {
+            loop {
+                args.push(self.num_expression()?);
+                if !self.match_token(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::CloseParen, "Expected ')' after arguments")?;
+
+        if args.len() != arity {
+            return Err(error_at_name(
+                self,
+                format!("Function '{}' expects {} argument(s) but {} were given", name, arity, args.len()),
+                "Pass one numerical argument for each parameter of the function".to_string(),
+            ));
+        }
+
+        Ok(NumExpression::MathCall(function, args))
+    }
+
+    /// Parses a primary numerical expression (literals, variables, and parenthesized expressions).
+    /// This is the highest precedence level in the expression grammar.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * A NumExpression object
+    /// * A formatted error message if parsing fails
+    fn num_primary(&mut self) -> Result<NumExpression, String> {
+        if let Some(element) = self.list_element(&Type::Num)? {
+            return Ok(NumExpression::ListElement(element));
+        }
+        if let Some(value) = self.map_value(&Type::Num)? {
+            return Ok(NumExpression::MapValue(value));
+        }
+        if let Some(access) = self.field_access(&Type::Num)? {
+            return Ok(NumExpression::Field(access));
+        }
+
+        // Check each possible primary expression type
+        if self.match_token(TokenType::NumberLiteral(0.0)) {
+            // Handle numeric literals
+            if let TokenType::NumberLiteral(value) = self.previous().token_type {
+                Ok(NumExpression::NumberLiteral(value))
+            } else {
+                unreachable!()
+            }
+        } else if self.match_token(TokenType::IntegerLiteral(0)) {
+            // Integer literals can be used as numbers directly
+            if let TokenType::IntegerLiteral(value) = self.previous().token_type {
+                Ok(NumExpression::NumberLiteral(value as f64))
+            } else {
+                unreachable!()
+            }
+        } else if self.check(&TokenType::Num)
+            && matches!(self.tokens.get(self.current + 1).map(|token| &token.token_type), Some(TokenType::OpenParen))
+        {
+            // Explicit conversion of an integer: 'num(i)'
+            self.advance();
+            self.advance();
+            let inner = self.int_expression()?;
+            self.consume(TokenType::CloseParen, "Expected ')' after the value to convert")?;
+            Ok(NumExpression::FromInt(Box::new(inner)))
+        } else if self.match_token(TokenType::Identifier("".to_string())) {
+            // Handle variable references
+            let token = self.previous().clone();
+            if let TokenType::Identifier(name) = &token.token_type {
+                if name == "len" && self.check(&TokenType::OpenParen) {
+                    return Err(format_error(
+                        &self.source_path,
+                        &self.source,
+                        ErrorPosition {
+                            line: token.line,
+                            column: token.column,
+                        },
+                        "Function 'len' returns 'int', but a 'num' is expected here".to_string(),
+                        conversion_hint(&Type::Int, &Type::Num),
+                    ));
+                }
+                if self.check(&TokenType::OpenParen) {
+                    match name.as_str() {
+                        // A number read from standard input: 'inputnum("prompt")'
+                        "inputnum" => return Ok(NumExpression::InputNum(self.input_prompt()?, token.line)),
+                        "tonum" => {
+                            // A string parsed as a number: 'tonum(text)'
+                            self.advance();
+                            let text = self.expression()?;
+                            self.consume(TokenType::CloseParen, "Expected ')' after the string")?;
+                            return Ok(NumExpression::ToNum(Box::new(text), token.line));
+                        }
+                        _ => self.check_builtin_type(name, &Type::Num, &token)?,
+                    }
+                    return self.math_call(name, &token);
+                }
+                if self.variable_type(name).is_none()
+                    && let Some(value) = math_constant(name)
+                {
+                    // Built-in constants can be shadowed by variables of the same name
+                    return Ok(NumExpression::NumberLiteral(value));
+                }
+                let var_type = self.resolve_variable(name, &token)?;
+                if var_type == Type::Int && self.is_constant(name) {
+                    // Integer constants can be used as numbers, like the literals they stand for
+                    return Ok(NumExpression::FromInt(Box::new(IntExpression::Variable(name.clone()))));
+                }
+                if var_type != Type::Num {
+                    return Err(self.variable_type_error(name, &var_type, &Type::Num, &token));
+                }
+                Ok(NumExpression::Variable(name.clone()))
+            } else {
+                unreachable!()
+            }
+        } else if self.match_token(TokenType::OpenParen) {
+            // Handle parenthesized expressions
+            let expr = self.num_expression()?;
+            self.consume(TokenType::CloseParen, "Expected ')' after expression")?;
+            Ok(NumExpression::Grouping(Box::new(expr)))
+        } else if self.check(&TokenType::CallFn) {
+            // Handle calls to functions returning a number
+            let call_token = self.advance().clone();
+            let (name, args, return_type) = self.function_call()?;
+            if return_type != Some(Type::Num) {
+                return Err(self.call_type_error(&name, return_type.as_ref(), &Type::Num, &call_token));
+            }
+            Ok(NumExpression::FunctionCall(name, args))
+        } else {
+            // Error: unexpected token
+            let token = self.peek().clone();
+            let hint = if self.is_string_operand() {
+                conversion_hint(&Type::Str, &Type::Num)
+            } else {
+                "Valid expressions are numbers, variables, function calls, math built-ins, or parenthesized expressions".to_string()
+            };
+            Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: token.line,
+                    column: token.column,
+                },
+                "Expected numerical expression".to_string(),
+                hint,
+            ))
+        }
+    }
+
+    /// Parses an integer expression.
+    /// Integer expressions use the same operators and precedence as numerical
+    /// expressions, but only accept integer operands.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * An IntExpression object
+    /// * A formatted error message if parsing fails
+    fn int_expression(&mut self) -> Result<IntExpression, String> {
+        // Start with the lowest precedence: addition and subtraction
+        self.int_addition()
+    }
+
+    /// Parses an integer addition or subtraction expression.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * An IntExpression object
+    /// * A formatted error message if parsing fails
+    fn int_addition(&mut self) -> Result<IntExpression, String> {
+        let mut expr = self.int_multiplication()?;
+
+        while self.match_token(TokenType::Plus) || self.match_token(TokenType::Minus) {
+            let operator_token = self.previous().clone();
+            let operator = match operator_token.token_type {
+                TokenType::Plus => BinaryOperator::Add,
+                TokenType::Minus => BinaryOperator::Subtract,
+                _ => unreachable!(),
+            };
+            let right = self.int_multiplication()?;
+            expr = IntExpression::BinaryOp(Box::new(expr), operator, Box::new(right), operator_token.line);
+        }
+
+        Ok(expr)
+    }
+
+    /// Parses an integer multiplication, division or modulo expression.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * An IntExpression object
+    /// * A formatted error message if parsing fails
+    fn int_multiplication(&mut self) -> Result<IntExpression, String> {
+        let mut expr = self.int_unary()?;
+
+        while self.match_token(TokenType::Star)
+            || self.match_token(TokenType::Slash)
+            || self.match_token(TokenType::Percent)
+        {
+            let operator_token = self.previous().clone();
+            let operator = match operator_token.token_type {
+                TokenType::Star => BinaryOperator::Multiply,
+                TokenType::Slash => BinaryOperator::Divide,
+                TokenType::Percent => BinaryOperator::Modulo,
+                _ => unreachable!(),
+            };
+            let right = self.int_unary()?;
+            expr = IntExpression::BinaryOp(Box::new(expr), operator, Box::new(right), operator_token.line);
+        }
+
+        Ok(expr)
+    }
+
+    /// Parses an integer unary plus or minus expression.
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * An IntExpression object
+    /// * A formatted error message if parsing fails
+    fn int_unary(&mut self) -> Result<IntExpression, String> {
+        if self.match_token(TokenType::Minus) {
+            let line_number = self.previous().line;
+            let operand_token = self.peek().clone();
+            if self.is_int_min_magnitude(&operand_token) {
+                // The smallest 'int' has no positive counterpart, so its literal
+                // is only valid when negated directly
+                self.advance();
+                return Ok(IntExpression::IntegerLiteral(i64::MIN));
+            }
+            let operand = self.int_unary()?;
+            Ok(IntExpression::Negate(Box::new(operand), line_number))
+        } else if self.match_token(TokenType::Plus) {
+            self.int_unary()
+        } else {
+            self.int_power()
+        }
+    }
+
+    /// Checks whether a token is the literal 9223372036854775808, the magnitude
+    /// of the smallest 'int'. It is too large for the lexer to read as an
+    /// integer, so its source text is checked rather than its rounded value.
+    fn is_int_min_magnitude(&self, token: &Token) -> bool {
+        if !matches!(token.token_type, TokenType::NumberLiteral(_)) {
+            return false;
+        }
+        let line = self.source.lines().nth(token.line - 1).unwrap_or("");
+        let text: String = line
+            .chars()
+            .skip(token.column - 1)
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '.')
+            .collect();
+        text == "9223372036854775808"
+    }
+
+    /// Parses an integer exponentiation expression (right-associative).
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * An IntExpression object
+    /// * A formatted error message if parsing fails
+    fn int_power(&mut self) -> Result<IntExpression, String> {
+        let mut expr = self.int_primary()?;
+
+        if self.match_token(TokenType::StarStar) {
+            let line_number = self.previous().line;
+            let right = self.int_unary()?;
+            expr = IntExpression::BinaryOp(Box::new(expr), BinaryOperator::Power, Box::new(right), line_number);
+        }
+
+        Ok(expr)
+    }
+
+    /// Parses a primary integer expression (literals, variables, conversions,
+    /// calls and parenthesized expressions).
+    ///
+    /// # Returns
+    ///
+    /// A Result containing either:
+    /// * An IntExpression object
+    /// * A formatted error message if parsing fails
+    fn int_primary(&mut self) -> Result<IntExpression, String> {
+        if let Some(element) = self.list_element(&Type::Int)? {
+            return Ok(IntExpression::ListElement(element));
+        }
+        if let Some(value) = self.map_value(&Type::Int)? {
+            return Ok(IntExpression::MapValue(value));
+        }
+        if let Some(access) = self.field_access(&Type::Int)? {
+            return Ok(IntExpression::Field(access));
+        }
+
+        let token = self.peek().clone();
+        let num_found = |parser: &Self, message: String| {
+            format_error(
+                &parser.source_path,
+                &parser.source,
+                ErrorPosition {
+                    line: token.line,
+                    column: token.column,
+                },
+                message,
+                conversion_hint(&Type::Num, &Type::Int),
+            )
+        };
+
+        match &token.token_type {
+            TokenType::IntegerLiteral(value) => {
+                self.advance();
+                Ok(IntExpression::IntegerLiteral(*value))
+            }
+            TokenType::NumberLiteral(value) if value.fract() == 0.0 => Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: token.line,
+                    column: token.column,
+                },
+                "Integer literal is too large for an 'int'".to_string(),
+                format!("An 'int' holds values from {} to {}", i64::MIN, i64::MAX),
+            )),
+            TokenType::NumberLiteral(value) => {
+                Err(num_found(self, format!("Number {} is a 'num', but an 'int' is expected here", value)))
+            }
+            TokenType::Int if matches!(
+                self.tokens.get(self.current + 1).map(|token| &token.token_type),
+                Some(TokenType::OpenParen)
+            ) => {
+                // Explicit conversion of a number: 'int(x)'
+                self.advance();
+                self.advance();
+                let inner = self.num_expression()?;
+                self.consume(TokenType::CloseParen, "Expected ')' after the value to convert")?;
+                Ok(IntExpression::FromNum(Box::new(inner), token.line))
+            }
+            TokenType::Identifier(name) if name == "len" && self.tokens.get(self.current + 1)
+                .is_some_and(|next| next.token_type == TokenType::OpenParen) =>
+            {
+                // Number of characters of a string, elements of a list or keys of a map: 'len(list)'
+                self.advance();
+                self.advance();
+                if self.is_string_operand() {
+                    let text = self.expression()?;
+                    self.consume(TokenType::CloseParen, "Expected ')' after the string")?;
+                    return Ok(IntExpression::StringCall(StringFunction::Length, vec![TypedExpression::Str(text)], token.line));
+                }
+                if self.is_map_operand() {
+                    let (_, map) = self.map_expression(None)?;
+                    self.consume(TokenType::CloseParen, "Expected ')' after the map")?;
+                    return Ok(IntExpression::MapLength(map));
+                }
+                let (_, list) = self.list_expression(None)?;
+                self.consume(TokenType::CloseParen, "Expected ')' after the list")?;
+                Ok(IntExpression::ListLength(list))
+            }
+            TokenType::Identifier(name) => {
+                self.advance();
+                if self.check(&TokenType::OpenParen) {
+                    self.check_builtin_type(name, &Type::Int, &token)?;
+                    if string_function(name).is_some() {
+                        let (function, args) = self.string_call(name, &token)?;
+                        return Ok(IntExpression::StringCall(function, args, token.line));
+                    }
+                    if name == "argc" {
+                        // The number of command-line arguments: 'argc()'
+                        self.advance();
+                        self.consume(TokenType::CloseParen, "Expected ')' after 'argc('")?;
+                        return Ok(IntExpression::ArgCount);
+                    }
+                    // Math built-ins always compute with numbers
+                    self.math_call(name, &token)?;
+                    return Err(num_found(
+                        self,
+                        format!("Function '{}' returns 'num', but an 'int' is expected here", name),
+                    ));
+                }
+                if self.variable_type(name).is_none() && math_constant(name).is_some() {
+                    return Err(num_found(
+                        self,
+                        format!("Constant '{}' is a 'num', but an 'int' is expected here", name),
+                    ));
+                }
+                let var_type = self.resolve_variable(name, &token)?;
+                if var_type != Type::Int {
+                    return Err(self.variable_type_error(name, &var_type, &Type::Int, &token));
+                }
+                Ok(IntExpression::Variable(name.clone()))
+            }
+            TokenType::OpenParen => {
+                self.advance();
+                let expr = self.int_expression()?;
+                self.consume(TokenType::CloseParen, "Expected ')' after expression")?;
+                Ok(IntExpression::Grouping(Box::new(expr)))
+            }
+            TokenType::CallFn => {
+                self.advance();
+                let (name, args, return_type) = self.function_call()?;
+                if return_type != Some(Type::Int) {
+                    return Err(self.call_type_error(&name, return_type.as_ref(), &Type::Int, &token));
+                }
+                Ok(IntExpression::FunctionCall(name, args))
+            }
+            _ => Err(format_error(
+                &self.source_path,
+                &self.source,
+                ErrorPosition {
+                    line: token.line,
+                    column: token.column,
+                },
+                "Expected integer expression".to_string(),
+                "Valid expressions are integers, 'int' variables, function calls, int(...) conversions, len(...), or parenthesized expressions".to_string(),
+            )),
+        }
+    }
+
+
+}
+
+/// Largest width or precision allowed in a format specifier.
+const MAX_FORMAT_NUMBER: usize = 1000;
+
+/// Hint shown for invalid format specifiers.
+const FORMAT_SPEC_HINT: &str = "Format specifiers have the form '[<|>|^][0][width][.precision][f|e|g|x]', e.g. '{x:.2}' or '{name:>10}'";
+
+/// Looks up a built-in math function by name.
+///
+/// # Returns
+///
+/// The function and its number of arguments, or None if the name is not a built-in
+fn math_function(name: &str) -> Option<(MathFunction, usize)> {
+    let function = match name {
+        "sqrt" => (MathFunction::Sqrt, 1),
+        "abs" => (MathFunction::Abs, 1),
+        "floor" => (MathFunction::Floor, 1),
+        "ceil" => (MathFunction::Ceil, 1),
+        "round" => (MathFunction::Round, 1),
+        "sin" => (MathFunction::Sin, 1),
+        "cos" => (MathFunction::Cos, 1),
+        "tan" => (MathFunction::Tan, 1),
+        "log" => (MathFunction::Log, 1),
+        "exp" => (MathFunction::Exp, 1),
+        "min" => (MathFunction::Min, 2),
+        "max" => (MathFunction::Max, 2),
+        _ => return None,
+    };
+    Some(function)
+}
+
+/// Looks up the value of a built-in math constant ('PI' or 'E') by name.
+fn math_constant(name: &str) -> Option<f64> {
+    match name {
+        "PI" => Some(std::f64::consts::PI),
+        "E" => Some(std::f64::consts::E),
+        _ => None,
+    }
+}
+
+/// Collects the declared type of every global variable in the token stream.
+///
+/// Globals are the variables declared at the top level of the program, outside
+/// of any block. The whole program is scanned up front so that a function body
+/// can use a global declared after the function; top-level code can only use
+/// a global after the line that declares it.
+///
+/// # Arguments
+///
+/// * `tokens` - The token stream to scan
+///
+/// # Returns
+///
+/// A map from global variable names to their declared types
+fn collect_global_types(tokens: &[Token]) -> HashMap<String, Type> {
+    let mut globals = HashMap::new();
+    let mut depth = 0usize;
+    for i in 0..tokens.len() {
+        let (var_type, name_index) = match tokens[i].token_type {
+            TokenType::OpenBrace => {
+                depth += 1;
+                continue;
+            }
+            TokenType::CloseBrace => {
+                depth = depth.saturating_sub(1);
+                continue;
+            }
+            TokenType::Let => (Type::Str, i + 1),
+            TokenType::Num => (Type::Num, i + 1),
+            TokenType::Int => (Type::Int, i + 1),
+            TokenType::Bool => (Type::Bool, i + 1),
+            TokenType::List => match type_from_tokens(tokens, i) {
+                Some((list_type, length)) => (list_type, i + length),
+                None => match literal_list_type(tokens, i + 3) {
+                    Some(list_type) => (list_type, i + 1),
+                    None => continue,
+                },
+            },
+            // Struct variables are declared with the name of their struct: 'Point p = ...'
+            TokenType::Identifier(ref struct_name)
+                if matches!(tokens.get(i + 1).map(|token| &token.token_type), Some(TokenType::Identifier(_)))
+                    && tokens.get(i + 2).is_some_and(|token| token.token_type == TokenType::Equals) =>
+            {
+                (Type::Struct(struct_name.clone()), i + 1)
+            }
+            TokenType::Map => match type_from_tokens(tokens, i) {
+                Some((map_type, length)) => (map_type, i + length),
+                None => match literal_map_type(tokens, i + 3) {
+                    Some(map_type) => (map_type, i + 1),
+                    None => continue,
+                },
+            },
+            _ => continue,
+        };
+        if depth == 0
+            && let Some(TokenType::Identifier(name)) = tokens.get(name_index).map(|token| &token.token_type)
+        {
+            globals.insert(name.clone(), var_type);
+        }
+    }
+    globals
+}
+
+/// Infers the type of a list literal made only of literal values, such as
+/// '[1, 2.5]', for globals declared without an element type.
+///
+/// Any other initializer gives None: such globals are only known from their
+/// declaration onwards.
+///
+/// # Arguments
+///
+/// * `tokens` - The token stream to scan
+/// * `start` - The index of the literal's opening '['
+fn literal_list_type(tokens: &[Token], start: usize) -> Option<Type> {
+    if tokens.get(start)?.token_type != TokenType::OpenBracket {
+        return None;
+    }
+
+    let mut element_type: Option<Type> = None;
+    let mut i = start + 1;
+    loop {
+        let literal_type = match tokens.get(i)?.token_type {
+            TokenType::StringLiteral(_) => Type::Str,
+            TokenType::True | TokenType::False => Type::Bool,
+            TokenType::IntegerLiteral(_) => Type::Int,
+            TokenType::NumberLiteral(_) => Type::Num,
+            _ => return None,
+        };
+        // Integers among numbers make a list of numbers, as when parsing the literal
+        element_type = match (element_type, literal_type) {
+            (None, literal_type) => Some(literal_type),
+            (Some(Type::Int), Type::Num) | (Some(Type::Num), Type::Int) => Some(Type::Num),
+            (Some(element_type), literal_type) if element_type == literal_type => Some(element_type),
+            _ => return None,
+        };
+        match tokens.get(i + 1)?.token_type {
+            TokenType::Comma => i += 2,
+            TokenType::CloseBracket => return element_type.map(|element_type| Type::List(Box::new(element_type))),
+            _ => return None,
+        }
+    }
+}
+
+/// Infers the type of a map literal made only of literal keys and values,
+/// such as '{"a": 1, "b": 2.5}', for globals declared without a value type.
+///
+/// Any other initializer gives None: such globals are only known from their
+/// declaration onwards.
+///
+/// # Arguments
+///
+/// * `tokens` - The token stream to scan
+/// * `start` - The index of the literal's opening '{'
+fn literal_map_type(tokens: &[Token], start: usize) -> Option<Type> {
+    if tokens.get(start)?.token_type != TokenType::OpenBrace {
+        return None;
+    }
+
+    let mut value_type: Option<Type> = None;
+    let mut i = start + 1;
+    loop {
+        if !matches!(tokens.get(i)?.token_type, TokenType::StringLiteral(_))
+            || tokens.get(i + 1)?.token_type != TokenType::Colon
+        {
+            return None;
+        }
+        let literal_type = match tokens.get(i + 2)?.token_type {
+            TokenType::StringLiteral(_) => Type::Str,
+            TokenType::True | TokenType::False => Type::Bool,
+            TokenType::IntegerLiteral(_) => Type::Int,
+            TokenType::NumberLiteral(_) => Type::Num,
+            _ => return None,
+        };
+        // Integers among numbers make a map of numbers, as when parsing the literal
+        value_type = match (value_type, literal_type) {
+            (None, literal_type) => Some(literal_type),
+            (Some(Type::Int), Type::Num) | (Some(Type::Num), Type::Int) => Some(Type::Num),
+            (Some(value_type), literal_type) if value_type == literal_type => Some(value_type),
+            _ => return None,
+        };
+        match tokens.get(i + 3)?.token_type {
+            TokenType::Comma => i += 4,
+            TokenType::CloseBrace => return value_type.map(|value_type| Type::Map(Box::new(value_type))),
+            _ => return None,
+        }
+    }
+}
+
+/// Collects the fields of every struct defined in the token stream.
+///
+/// Structs may be used before the line that defines them, for example in the
+/// signature of a function, so definitions are gathered up front. Malformed
+/// definitions are reported when the definition itself is parsed.
+///
+/// # Arguments
+///
+/// * `tokens` - The token stream to scan
+///
+/// # Returns
+///
+/// A map from struct names to their fields, in declaration order
+fn collect_struct_definitions(tokens: &[Token]) -> HashMap<String, Vec<(String, Type)>> {
+    let mut structs = HashMap::new();
+    let token_at = |i: usize| tokens.get(i).map(|token| &token.token_type);
+
+    for i in 0..tokens.len() {
+        let (Some(TokenType::Struct), Some(TokenType::Identifier(name)), Some(TokenType::OpenBrace)) =
+            (token_at(i), token_at(i + 1), token_at(i + 2))
+        else {
+            continue;
+        };
+
+        // Walk the fields: 'name: type' pairs separated by commas or newlines
+        let mut fields = Vec::new();
+        let mut j = i + 3;
+        loop {
+            while matches!(token_at(j), Some(TokenType::Newline | TokenType::Comma)) {
+                j += 1;
+            }
+            let (Some(TokenType::Identifier(field)), Some(TokenType::Colon), Some((field_type, length))) =
+                (token_at(j), token_at(j + 1), type_from_tokens(tokens, j + 2))
+            else {
+                break;
+            };
+            fields.push((field.clone(), field_type));
+            j += 2 + length;
+        }
+        structs.insert(name.clone(), fields);
+    }
+    structs
+}
+
+/// Collects the variants of every enum defined in the token stream, with the
+/// types of the values each variant carries.
+///
+/// Enums may be used before the line that defines them, for example in the
+/// signature of a function, so definitions are gathered up front. Malformed
+/// definitions are reported when the definition itself is parsed.
+///
+/// # Arguments
+///
+/// * `tokens` - The token stream to scan
+///
+/// # Returns
+///
+/// A map from enum names to their variants, in declaration order
+fn collect_enum_definitions(tokens: &[Token]) -> HashMap<String, Vec<(String, Vec<Type>)>> {
+    let mut enums = HashMap::new();
+    let token_at = |i: usize| tokens.get(i).map(|token| &token.token_type);
+
+    for i in 0..tokens.len() {
+        let (Some(TokenType::Enum), Some(TokenType::Identifier(name)), Some(TokenType::OpenBrace)) =
+            (token_at(i), token_at(i + 1), token_at(i + 2))
+        else {
+            continue;
+        };
+
+        // Walk the variants: names, optionally followed by '(type, ...)',
+        // separated by commas or newlines
+        let mut variants = Vec::new();
+        let mut j = i + 3;
+        loop {
+            while matches!(token_at(j), Some(TokenType::Newline | TokenType::Comma)) {
+                j += 1;
+            }
+            let Some(TokenType::Identifier(variant)) = token_at(j) else {
+                break;
+            };
+            let mut value_types = Vec::new();
+            j += 1;
+            if token_at(j) == Some(&TokenType::OpenParen) {
+                j += 1;
+                while let Some((value_type, length)) = type_from_tokens(tokens, j) {
+                    value_types.push(value_type);
+                    j += length;
+                    if token_at(j) == Some(&TokenType::Comma) {
+                        j += 1;
+                    }
+                }
+                j += 1; // The closing ')'
+            }
+            variants.push((variant.clone(), value_types));
+        }
+        enums.insert(name.clone(), variants);
+    }
+    enums
+}
+
+/// Collects the signature of every function defined in the token stream.
+///
+/// Functions may be called before the line that defines them, so signatures
+/// are gathered up front. Malformed definitions are skipped here and reported
+/// when the definition itself is parsed.
+///
+/// # Arguments
+///
+/// * `tokens` - The token stream to scan
+///
+/// # Returns
+///
+/// A map from function names to their signatures
+fn collect_function_signatures(tokens: &[Token]) -> HashMap<String, FunctionSignature> {
+    let mut functions = HashMap::new();
+    let token_at = |i: usize| tokens.get(i).map(|token| &token.token_type);
+
+    for i in 0..tokens.len() {
+        if token_at(i) != Some(&TokenType::NewFn) {
+            continue;
+        }
+
+        // C code functions take no parameters and return nothing
+        if let (Some(TokenType::DollarC), Some(TokenType::Identifier(name))) = (token_at(i + 1), token_at(i + 2)) {
+            functions.insert(name.clone(), FunctionSignature { parameters: Vec::new(), return_type: None });
+            continue;
+        }
+
+        let name = match (token_at(i + 1), token_at(i + 2), token_at(i + 3)) {
+            (Some(TokenType::Identifier(keyword)), Some(TokenType::Identifier(name)), Some(TokenType::OpenParen))
+                if keyword == "fn" => name.clone(),
+            _ => continue,
+        };
+
+        // Walk the parameter list: 'name: type' pairs separated by commas
+        let mut parameters = Vec::new();
+        let mut j = i + 4;
+        while let (Some(TokenType::Identifier(param)), Some(TokenType::Colon), Some((param_type, length))) =
+            (token_at(j), token_at(j + 1), type_from_tokens(tokens, j + 2))
+        {
+            parameters.push((param.clone(), param_type));
+            j += 2 + length;
+            if token_at(j) == Some(&TokenType::Comma) {
+                j += 1;
+            }
+        }
+        if token_at(j) != Some(&TokenType::CloseParen) {
+            continue;
+        }
+
+        let return_type = if token_at(j + 1) == Some(&TokenType::Arrow) {
+            type_from_tokens(tokens, j + 2).map(|(return_type, _)| return_type)
+        } else {
+            None
+        };
+
+        functions.insert(name, FunctionSignature { parameters, return_type });
+    }
+    functions
+}
+
+/// Reads a type name starting at the given token: 'num', 'int', 'str', 'bool',
+/// 'list<type>' or 'map<type>' with one of the former as the element or value
+/// type, or any other name, which is taken to be a struct. The parser turns the
+/// names of enums into enum types.
+///
+/// # Returns
+///
+/// The Type it denotes and the number of tokens it spans, or None if the tokens
+/// don't form a type name
+fn type_from_tokens(tokens: &[Token], start: usize) -> Option<(Type, usize)> {
+    match &tokens.get(start)?.token_type {
+        TokenType::Num => Some((Type::Num, 1)),
+        TokenType::Int => Some((Type::Int, 1)),
+        TokenType::Bool => Some((Type::Bool, 1)),
+        TokenType::Identifier(name) if name == "str" => Some((Type::Str, 1)),
+        TokenType::Identifier(name) => Some((Type::Struct(name.clone()), 1)),
+        TokenType::List if tokens.get(start + 1)?.token_type == TokenType::Less => {
+            let (element_type, length) = type_from_tokens(tokens, start + 2)?;
+            if !is_element_type(&element_type) || tokens.get(start + 2 + length)?.token_type != TokenType::Greater {
+                return None;
+            }
+            Some((Type::List(Box::new(element_type)), length + 3))
+        }
+        TokenType::Map if tokens.get(start + 1)?.token_type == TokenType::Less => {
+            let (value_type, length) = type_from_tokens(tokens, start + 2)?;
+            if !is_element_type(&value_type) || tokens.get(start + 2 + length)?.token_type != TokenType::Greater {
+                return None;
+            }
+            Some((Type::Map(Box::new(value_type)), length + 3))
+        }
+        _ => None,
+    }
+}
+
+/// Checks whether an 'int' expression is built only from integer literals,
+/// like the bounds of 'for i in 0..10'.
+fn is_int_literal(expr: &IntExpression) -> bool {
+    match expr {
+        IntExpression::IntegerLiteral(_) => true,
+        IntExpression::Negate(operand, _) | IntExpression::Grouping(operand) => is_int_literal(operand),
+        IntExpression::BinaryOp(left, _, right, _) => is_int_literal(left) && is_int_literal(right),
+        _ => false,
+    }
+}
+
+/// Checks whether a built-in function is run for its effect and returns no value.
+fn is_void_builtin(name: &str) -> bool {
+    matches!(name, "write_file" | "append_file" | "delete_file")
+}
+
+/// Checks whether values of a type can be stored in lists and maps.
+fn is_element_type(var_type: &Type) -> bool {
+    matches!(var_type, Type::Str | Type::Num | Type::Int | Type::Bool)
+}
+
+/// Returns a variant pattern that ignores every value the variant carries, e.g. "Rect(_, _)".
+fn variant_pattern(variant: &str, value_count: usize) -> String {
+    if value_count == 0 {
+        variant.to_string()
+    } else {
+        format!("{}({})", variant, vec!["_"; value_count].join(", "))
+    }
+}
+
+/// Returns a field access as written in Vortlang source code, e.g. "line.start.x".
+fn field_name(access: &FieldAccess) -> String {
+    format!("{}.{}", access.variable, access.fields.join("."))
+}
+
+/// Returns the type of the value returned by a built-in function for input,
+/// conversions, strings, files or the program's environment, or None for
+/// other names.
+fn builtin_return_type(name: &str) -> Option<Type> {
+    match name {
+        "input" | "tostr" | "read_file" | "arg" | "env" => Some(Type::Str),
+        "argc" => Some(Type::Int),
+        "inputnum" | "tonum" => Some(Type::Num),
+        "isnum" | "file_exists" => Some(Type::Bool),
+        _ => string_function(name).map(|(_, _, return_type)| return_type),
+    }
+}
+
+/// Looks up a built-in string function by name. 'len' is not included, since
+/// it also measures lists and maps.
+///
+/// # Returns
+///
+/// The function, the types of its parameters and its return type, or None if
+/// the name is not a string built-in
+fn string_function(name: &str) -> Option<(StringFunction, Vec<Type>, Type)> {
+    let function = match name {
+        "upper" => (StringFunction::Upper, vec![Type::Str], Type::Str),
+        "lower" => (StringFunction::Lower, vec![Type::Str], Type::Str),
+        "trim" => (StringFunction::Trim, vec![Type::Str], Type::Str),
+        "contains" => (StringFunction::Contains, vec![Type::Str, Type::Str], Type::Bool),
+        "starts_with" => (StringFunction::StartsWith, vec![Type::Str, Type::Str], Type::Bool),
+        "ends_with" => (StringFunction::EndsWith, vec![Type::Str, Type::Str], Type::Bool),
+        "index_of" => (StringFunction::IndexOf, vec![Type::Str, Type::Str], Type::Int),
+        "substr" => (StringFunction::Substr, vec![Type::Str, Type::Int, Type::Int], Type::Str),
+        "replace" => (StringFunction::Replace, vec![Type::Str, Type::Str, Type::Str], Type::Str),
+        "split" => (StringFunction::Split, vec![Type::Str, Type::Str], Type::List(Box::new(Type::Str))),
+        "join" => (StringFunction::Join, vec![Type::List(Box::new(Type::Str)), Type::Str], Type::Str),
+        _ => return None,
+    };
+    Some(function)
+}
+
+/// Returns the built-in that explicitly converts between two types, if there is one.
+fn conversion_function(from: &Type, to: &Type) -> Option<&'static str> {
+    match (from, to) {
+        (Type::Num, Type::Int) => Some("int"),
+        (Type::Int, Type::Num) => Some("num"),
+        (from, Type::Str) if *from != Type::Str => Some("tostr"),
+        (Type::Str, Type::Num) => Some("tonum"),
+        _ => None,
+    }
+}
+
+/// Builds the hint for a value of one type used where another type is expected.
+fn conversion_hint(found: &Type, expected: &Type) -> String {
+    match conversion_function(found, expected) {
+        Some(function) => format!("Convert the value explicitly with {}(...)", function),
+        None => "Use a variable of the expected type".to_string(),
+    }
+}
+
+/// Returns the quoted name of a type with its indefinite article, e.g. "an 'int'".
+fn a_type_name(var_type: &Type) -> String {
+    let article = if *var_type == Type::Int { "an" } else { "a" };
+    format!("{} '{}'", article, type_name(var_type))
+}
+
+/// Returns the name of a type as written in Vortlang source code.
+fn type_name(var_type: &Type) -> String {
+    match var_type {
+        Type::Str => "str".to_string(),
+        Type::Num => "num".to_string(),
+        Type::Int => "int".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::List(element_type) => format!("list<{}>", type_name(element_type)),
+        Type::Map(value_type) => format!("map<{}>", type_name(value_type)),
+        Type::Struct(name) | Type::Enum(name) => name.clone(),
+    }
+}
+
+/// Convenience function to parse a token stream into an AST.
+///
+/// # Arguments
+///
+/// * `tokens` - The token stream to parse
+/// * `source` - The original source code (for error reporting)
+/// * `source_path` - The path to the source file (for error reporting)
+///
+/// # Returns
+///
 /// A Result containing either:
 /// * A vector of Statement objects representing the program
 /// * A formatted error message if parsing fails
-pub fn parse(tokens: Vec<Token>) -> Result<Vec<Statement>, String> {
-    // In a real implementation, we would pass the actual source code and path
-    let source = String::new(); 
-    let source_path = String::new();
-    let mut parser = Parser::new(tokens, source, source_path);
+pub fn parse(tokens: Vec<Token>, source: &str, source_path: &str) -> Result<Vec<Statement>, String> {
+    let mut parser = Parser::new(tokens, source.to_string(), source_path.to_string());
     parser.parse()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::tokenize;
+
+    /// Parses a program, failing the test with the diagnostic if it is rejected.
+    fn parse_source(source: &str) -> Vec<Statement> {
+        let tokens = tokenize(source, "test.vl").unwrap_or_else(|error| panic!("{}", error));
+        parse(tokens, source, "test.vl").unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Parses a program that must be rejected, returning the diagnostic.
+    fn parse_error(source: &str) -> String {
+        let tokens = tokenize(source, "test.vl").unwrap_or_else(|error| panic!("{}", error));
+        match parse(tokens, source, "test.vl") {
+            Ok(_) => panic!("expected the program to be rejected"),
+            Err(error) => error,
+        }
+    }
+
+    #[test]
+    fn format_expression_with_string_literals() {
+        let statements = parse_source(
+            "let first = \"Ada\"\nlet last = \"Lovelace\"\nprint(o\"{first + \" \" + last}\")\n",
+        );
+        let Some(Statement::PrintFormat(parts)) = statements.last() else {
+            panic!("expected the format string to be printed");
+        };
+        assert!(matches!(
+            parts.as_slice(),
+            [FormatPart::Expression(TypedExpression::Str(Expression::Concat(operands)), _)] if operands.len() == 3
+        ));
+    }
+
+    #[test]
+    fn format_expression_with_map_key_and_call() {
+        parse_source("map ages = {\"alice\": 30}\nprint(o\"{ages[\"alice\"]}\")\n");
+        parse_source("let t = \"Hello World\"\nprint(o\"{index_of(t, \"W\")}\")\n");
+    }
+
+    #[test]
+    fn or_before_string_literal() {
+        let statements = parse_source("let s = \"a\"\nbool b = s == \"a\" or\"b\" == s\n");
+        assert!(matches!(statements.last(), Some(Statement::BoolDeclaration(_, Condition::Or(..), _))));
+        parse_source("let name = \"x\"\nprint(ro\"C:\\{name}\")\n");
+    }
+
+    #[test]
+    fn print_and_compare_booleans() {
+        let statements = parse_source("bool a = true\nprint(a)\nprint(true)\n");
+        assert!(matches!(statements[1], Statement::Print(Expression::Variable(_))));
+        assert!(matches!(
+            &statements[2],
+            Statement::PrintFormat(parts) if matches!(parts.as_slice(), [FormatPart::Expression(TypedExpression::Bool(_), _)])
+        ));
+
+        let statements = parse_source("bool a = true\nbool b = false\nif a != b {\n    print(\"differ\")\n}\n");
+        assert!(matches!(
+            &statements[2],
+            Statement::If(branches, None) if matches!(branches[0].0, Condition::BoolComparison(_, ComparisonOperator::NotEqual, _))
+        ));
+        assert!(parse_error("bool a = true\nbool b = a < true\n").contains("Booleans can only be compared with '==' and '!='"));
+    }
+
+    #[test]
+    fn print_string_comparisons() {
+        let statements = parse_source("let s = \"a\"\nprint(s == \"a\")\nprint(\"abc\" < \"abd\")\nprint(o\"{s == \"a\"}\")\n");
+        assert!(statements[1..].iter().all(|statement| matches!(
+            statement,
+            Statement::PrintFormat(parts) if matches!(parts.as_slice(), [FormatPart::Expression(TypedExpression::Bool(_), _)])
+        )));
+    }
+
+    #[test]
+    fn grouped_condition_on_either_side_of_bool_comparison() {
+        for source in ["bool a = true\nbool c = a == (1 < 2)\n", "bool a = true\nbool c = (1 < 2) == a\n"] {
+            let statements = parse_source(source);
+            assert!(matches!(statements[1], Statement::BoolDeclaration(_, Condition::BoolComparison(..), _)));
+        }
+        parse_source("num x = 2\nbool f = (x + 1) > 2\n");
+    }
+
+    #[test]
+    fn duplicate_function_definition() {
+        let error = parse_error("newfn fn f() {\n    print(\"a\")\n}\nnewfn fn f() {\n    print(\"b\")\n}\n");
+        assert!(error.contains("test.vl:4:10") && error.contains("Function 'f' is already defined"));
+    }
+
+    #[test]
+    fn smallest_int_literal() {
+        let statements = parse_source("int k = -9223372036854775808\n");
+        assert!(matches!(statements[0], Statement::IntDeclaration(_, IntExpression::IntegerLiteral(i64::MIN), _)));
+        assert!(parse_error("int k = 9223372036854775808\n").contains("Integer literal is too large for an 'int'"));
+        assert!(parse_error("int k = -(9223372036854775808)\n").contains("Integer literal is too large for an 'int'"));
+    }
+
+    #[test]
+    fn break_inside_match_inside_loop() {
+        let statements = parse_source("int n = 0\nwhile n < 10 {\n    n = n + 1\n    match n {\n        7 => break\n        _ => continue\n    }\n}\n");
+        let Statement::While(_, body) = &statements[1] else {
+            panic!("expected a while loop");
+        };
+        let Some(Statement::Match(_, arms, _, _)) = body.last() else {
+            panic!("expected the loop to end with a match");
+        };
+        assert!(matches!(arms[0].body.as_slice(), [Statement::Break]));
+        assert!(parse_error("int n = 1\nmatch n {\n    1 => break\n    _ => print(\"x\")\n}\n").contains("'break' outside of a loop"));
+    }
+
+    #[test]
+    fn list_literal_element_types() {
+        let statements = parse_source("list a = [1, 2]\nlist b = [1, 2.5]\nlist c = [\"x\"]\nlist<int> d = []\n");
+        let types: Vec<&Type> = statements
+            .iter()
+            .map(|statement| match statement {
+                Statement::ListDeclaration(_, element_type, _, _) => element_type,
+                _ => panic!("expected a list declaration"),
+            })
+            .collect();
+        assert!(matches!(types.as_slice(), [Type::Int, Type::Num, Type::Str, Type::Int]));
+        assert!(parse_error("list e = []\n").contains("Cannot infer the element type of an empty list"));
+        assert!(parse_error("list l = [1, \"a\"]\n").contains("Type mismatch for list element: expected 'int', found 'str'"));
+    }
+
+    #[test]
+    fn map_literal_value_types() {
+        let statements = parse_source("map a = {\"k\": 1}\nmap b = {\"k\": 1.5}\nmap c = {\"k\": \"v\"}\nmap<str> d = {}\n");
+        let types: Vec<&Type> = statements
+            .iter()
+            .map(|statement| match statement {
+                Statement::MapDeclaration(_, value_type, _, _) => value_type,
+                _ => panic!("expected a map declaration"),
+            })
+            .collect();
+        assert!(matches!(types.as_slice(), [Type::Int, Type::Num, Type::Str, Type::Str]));
+        assert!(parse_error("map e = {}\n").contains("Cannot infer the value type of an empty map"));
+        assert!(parse_error("map m = {\"a\": 1, \"b\": \"x\"}\n").contains("Type mismatch for map value: expected 'int', found 'str'"));
+    }
+
+    #[test]
+    fn format_spec_width_and_precision_limits() {
+        parse_source("num x = 1.5\nprint(o\"{x:>1000.1000}\")\n");
+        assert!(parse_error("num x = 1.5\nprint(o\"{x:99999999999999999999999}\")\n").contains("Width can be at most 1000"));
+        assert!(parse_error("num x = 1.5\nprint(o\"{x:.1001}\")\n").contains("Precision can be at most 1000"));
+    }
+
+    #[test]
+    fn print_builtin_calls_and_expressions() {
+        let statements = parse_source("let s = \"hello\"\nprint(len(s))\nint a = 2\nprint(a * 3)\nprint(PI)\n");
+        assert!(statements[1..]
+            .iter()
+            .filter(|statement| !matches!(statement, Statement::IntDeclaration(..)))
+            .all(|statement| matches!(statement, Statement::PrintFormat(..))));
+        assert!(parse_error("print(missing)\n").contains("Undefined variable 'missing'"));
+    }
+
+    #[test]
+    fn void_builtin_used_as_value() {
+        assert!(parse_error("bool b = write_file(\"a\", \"b\")\n").contains("but a 'bool' is expected here"));
+        assert!(parse_error("print(write_file(\"a\", \"b\"))\n").contains("but a value is expected here"));
+    }
+
+    #[test]
+    fn int_constant_used_as_num() {
+        parse_source("const N = 10\nnum x = N * 2.5\nnum q = 5 / N\n");
+        assert!(parse_error("int n = 10\nnum x = n * 2.5\n").contains("Variable 'n' is an 'int'"));
+    }
+
+    #[test]
+    fn globals_visible_from_their_declaration() {
+        assert!(parse_error("print(s)\nlet s = \"a\"\n").contains("Undefined variable 's'"));
+        assert!(parse_error("print(o\"{x}\")\nnum x = 1\n").contains("Undefined variable 'x'"));
+        // Function bodies can use globals declared further down
+        parse_source("newfn fn show() {\n    print(g)\n}\nlet g = \"ok\"\ncallfn show()\n");
+    }
+
+    #[test]
+    fn global_conflict_reported_at_later_declaration() {
+        let error = parse_error("num x = 1\nlet x = \"a\"\n");
+        assert!(error.contains("test.vl:2:5") && error.contains("Global variable 'x' is already declared as 'num'"));
+        parse_source("num x = 1\nnum x = 2\n");
+    }
+
+    #[test]
+    fn locals_are_scoped_to_their_block() {
+        let source = "if true {\n    num z = 1\n    print(z)\n}\nprint(z)\n";
+        assert!(parse_error(source).contains("Variable 'z' is not in scope here"));
+        let source = "if true {\n    num z = 1\n    let z = \"a\"\n}\n";
+        assert!(parse_error(source).contains("Variable 'z' is already declared in this scope as 'num'"));
+    }
+
+    #[test]
+    fn constants_declared_after_a_function_that_uses_them() {
+        parse_source("newfn fn f() {\n    print(A)\n    num half = A * 0.5\n}\nconst A = 1\ncallfn f()\n");
+        assert!(parse_error("newfn fn f() {\n    A = 2\n}\nconst A = 1\n").contains("Cannot assign to constant 'A'"));
+        // Like a global, a constant can only be used at the top level after its declaration
+        assert!(parse_error("print(A)\nconst A = 1\n").contains("Undefined variable 'A'"));
+    }
+
+    #[test]
+    fn constant_conflict_reported_at_later_declaration() {
+        assert!(parse_error("const A = 1\nnum A = 3\n").contains("test.vl:2:5"));
+        assert!(parse_error("num A = 3\nconst A = 1\n").contains("test.vl:2:7"));
+    }
+
+    #[test]
+    fn for_loop_over_int_range() {
+        let statements = parse_source("list<int> l = [3, 4]\nfor i in 0..len(l) {\n    int v = l[i]\n}\n");
+        assert!(matches!(statements.last(), Some(Statement::IntFor(..))));
+        let statements = parse_source("for i in 0..10 {\n    num half = i / 2\n}\n");
+        assert!(matches!(statements.last(), Some(Statement::For(..))));
+    }
+}